@@ -51,6 +51,11 @@ static int32_t groveLogFn(const GroveValue* args, uint32_t argc, GroveValue* /*r
             case GROVE_OBJECT:
                 accum->append("<object:" + std::to_string(args[i].data.object_handle) + ">");
                 break;
+            case GROVE_COLOR: {
+                auto& c = args[i].data.color_val;
+                accum->append("color(" + std::to_string(c.r) + ", " + std::to_string(c.g) + ", " + std::to_string(c.b) + ", " + std::to_string(c.a) + ")");
+                break;
+            }
         }
     }
     accum->append("\n");
@@ -13,9 +13,16 @@
 extern "C" {
 #endif
 
-/* Opaque VM handle */
+/*
+ * Opaque VM handle. A VM is single-threaded: use it from one thread at a time
+ * and never re-enter it from its own callbacks. Use GroveVmShared to share a
+ * VM between threads.
+ */
 typedef struct GroveVm GroveVm;
 
+/* Opaque handle to a VM behind a lock, see grove_shared_new(). */
+typedef struct GroveVmShared GroveVmShared;
+
 /* Value tag — matches Rust GroveValueTag repr(C) */
 typedef enum GroveValueTag {
     GROVE_NIL    = 0,
@@ -23,7 +30,9 @@ typedef enum GroveValueTag {
     GROVE_NUMBER = 2,
     GROVE_STRING = 3,
     GROVE_VEC3   = 4,
-    GROVE_OBJECT = 5
+    GROVE_OBJECT = 5,
+    GROVE_BYTES  = 6, /* raw bytes in string_val, not necessarily UTF-8 */
+    GROVE_COLOR  = 7
 } GroveValueTag;
 
 /* String value (pointer + length, NOT null-terminated) */
@@ -39,6 +48,14 @@ typedef struct GroveVec3Val {
     double z;
 } GroveVec3Val;
 
+/* Color value, RGBA components */
+typedef struct GroveColorVal {
+    double r;
+    double g;
+    double b;
+    double a;
+} GroveColorVal;
+
 /* Value data union — matches Rust GroveValueData repr(C) */
 typedef union GroveValueData {
     int32_t        bool_val;
@@ -46,6 +63,7 @@ typedef union GroveValueData {
     GroveStringVal string_val;
     GroveVec3Val   vec3_val;
     uint64_t       object_handle;
+    GroveColorVal  color_val;
 } GroveValueData;
 
 /* Tagged value — matches Rust GroveValue repr(C) */
@@ -69,6 +87,56 @@ typedef int32_t (*GroveHostFn)(
     void*             userdata
 );
 
+/*
+ * Object namer callback signature. Returns a null-terminated display name for
+ * handle (copied before the next call), or NULL to print it as "object".
+ */
+typedef const char* (*GroveObjectNamer)(uint64_t handle, void* userdata);
+
+/* Trace callback signature: the line and column of the next statement. */
+typedef void (*GroveTraceFn)(uint32_t line, uint32_t column, void* userdata);
+
+/*
+ * Callback run by grove_shared_with() while holding the shared VM's lock.
+ * vm must not be used after it returns.
+ */
+typedef int32_t (*GroveSharedFn)(GroveVm* vm, void* userdata);
+
+/* Levels passed to a GroveLogFn. */
+#define GROVE_LOG_INFO  0
+#define GROVE_LOG_WARN  1
+#define GROVE_LOG_ERROR 2
+
+/*
+ * Log callback signature: a GROVE_LOG_* level and the message, which is
+ * null-terminated and only valid during the call.
+ */
+typedef void (*GroveLogFn)(int32_t level, const char* message, void* userdata);
+
+/* Kinds passed to a GroveLimitFn. */
+#define GROVE_LIMIT_INSTRUCTIONS 0
+#define GROVE_LIMIT_LOOP         1
+
+/* Limit callback signature: a GROVE_LIMIT_* kind and where the script stopped. */
+typedef void (*GroveLimitFn)(int32_t kind, uint32_t line, uint32_t column, void* userdata);
+
+/*
+ * Import resolver signature: the null-terminated source of module name, or
+ * NULL if there is no such module. The source is copied before the next call.
+ */
+typedef const char* (*GroveImportResolver)(const char* name, void* userdata);
+
+/* ── Version ───────────────────────────────────────── */
+
+/* Version of this build, e.g. "0.1.0". Static; do not free. */
+const char* grove_version(void);
+
+/*
+ * Returns 1 if this build supports the named language feature, such as
+ * "keyword_args", "spread_args" or "seeded_random", 0 otherwise.
+ */
+int32_t grove_has_feature(const char* name);
+
 /* ── Lifecycle ─────────────────────────────────────── */
 
 /* Create a new Grove VM. Returns NULL on allocation failure. */
@@ -77,6 +145,31 @@ GroveVm* grove_new(void);
 /* Destroy a Grove VM. Safe to call with NULL. */
 void grove_destroy(GroveVm* vm);
 
+/*
+ * Clear globals, blueprints and the last error so the VM can be reused.
+ * Registered host functions are kept when keep_host_fns is non-zero.
+ */
+void grove_reset(GroveVm* vm, int32_t keep_host_fns);
+
+/* ── Multi-threaded hosts ──────────────────────────── */
+
+/* Create a VM that can be shared between threads. NULL on failure. */
+GroveVmShared* grove_shared_new(void);
+
+/* Destroy a shared VM once no thread uses it. Safe to call with NULL. */
+void grove_shared_destroy(GroveVmShared* shared);
+
+/* grove_eval() on a shared VM, waiting for other threads using it. */
+int32_t grove_shared_eval(GroveVmShared* shared, const char* source);
+
+/*
+ * Call callback(vm, userdata) with exclusive access to the shared VM and
+ * return its result (-1 if shared is NULL). Use this for any other VM call,
+ * and to read results like grove_last_error() before another thread can
+ * replace them.
+ */
+int32_t grove_shared_with(GroveVmShared* shared, GroveSharedFn callback, void* userdata);
+
 /* ── Evaluation ────────────────────────────────────── */
 
 /*
@@ -86,11 +179,21 @@ void grove_destroy(GroveVm* vm);
  */
 int32_t grove_eval(GroveVm* vm, const char* source);
 
+/*
+ * Like grove_eval(), also handing back the value of a top-level
+ * `return expr` (nil without one), as a module script returns its exports.
+ * result receives the value, with arrays and tables as nil; json, if
+ * non-NULL, receives it as JSON, e.g. {"version":1}. Strings are in temp
+ * storage (see grove_make_string()).
+ */
+int32_t grove_eval_value(GroveVm* vm, const char* source, GroveValue* result, const char** json);
+
 /* ── Host functions ────────────────────────────────── */
 
 /*
  * Register a host function callable from Grove scripts.
- * Returns 0 on success, -1 on error.
+ * Returns 0 on success, -1 on error. Names that are Grove keywords (such as
+ * "fn" or "if") or not valid identifiers are rejected; see grove_last_error().
  */
 int32_t grove_register_fn(
     GroveVm*    vm,
@@ -99,12 +202,232 @@ int32_t grove_register_fn(
     void*       userdata
 );
 
+/* Destructor for host function userdata. */
+typedef void (*GroveDestroyFn)(void* userdata);
+
+/*
+ * Like grove_register_fn(), but the VM takes ownership of userdata once
+ * registration succeeds: destroy(userdata) is called when the function is
+ * replaced by registering name again, dropped by grove_reset(), or destroyed
+ * with the VM. destroy may be NULL. On failure userdata stays with the caller.
+ */
+int32_t grove_register_fn_with_destructor(
+    GroveVm*       vm,
+    const char*    name,
+    GroveHostFn    callback,
+    void*          userdata,
+    GroveDestroyFn destroy
+);
+
+/*
+ * Create an empty global table to group host functions, e.g. "game".
+ * Returns 0 on success, -1 on error.
+ */
+int32_t grove_register_namespace(GroveVm* vm, const char* name);
+
+/*
+ * Register a host function callable as ns.fn_name(...). The namespace must
+ * already exist (see grove_register_namespace). Returns 0 on success, -1 on
+ * error.
+ */
+int32_t grove_register_namespaced_fn(
+    GroveVm*    vm,
+    const char* ns,
+    const char* fn_name,
+    GroveHostFn callback,
+    void*       userdata
+);
+
+/*
+ * Register a host function as a method on object handles. A script call
+ * obj:method_name(a, b) invokes callback with args (obj, a, b).
+ * Returns 0 on success, -1 on error.
+ */
+int32_t grove_register_object_method(
+    GroveVm*    vm,
+    const char* method_name,
+    GroveHostFn callback,
+    void*       userdata
+);
+
+/*
+ * Route field access on object handles to the host. obj.field calls getter
+ * with args (obj, "field") and uses its result; obj.field = v calls setter
+ * with args (obj, "field", v). Either callback may be NULL to disallow that
+ * access. Returns 0 on success, -1 on error.
+ */
+int32_t grove_register_object_accessor(
+    GroveVm*    vm,
+    GroveHostFn getter,
+    GroveHostFn setter,
+    void*       userdata
+);
+
+/*
+ * Show objects as <Name:handle> when scripts convert them to strings with
+ * tostring() or "..", using the name returned by callback.
+ * Returns 0 on success, -1 on error.
+ */
+int32_t grove_register_object_namer(
+    GroveVm*         vm,
+    GroveObjectNamer callback,
+    void*            userdata
+);
+
+/* ── Calling scripts ───────────────────────────────── */
+
+/*
+ * Call a blueprint defined by a previously evaluated script.
+ * Writes the return value to result (may be NULL) and returns 0, or -1 on
+ * error. A string result is in temp storage (see grove_make_string()).
+ */
+int32_t grove_call_fn(
+    GroveVm*          vm,
+    const char*       name,
+    const GroveValue* args,
+    uint32_t          arg_count,
+    GroveValue*       result
+);
+
+/*
+ * Serialize the blueprint called name so it can be loaded into other VMs
+ * with grove_load_blueprint_blob() without shipping its source. Writes the
+ * size to len and returns the bytes, valid until the next call; NULL if no
+ * such blueprint is defined.
+ */
+const uint8_t* grove_save_blueprint_blob(GroveVm* vm, const char* name, uint32_t* len);
+
+/*
+ * Define a blueprint from a blob made by grove_save_blueprint_blob().
+ * Returns 0 on success, -1 if the blob is invalid (see grove_last_error()).
+ */
+int32_t grove_load_blueprint_blob(GroveVm* vm, const uint8_t* data, uint32_t len);
+
+/* Value constructors for building grove_call_fn() arguments. */
+GroveValue grove_make_nil(void);
+GroveValue grove_make_bool(int32_t value);
+GroveValue grove_make_number(double value);
+GroveValue grove_make_vec3(double x, double y, double z);
+GroveValue grove_make_color(double r, double g, double b, double a);
+
+/*
+ * Copy a null-terminated string into VM temp storage. Temp storage holds
+ * every string and bytes pointer the VM hands out, and is cleared at the
+ * start of grove_eval(), grove_eval_value() and grove_command_get(), after
+ * grove_call_fn() and grove_set_globals() have read their arguments, and by
+ * grove_reset(). A pointer into it is valid only until then; copy anything
+ * you need to keep.
+ */
+GroveValue grove_make_string(GroveVm* vm, const char* value);
+
+/*
+ * Copy len raw bytes into VM temp storage, like grove_make_string() but kept
+ * exactly as given: invalid UTF-8 and NULs are preserved.
+ */
+GroveValue grove_make_bytes(GroveVm* vm, const uint8_t* data, uint32_t len);
+
+/* ── Debugging ─────────────────────────────────────── */
+
+/*
+ * Call callback before every statement executes, e.g. to drive a step
+ * debugger. The callback only observes. Pass NULL to remove it.
+ */
+void grove_set_trace_callback(GroveVm* vm, GroveTraceFn callback, void* userdata);
+
+/*
+ * Receive messages from the script's info(), warn() and error_log() with
+ * their level. Without a callback they are appended to the output buffer as
+ * "[warn] message". Pass NULL to remove it.
+ */
+void grove_set_log_callback(GroveVm* vm, GroveLogFn callback, void* userdata);
+
+/*
+ * Call callback when a script is stopped by the instruction limit or the loop
+ * iteration cap, before grove_eval() returns. Pass NULL to remove it.
+ */
+void grove_set_limit_callback(GroveVm* vm, GroveLimitFn callback, void* userdata);
+
+/*
+ * Let scripts import("name") modules whose source callback supplies. Each
+ * module runs once, in globals of its own, and the value it returns with a
+ * top-level return is cached and shared by every import. Importing a module
+ * that is still being imported is an error. Pass NULL to remove it.
+ */
+void grove_set_import_resolver(GroveVm* vm, GroveImportResolver callback, void* userdata);
+
+/* Capture the variables in scope when grove_eval() fails at runtime (0 = off). */
+void grove_set_capture_error_locals(GroveVm* vm, int32_t enabled);
+
+/*
+ * Strict mode (0 = off, the default): '..' raises a type error unless both
+ * operands are strings or numbers, instead of stringifying nil or tables,
+ * reading an array out of bounds is an error instead of nil, so is calling a
+ * blueprint with the wrong number of arguments, and integer literals too
+ * large to represent exactly are syntax errors instead of warnings sent to
+ * the log callback.
+ */
+void grove_set_strict(GroveVm* vm, int32_t enabled);
+
+/*
+ * Accept shell-style "# comment" lines (0 = off, the default): a '#' that
+ * starts a line where a statement could begin comments out the rest of the
+ * line. Elsewhere '#' is still the length operator.
+ */
+void grove_set_hash_comments(GroveVm* vm, int32_t enabled);
+
+/*
+ * Let scripts run source strings with eval() and compile() (0 = off, the
+ * default). The evaluated code shares globals, blueprints and the
+ * instruction limit with the calling script; only enable this for trusted
+ * scripts.
+ */
+void grove_set_allow_eval(GroveVm* vm, int32_t enabled);
+
+/*
+ * JSON array of the scopes at the failing statement of the last grove_eval(),
+ * global scope first, e.g. [{"total":"11"},{"i":"3"}]. Values are rendered as
+ * strings. Returns NULL if nothing was captured; valid until the next eval.
+ */
+const char* grove_error_locals_json(const GroveVm* vm);
+
+/* ── Commands ──────────────────────────────────────── */
+
+/*
+ * Scripts queue commands with emit(kind, data) for the host to apply after
+ * the script has run. Commands accumulate across evals until cleared.
+ */
+uint32_t grove_command_count(const GroveVm* vm);
+
+/*
+ * Kind of command index (in emit order), or NULL if out of range. When
+ * data_json is non-NULL it receives the command's data as JSON, e.g.
+ * {"block":"stone","x":1}. Both strings are in temp storage (see
+ * grove_make_string()), so the next grove_command_get() invalidates them.
+ */
+const char* grove_command_get(GroveVm* vm, uint32_t index, const char** data_json);
+
+/* Drop all queued commands. */
+void grove_clear_commands(GroveVm* vm);
+
 /* ── Globals ───────────────────────────────────────── */
 
+/*
+ * The grove_set_global* functions return 0 on success and -1 on bad
+ * arguments or if a script has declared the name const.
+ */
 int32_t grove_set_global_number(GroveVm* vm, const char* name, double value);
 int32_t grove_set_global_string(GroveVm* vm, const char* name, const char* value);
 int32_t grove_set_global_vec3(GroveVm* vm, const char* name, double x, double y, double z);
 
+/* Set a bytes global to a copy of len bytes, preserved exactly (no UTF-8 check). */
+int32_t grove_set_global_bytes(GroveVm* vm, const char* name, const uint8_t* data, uint32_t len);
+
+/*
+ * Set count globals in one call, names[i] to values[i]. Returns -1 and sets
+ * nothing if any name is NULL, not valid UTF-8 or a script const.
+ */
+int32_t grove_set_globals(GroveVm* vm, const char* const* names, const GroveValue* values, uint32_t count);
+
 /* ── Error reporting ───────────────────────────────── */
 
 /*
@@ -121,6 +444,34 @@ uint32_t grove_last_error_line(const GroveVm* vm);
 /* Set the maximum number of instructions before aborting (0 = unlimited). */
 void grove_set_instruction_limit(GroveVm* vm, uint64_t limit);
 
+/*
+ * Abort with "loop iteration limit exceeded" when any single loop runs more
+ * than limit iterations (0 = unlimited, the default).
+ */
+void grove_set_max_loop_iterations(GroveVm* vm, uint64_t limit);
+
+/*
+ * Fail grove_eval() with "global data exceeds memory budget" when the globals
+ * a script leaves behind are estimated at more than bytes (0 = unlimited).
+ * The error is reported at the last statement run. The script's top-level
+ * return value is discarded, so grove_eval_value() fails without handing it
+ * back, but the oversized globals stay in place until grove_reset().
+ */
+void grove_set_memory_budget(GroveVm* vm, uint64_t bytes);
+
+/*
+ * Seed the random builtins (random_choice, shuffle, ...). The same seed
+ * gives the same results; grove_reset restarts the sequence from it.
+ */
+void grove_set_seed(GroveVm* vm, uint64_t seed);
+
+/*
+ * Reject sources longer than max_chars characters or producing more than
+ * max_tokens tokens before they are parsed (0 = unlimited). The limits also
+ * apply to source scripts run with eval(), compile() and import().
+ */
+void grove_set_source_limits(GroveVm* vm, uint64_t max_chars, uint64_t max_tokens);
+
 #ifdef __cplusplus
 } /* extern "C" */
 #endif
@@ -19,6 +19,12 @@ pub enum Stmt {
         init: Option<Expr>,
         span: Span,
     },
+    /// `const X = expr` — a binding that can't be reassigned or mutated
+    ConstDecl {
+        name: String,
+        init: Expr,
+        span: Span,
+    },
     /// `x = expr` (assignment to existing variable)
     Assign {
         target: Expr,
@@ -73,7 +79,8 @@ pub enum Stmt {
         body: Vec<Stmt>,
         span: Span,
     },
-    /// `build name(args)`
+    /// `build name(args)`: calls blueprint `name` directly, skipping the
+    /// variables and host functions a plain `name(args)` call would find first.
     Build {
         name: String,
         args: Vec<Expr>,
@@ -88,6 +95,8 @@ pub enum Stmt {
     Break { span: Span },
     /// `continue`
     Continue { span: Span },
+    /// `pass` — explicit no-op for stubbed bodies
+    Pass { span: Span },
 }
 
 #[derive(Debug, Clone)]
@@ -150,6 +159,53 @@ pub enum Expr {
         fields: Vec<(String, Expr)>,
         span: Span,
     },
+    /// `...expr` in an argument list, expanding an array into arguments
+    Spread {
+        expr: Box<Expr>,
+        span: Span,
+    },
+    /// `build name(args)` used for its value: `local t = build tree(3)`
+    Build {
+        name: String,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    /// `fn(params) ... end`: an anonymous function value
+    FunctionLit {
+        params: Vec<String>,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    /// `name: expr` in an argument list, binding a blueprint parameter by name
+    KeywordArg {
+        name: String,
+        value: Box<Expr>,
+        span: Span,
+    },
+}
+
+impl Stmt {
+    /// Where the statement starts: its first token, e.g. the target of an
+    /// assignment or the `local` of a declaration.
+    pub fn span(&self) -> &Span {
+        match self {
+            Stmt::LocalDecl { span, .. }
+            | Stmt::ConstDecl { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::ExprStmt { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::NumericFor { span, .. }
+            | Stmt::GenericFor { span, .. }
+            | Stmt::RepeatUntil { span, .. }
+            | Stmt::Blueprint { span, .. }
+            | Stmt::Build { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Break { span }
+            | Stmt::Continue { span }
+            | Stmt::Pass { span } => span,
+        }
+    }
 }
 
 impl Expr {
@@ -167,7 +223,11 @@ impl Expr {
             | Expr::IndexAccess { span, .. }
             | Expr::MethodCall { span, .. }
             | Expr::ArrayLit { span, .. }
-            | Expr::TableLit { span, .. } => span,
+            | Expr::TableLit { span, .. }
+            | Expr::Spread { span, .. }
+            | Expr::Build { span, .. }
+            | Expr::FunctionLit { span, .. }
+            | Expr::KeywordArg { span, .. } => span,
         }
     }
 }
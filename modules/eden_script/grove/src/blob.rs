@@ -0,0 +1,586 @@
+//! Binary serialization of blueprints, so hosts can ship precompiled
+//! blueprint libraries and load them without lexing or parsing source.
+//!
+//! A blob is `GRVB`, a format version byte, then the blueprint's name,
+//! parameters and body. Integers are little-endian `u32`s, strings are a
+//! length and UTF-8 bytes, and every AST node starts with a tag byte.
+
+use crate::ast::*;
+use crate::error::{GroveError, GroveResult};
+use crate::parser::{DEFAULT_MAX_DEPTH, DEFAULT_MAX_EXPRESSION_DEPTH};
+
+const MAGIC: &[u8; 4] = b"GRVB";
+const VERSION: u8 = 1;
+
+/// Serialize the blueprint `name(params) body end`.
+pub fn encode_blueprint(name: &str, params: &[String], body: &[Stmt]) -> Vec<u8> {
+    let mut w = Writer(Vec::new());
+    w.0.extend_from_slice(MAGIC);
+    w.0.push(VERSION);
+    w.string(name);
+    w.len(params.len());
+    for param in params {
+        w.string(param);
+    }
+    w.block(body);
+    w.0
+}
+
+/// Read back a blueprint written by `encode_blueprint` as its name,
+/// parameters and body. Truncated, corrupt or too deeply nested blobs, and
+/// blobs from another format version, are errors.
+pub fn decode_blueprint(bytes: &[u8]) -> GroveResult<(String, Vec<String>, Vec<Stmt>)> {
+    let mut r = Reader { bytes, pos: 0, depth: 0, expression_depth: 0 };
+    if r.take(4)? != MAGIC {
+        return Err(invalid("not a blueprint blob"));
+    }
+    let version = r.u8()?;
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported blueprint blob version {}", version)));
+    }
+    let name = r.string()?;
+    let params = r.list(Reader::string)?;
+    let body = r.block()?;
+    if r.pos != bytes.len() {
+        return Err(invalid("trailing bytes after blueprint"));
+    }
+    Ok((name, params, body))
+}
+
+fn invalid(msg: &str) -> GroveError {
+    GroveError::runtime(format!("invalid blueprint blob: {}", msg), 0, 0)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u32(&mut self, n: u32) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.u32(n as u32);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.len(s.len());
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn span(&mut self, span: &Span) {
+        self.len(span.line);
+        self.len(span.column);
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.len(stmts.len());
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        self.len(exprs.len());
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn opt_expr(&mut self, expr: Option<&Expr>) {
+        match expr {
+            Some(expr) => {
+                self.0.push(1);
+                self.expr(expr);
+            }
+            None => self.0.push(0),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::LocalDecl { name, init, span } => {
+                self.0.push(0);
+                self.string(name);
+                self.opt_expr(init.as_ref());
+                self.span(span);
+            }
+            Stmt::ConstDecl { name, init, span } => {
+                self.0.push(1);
+                self.string(name);
+                self.expr(init);
+                self.span(span);
+            }
+            Stmt::Assign { target, value, span } => {
+                self.0.push(2);
+                self.expr(target);
+                self.expr(value);
+                self.span(span);
+            }
+            Stmt::ExprStmt { expr, span } => {
+                self.0.push(3);
+                self.expr(expr);
+                self.span(span);
+            }
+            Stmt::If { condition, then_body, elseif_clauses, else_body, span } => {
+                self.0.push(4);
+                self.expr(condition);
+                self.block(then_body);
+                self.len(elseif_clauses.len());
+                for (cond, body) in elseif_clauses {
+                    self.expr(cond);
+                    self.block(body);
+                }
+                match else_body {
+                    Some(body) => {
+                        self.0.push(1);
+                        self.block(body);
+                    }
+                    None => self.0.push(0),
+                }
+                self.span(span);
+            }
+            Stmt::While { condition, body, span } => {
+                self.0.push(5);
+                self.expr(condition);
+                self.block(body);
+                self.span(span);
+            }
+            Stmt::NumericFor { var, start, limit, step, body, span } => {
+                self.0.push(6);
+                self.string(var);
+                self.expr(start);
+                self.expr(limit);
+                self.opt_expr(step.as_ref());
+                self.block(body);
+                self.span(span);
+            }
+            Stmt::GenericFor { vars, iter, body, span } => {
+                self.0.push(7);
+                self.len(vars.len());
+                for var in vars {
+                    self.string(var);
+                }
+                self.expr(iter);
+                self.block(body);
+                self.span(span);
+            }
+            Stmt::RepeatUntil { body, condition, span } => {
+                self.0.push(8);
+                self.block(body);
+                self.expr(condition);
+                self.span(span);
+            }
+            Stmt::Blueprint { name, params, body, span } => {
+                self.0.push(9);
+                self.string(name);
+                self.len(params.len());
+                for param in params {
+                    self.string(param);
+                }
+                self.block(body);
+                self.span(span);
+            }
+            Stmt::Build { name, args, span } => {
+                self.0.push(10);
+                self.string(name);
+                self.exprs(args);
+                self.span(span);
+            }
+            Stmt::Return { value, span } => {
+                self.0.push(11);
+                self.opt_expr(value.as_ref());
+                self.span(span);
+            }
+            Stmt::Break { span } => {
+                self.0.push(12);
+                self.span(span);
+            }
+            Stmt::Continue { span } => {
+                self.0.push(13);
+                self.span(span);
+            }
+            Stmt::Pass { span } => {
+                self.0.push(14);
+                self.span(span);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::NumberLit { value, span } => {
+                self.0.push(0);
+                self.0.extend_from_slice(&value.to_le_bytes());
+                self.span(span);
+            }
+            Expr::StringLit { value, span } => {
+                self.0.push(1);
+                self.string(value);
+                self.span(span);
+            }
+            Expr::BoolLit { value, span } => {
+                self.0.push(2);
+                self.0.push(*value as u8);
+                self.span(span);
+            }
+            Expr::NilLit { span } => {
+                self.0.push(3);
+                self.span(span);
+            }
+            Expr::Ident { name, span } => {
+                self.0.push(4);
+                self.string(name);
+                self.span(span);
+            }
+            Expr::BinaryOp { left, op, right, span } => {
+                self.0.push(5);
+                self.expr(left);
+                self.0.push(BINOPS.iter().position(|o| o == op).unwrap() as u8);
+                self.expr(right);
+                self.span(span);
+            }
+            Expr::UnaryOp { op, operand, span } => {
+                self.0.push(6);
+                self.0.push(UNARYOPS.iter().position(|o| o == op).unwrap() as u8);
+                self.expr(operand);
+                self.span(span);
+            }
+            Expr::Call { callee, args, span } => {
+                self.0.push(7);
+                self.expr(callee);
+                self.exprs(args);
+                self.span(span);
+            }
+            Expr::FieldAccess { object, field, span } => {
+                self.0.push(8);
+                self.expr(object);
+                self.string(field);
+                self.span(span);
+            }
+            Expr::IndexAccess { object, index, span } => {
+                self.0.push(9);
+                self.expr(object);
+                self.expr(index);
+                self.span(span);
+            }
+            Expr::MethodCall { object, method, args, span } => {
+                self.0.push(10);
+                self.expr(object);
+                self.string(method);
+                self.exprs(args);
+                self.span(span);
+            }
+            Expr::ArrayLit { elements, span } => {
+                self.0.push(11);
+                self.exprs(elements);
+                self.span(span);
+            }
+            Expr::TableLit { fields, span } => {
+                self.0.push(12);
+                self.len(fields.len());
+                for (key, value) in fields {
+                    self.string(key);
+                    self.expr(value);
+                }
+                self.span(span);
+            }
+            Expr::Spread { expr, span } => {
+                self.0.push(13);
+                self.expr(expr);
+                self.span(span);
+            }
+            Expr::Build { name, args, span } => {
+                self.0.push(14);
+                self.string(name);
+                self.exprs(args);
+                self.span(span);
+            }
+            Expr::KeywordArg { name, value, span } => {
+                self.0.push(15);
+                self.string(name);
+                self.expr(value);
+                self.span(span);
+            }
+            Expr::FunctionLit { params, body, span } => {
+                self.0.push(16);
+                self.len(params.len());
+                for param in params {
+                    self.string(param);
+                }
+                self.block(body);
+                self.span(span);
+            }
+        }
+    }
+}
+
+/// Operators by their encoded index.
+const BINOPS: [BinOp; 15] = [
+    BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div, BinOp::Mod, BinOp::Pow, BinOp::Concat,
+    BinOp::Eq, BinOp::NotEq, BinOp::Lt, BinOp::LtEq, BinOp::Gt, BinOp::GtEq, BinOp::And, BinOp::Or,
+];
+const UNARYOPS: [UnaryOp; 3] = [UnaryOp::Neg, UnaryOp::Not, UnaryOp::Len];
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Statements and expressions being read, one inside the other, capped
+    /// like the parser's nesting so a crafted blob can't overflow the stack.
+    depth: usize,
+    expression_depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> GroveResult<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| invalid("unexpected end of data"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> GroveResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn len(&mut self) -> GroveResult<usize> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> GroveResult<String> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not UTF-8"))
+    }
+
+    fn span(&mut self) -> GroveResult<Span> {
+        Ok(Span { line: self.len()?, column: self.len()? })
+    }
+
+    fn flag(&mut self) -> GroveResult<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(invalid(&format!("bad flag byte {}", b))),
+        }
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> GroveResult<T>) -> GroveResult<Vec<T>> {
+        let count = self.len()?;
+        // Not preallocated: `count` is untrusted until the items are read.
+        let mut items = Vec::new();
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn block(&mut self) -> GroveResult<Vec<Stmt>> {
+        self.list(Self::stmt)
+    }
+
+    fn exprs(&mut self) -> GroveResult<Vec<Expr>> {
+        self.list(Self::expr)
+    }
+
+    fn boxed(&mut self) -> GroveResult<Box<Expr>> {
+        Ok(Box::new(self.expr()?))
+    }
+
+    fn opt_expr(&mut self) -> GroveResult<Option<Expr>> {
+        Ok(if self.flag()? { Some(self.expr()?) } else { None })
+    }
+
+    /// Run `read` one statement or expression level deeper, failing past
+    /// `DEFAULT_MAX_DEPTH` or `DEFAULT_MAX_EXPRESSION_DEPTH`.
+    fn nested<T>(&mut self, expression: bool, read: impl FnOnce(&mut Self) -> GroveResult<T>) -> GroveResult<T> {
+        let (depth, max) = if expression {
+            (self.expression_depth, DEFAULT_MAX_EXPRESSION_DEPTH)
+        } else {
+            (self.depth, DEFAULT_MAX_DEPTH)
+        };
+        if depth >= max {
+            return Err(invalid("nested too deeply"));
+        }
+        self.set_depth(expression, depth + 1);
+        let result = read(self);
+        self.set_depth(expression, depth);
+        result
+    }
+
+    fn set_depth(&mut self, expression: bool, depth: usize) {
+        if expression {
+            self.expression_depth = depth;
+        } else {
+            self.depth = depth;
+        }
+    }
+
+    fn stmt(&mut self) -> GroveResult<Stmt> {
+        self.nested(false, Self::nested_stmt)
+    }
+
+    fn nested_stmt(&mut self) -> GroveResult<Stmt> {
+        Ok(match self.u8()? {
+            0 => Stmt::LocalDecl { name: self.string()?, init: self.opt_expr()?, span: self.span()? },
+            1 => Stmt::ConstDecl { name: self.string()?, init: self.expr()?, span: self.span()? },
+            2 => Stmt::Assign { target: self.expr()?, value: self.expr()?, span: self.span()? },
+            3 => Stmt::ExprStmt { expr: self.expr()?, span: self.span()? },
+            4 => Stmt::If {
+                condition: self.expr()?,
+                then_body: self.block()?,
+                elseif_clauses: self.list(|r| Ok((r.expr()?, r.block()?)))?,
+                else_body: if self.flag()? { Some(self.block()?) } else { None },
+                span: self.span()?,
+            },
+            5 => Stmt::While { condition: self.expr()?, body: self.block()?, span: self.span()? },
+            6 => Stmt::NumericFor {
+                var: self.string()?,
+                start: self.expr()?,
+                limit: self.expr()?,
+                step: self.opt_expr()?,
+                body: self.block()?,
+                span: self.span()?,
+            },
+            7 => Stmt::GenericFor {
+                vars: self.list(Self::string)?,
+                iter: self.expr()?,
+                body: self.block()?,
+                span: self.span()?,
+            },
+            8 => Stmt::RepeatUntil { body: self.block()?, condition: self.expr()?, span: self.span()? },
+            9 => Stmt::Blueprint {
+                name: self.string()?,
+                params: self.list(Self::string)?,
+                body: self.block()?,
+                span: self.span()?,
+            },
+            10 => Stmt::Build { name: self.string()?, args: self.exprs()?, span: self.span()? },
+            11 => Stmt::Return { value: self.opt_expr()?, span: self.span()? },
+            12 => Stmt::Break { span: self.span()? },
+            13 => Stmt::Continue { span: self.span()? },
+            14 => Stmt::Pass { span: self.span()? },
+            tag => return Err(invalid(&format!("unknown statement tag {}", tag))),
+        })
+    }
+
+    fn expr(&mut self) -> GroveResult<Expr> {
+        self.nested(true, Self::nested_expr)
+    }
+
+    fn nested_expr(&mut self) -> GroveResult<Expr> {
+        Ok(match self.u8()? {
+            0 => {
+                let value = f64::from_le_bytes(self.take(8)?.try_into().unwrap());
+                Expr::NumberLit { value, span: self.span()? }
+            }
+            1 => Expr::StringLit { value: self.string()?, span: self.span()? },
+            2 => Expr::BoolLit { value: self.flag()?, span: self.span()? },
+            3 => Expr::NilLit { span: self.span()? },
+            4 => Expr::Ident { name: self.string()?, span: self.span()? },
+            5 => {
+                let left = self.boxed()?;
+                let op = BINOPS.get(self.u8()? as usize).cloned().ok_or_else(|| invalid("unknown operator"))?;
+                Expr::BinaryOp { left, op, right: self.boxed()?, span: self.span()? }
+            }
+            6 => {
+                let op = UNARYOPS.get(self.u8()? as usize).cloned().ok_or_else(|| invalid("unknown operator"))?;
+                Expr::UnaryOp { op, operand: self.boxed()?, span: self.span()? }
+            }
+            7 => Expr::Call { callee: self.boxed()?, args: self.exprs()?, span: self.span()? },
+            8 => Expr::FieldAccess { object: self.boxed()?, field: self.string()?, span: self.span()? },
+            9 => Expr::IndexAccess { object: self.boxed()?, index: self.boxed()?, span: self.span()? },
+            10 => Expr::MethodCall {
+                object: self.boxed()?,
+                method: self.string()?,
+                args: self.exprs()?,
+                span: self.span()?,
+            },
+            11 => Expr::ArrayLit { elements: self.exprs()?, span: self.span()? },
+            12 => Expr::TableLit {
+                fields: self.list(|r| Ok((r.string()?, r.expr()?)))?,
+                span: self.span()?,
+            },
+            13 => Expr::Spread { expr: self.boxed()?, span: self.span()? },
+            14 => Expr::Build { name: self.string()?, args: self.exprs()?, span: self.span()? },
+            15 => Expr::KeywordArg { name: self.string()?, value: self.boxed()?, span: self.span()? },
+            16 => Expr::FunctionLit {
+                params: self.list(Self::string)?,
+                body: self.block()?,
+                span: self.span()?,
+            },
+            tag => return Err(invalid(&format!("unknown expression tag {}", tag))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse_blueprint(src: &str) -> (String, Vec<String>, Vec<Stmt>) {
+        let program = Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        match program.statements.into_iter().next() {
+            Some(Stmt::Blueprint { name, params, body, .. }) => (name, params, body),
+            other => panic!("expected a blueprint, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_preserves_ast() {
+        let (name, params, body) = parse_blueprint(r#"
+blueprint forest(n, kind)
+    local trees = []
+    for i = 1, n, 2 do
+        if i % 3 == 0 then continue elseif not (i > 5) then pass else break end
+        trees:push({x = i * 1.5, kind = kind .. "!", tags = [true, nil]})
+    end
+    for _, t in trees do while false do log(...t) end end
+    trees[0] = trees[#trees - 1]
+    repeat n = n - 1 until n <= 0
+    const k = build tree(height: 2):grow(-1)
+    local by_x = fn(a, b) return a.x < b.x end
+    return trees
+end
+"#);
+        let blob = encode_blueprint(&name, &params, &body);
+        let (name2, params2, body2) = decode_blueprint(&blob).unwrap();
+        assert_eq!(name2, "forest");
+        assert_eq!(params2, ["n", "kind"]);
+        // Debug output covers every field, spans included.
+        assert_eq!(format!("{:?}", body2), format!("{:?}", body));
+    }
+
+    #[test]
+    fn test_rejects_bad_blobs() {
+        let (name, params, body) = parse_blueprint("blueprint f(a) return a + 1 end");
+        let blob = encode_blueprint(&name, &params, &body);
+        for len in 0..blob.len() {
+            assert!(decode_blueprint(&blob[..len]).is_err(), "truncated to {}", len);
+        }
+        let mut extra = blob.clone();
+        extra.push(0);
+        assert!(decode_blueprint(&extra).is_err());
+        let mut version = blob.clone();
+        version[4] = 99;
+        let err = decode_blueprint(&version).unwrap_err();
+        assert_eq!(err.message, "invalid blueprint blob: unsupported blueprint blob version 99");
+
+        // A chain of unary minus nodes far deeper than the parser allows.
+        let mut deep = Writer(Vec::new());
+        deep.0.extend_from_slice(MAGIC);
+        deep.0.push(VERSION);
+        deep.string("f");
+        deep.len(0);
+        deep.len(1);
+        deep.0.push(3);
+        for _ in 0..100_000 {
+            deep.0.extend_from_slice(&[6, 0]);
+        }
+        let err = crate::parser::on_main_thread_stack(move || decode_blueprint(&deep.0).unwrap_err());
+        assert_eq!(err.message, "invalid blueprint blob: nested too deeply");
+    }
+}
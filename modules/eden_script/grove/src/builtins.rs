@@ -0,0 +1,1073 @@
+//! Pure built-in functions that don't need interpreter state.
+
+use std::collections::HashMap;
+
+use crate::ast::Span;
+use crate::error::{GroveError, GroveResult};
+use crate::types::{self, Function, Value};
+
+/// Namespaces whose functions are reachable as `ns.fn(args)`.
+pub const NAMESPACES: &[&str] = &["math", "string"];
+
+/// Dispatch a global built-in call. Returns `None` if `name` isn't a builtin.
+pub fn call_global(name: &str, args: &[Value], span: &Span) -> Option<GroveResult<Value>> {
+    let result = match name {
+        "format" => format(args, span),
+        "assert" => assert(args, span),
+        "unreachable" => unreachable(args, span),
+        "between" => between(args, span),
+        "approx_eq" => approx_eq(args, span),
+        "zip" => zip(args, span),
+        "merge" => merge("merge", args, span, false),
+        "deep_merge" => merge("deep_merge", args, span, true),
+        "enumerate" => enumerate(args, span),
+        "partial" => partial(args, span),
+        "pairs" => pairs(args, span),
+        "sorted" => sorted(args, span),
+        "sorted_keys" => sorted_keys(args, span),
+        "is_empty" => is_empty(args, span),
+        "contains" => index_of("contains", args, span).map(|idx| Value::Bool(idx.is_some())),
+        "index_of" => index_of("index_of", args, span)
+            .map(|idx| Value::Number(idx.map_or(-1.0, |i| i as f64))),
+        "reverse" => reverse(args, span),
+        "trim" => trim("trim", args, span, str::trim),
+        "trim_start" => trim("trim_start", args, span, str::trim_start),
+        "trim_end" => trim("trim_end", args, span, str::trim_end),
+        "split_lines" => split_lines(args, span),
+        "char" => char_of(args, span),
+        "byte" => byte(args, span),
+        "to_bytes" => to_bytes(args, span),
+        "from_bytes" => from_bytes(args, span),
+        "starts_with" => affix("starts_with", args, span, |s, p| s.starts_with(p)),
+        "ends_with" => affix("ends_with", args, span, |s, p| s.ends_with(p)),
+        "first" => array_end("first", args, span, |arr| arr.first()),
+        "last" => array_end("last", args, span, |arr| arr.last()),
+        "is_integer" => number_predicate("is_integer", args, span, types::is_integer),
+        "is_finite" => number_predicate("is_finite", args, span, f64::is_finite),
+        "is_nan" => number_predicate("is_nan", args, span, f64::is_nan),
+        "is_infinite" => number_predicate("is_infinite", args, span, f64::is_infinite),
+        "is_frozen" => {
+            expect_args("is_frozen", args, 1, span).map(|_| Value::Bool(args[0].is_frozen()))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Dispatch a `ns.<name>(args)` call for one of `NAMESPACES`.
+pub fn call_namespaced(ns: &str, name: &str, args: &[Value], span: &Span) -> GroveResult<Value> {
+    match ns {
+        "math" => call_math(name, args, span),
+        "string" => call_string(name, args, span),
+        _ => Err(GroveError::name_error(
+            format!("undefined namespace '{}'", ns),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Dispatch a `string.<name>(args)` call.
+pub fn call_string(name: &str, args: &[Value], span: &Span) -> GroveResult<Value> {
+    match name {
+        "format" => format(args, span),
+        _ => Err(GroveError::name_error(
+            format!("undefined function 'string.{}'", name),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Dispatch a `math.<name>(args)` call.
+pub fn call_math(name: &str, args: &[Value], span: &Span) -> GroveResult<Value> {
+    match name {
+        "floor" => unary_numeric("math.floor", args, span, f64::floor),
+        "ceil" => unary_numeric("math.ceil", args, span, f64::ceil),
+        "round" => unary_numeric("math.round", args, span, f64::round),
+        "abs" => unary_numeric("math.abs", args, span, f64::abs),
+        "min" => fold_numeric("math.min", args, span, f64::min),
+        "max" => fold_numeric("math.max", args, span, f64::max),
+        _ => Err(GroveError::name_error(
+            format!("undefined function 'math.{}'", name),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Combine one or more numbers with `f`, or one or more vec3s component-wise
+/// (`math.max(vec3(1, 5, 2), vec3(3, 0, 2))` is `vec3(3, 5, 2)`).
+fn fold_numeric(fname: &str, args: &[Value], span: &Span, f: fn(f64, f64) -> f64) -> GroveResult<Value> {
+    let (first, rest) = args.split_first().ok_or_else(|| {
+        GroveError::runtime(format!("{}() expects at least 1 argument", fname), span.line, span.column)
+    })?;
+    let mut acc = first.clone();
+    for arg in rest {
+        acc = match (&acc, arg) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(f(*a, *b)),
+            (Value::Vec3(ax, ay, az), Value::Vec3(bx, by, bz)) => Value::Vec3(f(*ax, *bx), f(*ay, *by), f(*az, *bz)),
+            (a, b) => {
+                return Err(GroveError::type_error(
+                    format!("{}() expects all numbers or all vec3s, got {} and {}", fname, a.type_name(), b.type_name()),
+                    span.line, span.column,
+                ));
+            }
+        };
+    }
+    match acc {
+        Value::Number(_) | Value::Vec3(..) => Ok(acc),
+        other => Err(GroveError::type_error(
+            format!("{}() expects numbers or vec3s, got {}", fname, other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `assert(a, b, ...)` — error at the call site unless every argument is truthy.
+fn assert(args: &[Value], span: &Span) -> GroveResult<Value> {
+    if args.is_empty() {
+        return Err(GroveError::runtime("assert() expects at least 1 argument", span.line, span.column));
+    }
+    match args.iter().position(|v| !v.is_truthy()) {
+        Some(i) if args.len() == 1 => Err(GroveError::runtime(
+            format!("assertion failed: got {}", args[i]),
+            span.line, span.column,
+        )),
+        Some(i) => Err(GroveError::runtime(
+            format!("assertion failed: argument {} is {}", i + 1, args[i]),
+            span.line, span.column,
+        )),
+        None => Ok(Value::Nil),
+    }
+}
+
+/// `unreachable(msg)` — always an error, for branches that should never run.
+fn unreachable(args: &[Value], span: &Span) -> GroveResult<Value> {
+    let message = match args {
+        [] => "reached unreachable code".to_string(),
+        [msg] => format!("unreachable: {}", msg),
+        _ => {
+            return Err(GroveError::runtime(
+                format!("unreachable() expects 0 or 1 arguments, got {}", args.len()),
+                span.line, span.column,
+            ));
+        }
+    };
+    Err(GroveError::runtime(message, span.line, span.column))
+}
+
+/// `between(x, lo, hi)` — whether `lo <= x <= hi`, for numbers or strings
+/// (compared lexicographically). All three must be the same type.
+fn between(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("between", args, 3, span)?;
+    match (&args[0], &args[1], &args[2]) {
+        (Value::Number(x), Value::Number(lo), Value::Number(hi)) => Ok(Value::Bool(lo <= x && x <= hi)),
+        (Value::String(x), Value::String(lo), Value::String(hi)) => Ok(Value::Bool(lo <= x && x <= hi)),
+        (x, lo, hi) => Err(GroveError::type_error(
+            format!(
+                "between() expects three numbers or three strings, got {}, {} and {}",
+                x.type_name(), lo.type_name(), hi.type_name()
+            ),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Tolerance `approx_eq` uses when none is given.
+const DEFAULT_EPSILON: f64 = 1e-9;
+
+/// `approx_eq(a, b[, epsilon])` — whether numbers `a` and `b` differ by at
+/// most `epsilon` (default 1e-9). Vec3s compare component-wise.
+fn approx_eq(args: &[Value], span: &Span) -> GroveResult<Value> {
+    if !matches!(args.len(), 2 | 3) {
+        return Err(GroveError::runtime(
+            format!("approx_eq() expects 2 or 3 arguments, got {}", args.len()),
+            span.line, span.column,
+        ));
+    }
+    let epsilon = match args.get(2) {
+        None => DEFAULT_EPSILON,
+        Some(Value::Number(e)) if *e >= 0.0 => *e,
+        Some(other) => {
+            return Err(GroveError::type_error(
+                format!("approx_eq() expects a non-negative epsilon, got {}", other),
+                span.line, span.column,
+            ));
+        }
+    };
+    let close = |a: f64, b: f64| a == b || (a - b).abs() <= epsilon;
+    match (&args[0], &args[1]) {
+        (Value::Number(a), Value::Number(b)) => Ok(Value::Bool(close(*a, *b))),
+        (Value::Vec3(ax, ay, az), Value::Vec3(bx, by, bz)) => {
+            Ok(Value::Bool(close(*ax, *bx) && close(*ay, *by) && close(*az, *bz)))
+        }
+        (a, b) => Err(GroveError::type_error(
+            format!("approx_eq() expects two numbers or two vec3s, got {} and {}", a.type_name(), b.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `partial(f, args...)` — a function that calls `f` with `args` (copied
+/// now) followed by whatever it's called with.
+fn partial(args: &[Value], span: &Span) -> GroveResult<Value> {
+    match args.split_first() {
+        Some((Value::Function(func), bound)) => Ok(Value::Function(Function::Partial(
+            Box::new(func.clone()),
+            bound.to_vec(),
+        ))),
+        Some((other, _)) => Err(GroveError::type_error(
+            format!("partial() expects a function as its first argument, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+        None => Err(GroveError::runtime(
+            "partial() expects at least 1 argument, got 0",
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `pairs(t)` — `t` itself, so `for k, v in pairs(t)` reads as in Lua. A
+/// generic for visits table keys in sorted order and arrays by index.
+fn pairs(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("pairs", args, 1, span)?;
+    match &args[0] {
+        v @ (Value::Array { .. } | Value::Table { .. }) => Ok(v.clone()),
+        other => Err(GroveError::type_error(
+            format!("pairs() expects an array or table, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `sorted(v)` — a copy of an array of numbers or of strings in ascending
+/// order, or a table unchanged, as generic for already visits table keys in
+/// sorted order; `for k, v in sorted(t)` states that explicitly.
+fn sorted(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("sorted", args, 1, span)?;
+    match &args[0] {
+        Value::Array { items: arr, .. } => {
+            let mut items = arr.clone();
+            let mut mismatch = None;
+            items.sort_by(|a, b| match (a, b) {
+                (Value::Number(x), Value::Number(y)) => x.total_cmp(y),
+                (Value::String(x), Value::String(y)) => x.cmp(y),
+                _ => {
+                    mismatch.get_or_insert((a.type_name(), b.type_name()));
+                    std::cmp::Ordering::Equal
+                }
+            });
+            if let Some((a, b)) = mismatch {
+                return Err(GroveError::type_error(
+                    format!("sorted() expects all numbers or all strings, got {} and {}", a, b),
+                    span.line, span.column,
+                ));
+            }
+            Ok(Value::array(items))
+        }
+        table @ Value::Table { .. } => Ok(table.clone()),
+        other => Err(GroveError::type_error(
+            format!("sorted() expects an array or table, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `sorted_keys(t)` — the keys of table `t` as an array, in lexicographic order.
+fn sorted_keys(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("sorted_keys", args, 1, span)?;
+    match &args[0] {
+        Value::Table { fields: map, .. } => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            Ok(Value::array(keys.into_iter().map(|k| Value::String(k.clone())).collect()))
+        }
+        other => Err(GroveError::type_error(
+            format!("sorted_keys() expects a table, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `is_empty(v)` — whether an array, string or table has no elements.
+fn is_empty(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("is_empty", args, 1, span)?;
+    match &args[0] {
+        Value::Array { items: arr, .. } => Ok(Value::Bool(arr.is_empty())),
+        Value::String(s) => Ok(Value::Bool(s.is_empty())),
+        Value::Table { fields: map, .. } => Ok(Value::Bool(map.is_empty())),
+        other => Err(GroveError::type_error(
+            format!("is_empty() expects an array, string or table, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Shared by `contains(c, x)` and `index_of(c, x)`: the 0-based position of
+/// the first element of array `c` equal to `x`, or of substring `x` in string
+/// `c` counted in chars.
+fn index_of(name: &str, args: &[Value], span: &Span) -> GroveResult<Option<usize>> {
+    expect_args(name, args, 2, span)?;
+    match (&args[0], &args[1]) {
+        (Value::Array { items: arr, .. }, x) => Ok(arr.iter().position(|v| v == x)),
+        (Value::String(s), Value::String(sub)) => {
+            Ok(s.find(sub.as_str()).map(|byte| s[..byte].chars().count()))
+        }
+        (Value::String(_), other) => Err(GroveError::type_error(
+            format!("{}() on a string expects a string to search for, got {}", name, other.type_name()),
+            span.line, span.column,
+        )),
+        (other, _) => Err(GroveError::type_error(
+            format!("{}() expects an array or string, got {}", name, other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `reverse(v)` — a reversed copy of an array, or of a string by char so
+/// multi-byte characters stay intact.
+fn reverse(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("reverse", args, 1, span)?;
+    match &args[0] {
+        Value::Array { items: arr, .. } => Ok(Value::array(arr.iter().rev().cloned().collect())),
+        Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+        other => Err(GroveError::type_error(
+            format!("reverse() expects an array or string, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// The single string argument of `name(s)`.
+fn string_arg<'a>(name: &str, args: &'a [Value], span: &Span) -> GroveResult<&'a str> {
+    expect_args(name, args, 1, span)?;
+    args[0].as_string().ok_or_else(|| {
+        GroveError::type_error(
+            format!("{}() expects a string, got {}", name, args[0].type_name()),
+            span.line, span.column,
+        )
+    })
+}
+
+/// `trim(s)`, `trim_start(s)`, `trim_end(s)` — `s` without leading and/or
+/// trailing whitespace.
+fn trim(name: &str, args: &[Value], span: &Span, f: fn(&str) -> &str) -> GroveResult<Value> {
+    string_arg(name, args, span).map(|s| Value::String(f(s).to_string()))
+}
+
+/// `split_lines(s)` — the lines of `s` as an array, split on `\n` or `\r\n`.
+/// A final line ending doesn't add an empty line.
+fn split_lines(args: &[Value], span: &Span) -> GroveResult<Value> {
+    let s = string_arg("split_lines", args, span)?;
+    Ok(Value::array(s.lines().map(|line| Value::String(line.to_string())).collect()))
+}
+
+/// `char(n)` — the one-character string for Unicode scalar value `n`.
+fn char_of(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("char", args, 1, span)?;
+    let c = match &args[0] {
+        Value::Number(n) if types::is_integer(*n) && *n >= 0.0 && *n <= u32::MAX as f64 => char::from_u32(*n as u32),
+        Value::Number(_) => None,
+        other => {
+            return Err(GroveError::type_error(
+                format!("char() expects a number, got {}", other.type_name()),
+                span.line, span.column,
+            ));
+        }
+    };
+    c.map(|c| Value::String(c.to_string())).ok_or_else(|| {
+        GroveError::runtime(
+            format!("char() expects a Unicode scalar value, got {}", args[0]),
+            span.line, span.column,
+        )
+    })
+}
+
+/// `byte(s, i)` — the code point of the character at 0-based index `i`, or
+/// nil when `i` is out of range.
+fn byte(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("byte", args, 2, span)?;
+    match (&args[0], &args[1]) {
+        (Value::String(s), Value::Number(i)) => {
+            if !types::is_integer(*i) || *i < 0.0 {
+                return Ok(Value::Nil);
+            }
+            Ok(s.chars().nth(*i as usize).map_or(Value::Nil, |c| Value::Number(c as u32 as f64)))
+        }
+        (a, b) => Err(GroveError::type_error(
+            format!("byte() expects a string and a number, got {} and {}", a.type_name(), b.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `to_bytes(s)` — the UTF-8 encoding of string `s` as bytes.
+fn to_bytes(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("to_bytes", args, 1, span)?;
+    match &args[0] {
+        Value::String(s) => Ok(Value::Bytes(s.as_bytes().to_vec())),
+        Value::Bytes(b) => Ok(Value::Bytes(b.clone())),
+        other => Err(GroveError::type_error(
+            format!("to_bytes() expects a string, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `from_bytes(b)` — bytes `b` decoded as a UTF-8 string. Fails rather than
+/// replacing invalid sequences, so no data is silently lost.
+fn from_bytes(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("from_bytes", args, 1, span)?;
+    match &args[0] {
+        Value::Bytes(b) => String::from_utf8(b.clone()).map(Value::String).map_err(|e| {
+            GroveError::runtime(
+                format!("from_bytes() got invalid UTF-8 at byte {}", e.utf8_error().valid_up_to()),
+                span.line, span.column,
+            )
+        }),
+        Value::String(s) => Ok(Value::String(s.clone())),
+        other => Err(GroveError::type_error(
+            format!("from_bytes() expects bytes, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `starts_with(s, prefix)` / `ends_with(s, suffix)`. An empty affix
+/// always matches.
+fn affix(name: &str, args: &[Value], span: &Span, f: fn(&str, &str) -> bool) -> GroveResult<Value> {
+    expect_args(name, args, 2, span)?;
+    match (&args[0], &args[1]) {
+        (Value::String(s), Value::String(affix)) => Ok(Value::Bool(f(s, affix))),
+        (a, b) => Err(GroveError::type_error(
+            format!("{}() expects two strings, got {} and {}", name, a.type_name(), b.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `first(arr)` / `last(arr)` — the element at one end, or nil if `arr` is empty.
+fn array_end(
+    name: &str,
+    args: &[Value],
+    span: &Span,
+    pick: fn(&[Value]) -> Option<&Value>,
+) -> GroveResult<Value> {
+    expect_args(name, args, 1, span)?;
+    match &args[0] {
+        Value::Array { items: arr, .. } => Ok(pick(arr).cloned().unwrap_or(Value::Nil)),
+        other => Err(GroveError::type_error(
+            format!("{}() expects an array, got {}", name, other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `zip(a, b)` — pair up elements of two arrays as two-element arrays
+/// `[a[i], b[i]]`, stopping at the end of the shorter one.
+fn zip(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("zip", args, 2, span)?;
+    match (&args[0], &args[1]) {
+        (Value::Array { items: a, .. }, Value::Array { items: b, .. }) => Ok(Value::array(a.iter()
+                .zip(b)
+                .map(|(x, y)| Value::array(vec![x.clone(), y.clone()]))
+                .collect())),
+        (a, b) => Err(GroveError::type_error(
+            format!("zip() expects two arrays, got {} and {}", a.type_name(), b.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `merge(base, overrides)` — a new table with the fields of both, those of
+/// `overrides` winning. `deep_merge` merges fields that are tables on both
+/// sides the same way instead of replacing them. Neither changes its inputs.
+fn merge(name: &str, args: &[Value], span: &Span, deep: bool) -> GroveResult<Value> {
+    expect_args(name, args, 2, span)?;
+    match (&args[0], &args[1]) {
+        (Value::Table { fields: base, .. }, Value::Table { fields: overrides, .. }) => {
+            Ok(Value::table(merge_tables(base, overrides, deep)))
+        }
+        (a, b) => Err(GroveError::type_error(
+            format!("{}() expects two tables, got {} and {}", name, a.type_name(), b.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+fn merge_tables(base: &HashMap<String, Value>, overrides: &HashMap<String, Value>, deep: bool) -> HashMap<String, Value> {
+    let mut merged = base.clone();
+    for (key, value) in overrides {
+        let combined = match (merged.get(key), value) {
+            (Some(Value::Table { fields: inner, .. }), Value::Table { fields: over, .. }) if deep => {
+                Value::table(merge_tables(inner, over, true))
+            }
+            _ => value.clone(),
+        };
+        merged.insert(key.clone(), combined);
+    }
+    merged
+}
+
+/// `enumerate(arr)` — `[index, value]` pairs for each element, with 0-based
+/// indices to match array indexing.
+fn enumerate(args: &[Value], span: &Span) -> GroveResult<Value> {
+    expect_args("enumerate", args, 1, span)?;
+    match &args[0] {
+        Value::Array { items: arr, .. } => Ok(Value::array(arr.iter()
+                .enumerate()
+                .map(|(i, v)| Value::array(vec![Value::Number(i as f64), v.clone()]))
+                .collect())),
+        other => Err(GroveError::type_error(
+            format!("enumerate() expects an array, got {}", other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Test a single number argument with `pred`.
+fn number_predicate(fname: &str, args: &[Value], span: &Span, pred: fn(f64) -> bool) -> GroveResult<Value> {
+    expect_args(fname, args, 1, span)?;
+    match &args[0] {
+        Value::Number(n) => Ok(Value::Bool(pred(*n))),
+        other => Err(GroveError::type_error(
+            format!("{}() expects a number, got {}", fname, other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Apply `f` to a number, or to each component of a vec3.
+fn unary_numeric(fname: &str, args: &[Value], span: &Span, f: fn(f64) -> f64) -> GroveResult<Value> {
+    expect_args(fname, args, 1, span)?;
+    match &args[0] {
+        Value::Number(n) => Ok(Value::Number(f(*n))),
+        Value::Vec3(x, y, z) => Ok(Value::Vec3(f(*x), f(*y), f(*z))),
+        other => Err(GroveError::type_error(
+            format!("{}() expects a number or vec3, got {}", fname, other.type_name()),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// Largest width or precision `format` accepts, so a script can't ask for
+/// unbounded padding.
+const MAX_FORMAT_FIELD: usize = 999;
+
+/// Read a width or precision from a `format` specifier, 0 if absent.
+fn format_field(chars: &mut std::iter::Peekable<std::str::Chars>, span: &Span) -> GroveResult<usize> {
+    let mut n = 0usize;
+    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+        n = n * 10 + d as usize;
+        if n > MAX_FORMAT_FIELD {
+            return Err(GroveError::runtime(
+                format!("format width or precision exceeds {}", MAX_FORMAT_FIELD),
+                span.line, span.column,
+            ));
+        }
+        chars.next();
+    }
+    Ok(n)
+}
+
+/// `format(fmt, ...)` — printf-style formatting supporting `%d %i %s %f %e
+/// %g %x %X %%` with `-`, `+`, `0` flags, width and precision. Output never
+/// depends on the host locale: the decimal separator is always `.`, and `%f`
+/// prints large magnitudes in full rather than switching to exponents.
+fn format(args: &[Value], span: &Span) -> GroveResult<Value> {
+    let fmt = match args.first() {
+        Some(Value::String(s)) => s,
+        Some(other) => {
+            return Err(GroveError::type_error(
+                format!("format() expects a format string, got {}", other.type_name()),
+                span.line, span.column,
+            ));
+        }
+        None => {
+            return Err(GroveError::runtime("format() expects a format string", span.line, span.column));
+        }
+    };
+
+    let mut out = String::new();
+    let mut next_arg = 1;
+    let mut chars = fmt.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push(ch);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+
+        let (mut left, mut zero, mut plus) = (false, false, false);
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => left = true,
+                '0' => zero = true,
+                '+' => plus = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let width = format_field(&mut chars, span)?;
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            precision = Some(format_field(&mut chars, span)?);
+        }
+        let conv = chars.next().ok_or_else(|| {
+            GroveError::runtime("format string ends with an incomplete '%' specifier", span.line, span.column)
+        })?;
+
+        let arg = args.get(next_arg).ok_or_else(|| {
+            GroveError::runtime(
+                format!("bad argument #{} to 'format' (no value)", next_arg + 1),
+                span.line, span.column,
+            )
+        })?;
+        next_arg += 1;
+
+        let number = |arg: &Value| {
+            arg.as_number().ok_or_else(|| {
+                GroveError::type_error(
+                    format!("format '%{}' expects a number, got {}", conv, arg.type_name()),
+                    span.line, span.column,
+                )
+            })
+        };
+        let integer = |arg: &Value| {
+            let n = number(arg)?;
+            if n.fract() != 0.0 || !n.is_finite() {
+                return Err(GroveError::type_error(
+                    format!("format '%{}' expects an integer, got {}", conv, arg),
+                    span.line, span.column,
+                ));
+            }
+            Ok(n as i64)
+        };
+
+        let (body, numeric) = match conv {
+            'd' | 'i' => (integer(arg)?.to_string(), true),
+            'x' => (format!("{:x}", integer(arg)?), true),
+            'X' => (format!("{:X}", integer(arg)?), true),
+            'f' | 'F' => (format_fixed(number(arg)?, precision.unwrap_or(6)), true),
+            'e' | 'E' => {
+                let s = format_exp(number(arg)?, precision.unwrap_or(6));
+                (if conv == 'E' { s.to_uppercase() } else { s }, true)
+            }
+            'g' | 'G' => {
+                let s = format_general(number(arg)?, precision.unwrap_or(6));
+                (if conv == 'G' { s.to_uppercase() } else { s }, true)
+            }
+            's' => {
+                let s = format!("{}", arg);
+                match precision {
+                    Some(p) => (s.chars().take(p).collect(), false),
+                    None => (s, false),
+                }
+            }
+            other => {
+                return Err(GroveError::runtime(
+                    format!("invalid conversion '%{}' in format string", other),
+                    span.line, span.column,
+                ));
+            }
+        };
+
+        let body = if numeric && plus && !body.starts_with('-') { format!("+{}", body) } else { body };
+        let len = body.chars().count();
+        if len >= width {
+            out.push_str(&body);
+        } else if left {
+            out.push_str(&body);
+            out.extend(std::iter::repeat_n(' ', width - len));
+        } else if zero && numeric && body.chars().last().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            // Zero padding goes between the sign and the digits
+            let (sign, digits) = match body.chars().next() {
+                Some(c @ ('-' | '+')) => (c.to_string(), &body[1..]),
+                _ => (String::new(), body.as_str()),
+            };
+            out.push_str(&sign);
+            out.extend(std::iter::repeat_n('0', width - len));
+            out.push_str(digits);
+        } else {
+            out.extend(std::iter::repeat_n(' ', width - len));
+            out.push_str(&body);
+        }
+    }
+    Ok(Value::String(out))
+}
+
+/// Spell non-finite numbers the way C's printf does.
+fn non_finite(n: f64) -> Option<String> {
+    if n.is_nan() {
+        Some("nan".into())
+    } else if n.is_infinite() {
+        Some(if n > 0.0 { "inf".into() } else { "-inf".into() })
+    } else {
+        None
+    }
+}
+
+fn format_fixed(n: f64, precision: usize) -> String {
+    non_finite(n).unwrap_or_else(|| format!("{:.*}", precision, n))
+}
+
+/// `%e`: one leading digit and a signed, at least two-digit exponent (`1.5e+03`).
+fn format_exp(n: f64, precision: usize) -> String {
+    if let Some(s) = non_finite(n) {
+        return s;
+    }
+    let s = format!("{:.*e}", precision, n);
+    let (mantissa, exp) = s.split_once('e').unwrap_or((&s, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs())
+}
+
+/// `%g`: `%e` or `%f`, whichever C would pick for this precision, with
+/// trailing zeros removed.
+fn format_general(n: f64, precision: usize) -> String {
+    if let Some(s) = non_finite(n) {
+        return s;
+    }
+    let p = precision.max(1);
+    // The exponent after rounding to `p` significant digits decides the style.
+    let exp = if n == 0.0 {
+        0
+    } else {
+        let s = format!("{:.*e}", p - 1, n);
+        s.split_once('e').and_then(|(_, e)| e.parse::<i32>().ok()).unwrap_or(0)
+    };
+    if exp < -4 || exp >= p as i32 {
+        let s = format_exp(n, p - 1);
+        let (mantissa, exp) = s.split_once('e').unwrap_or((&s, ""));
+        format!("{}e{}", strip_fraction_zeros(mantissa), exp)
+    } else {
+        let decimals = (p as i32 - 1 - exp).max(0) as usize;
+        strip_fraction_zeros(&format!("{:.*}", decimals, n)).to_string()
+    }
+}
+
+fn strip_fraction_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+/// Error unless exactly `count` arguments were passed to `fname`.
+pub(crate) fn expect_args(fname: &str, args: &[Value], count: usize, span: &Span) -> GroveResult<()> {
+    if args.len() != count {
+        return Err(GroveError::runtime(
+            format!("{}() expects {} argument{}, got {}",
+                fname, count, if count == 1 { "" } else { "s" }, args.len()),
+            span.line, span.column,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span() -> Span {
+        Span { line: 1, column: 1 }
+    }
+
+    #[test]
+    fn test_rounding_scalars() {
+        assert_eq!(call_math("floor", &[Value::Number(1.7)], &span()).unwrap(), Value::Number(1.0));
+        assert_eq!(call_math("ceil", &[Value::Number(1.2)], &span()).unwrap(), Value::Number(2.0));
+        assert_eq!(call_math("round", &[Value::Number(-2.5)], &span()).unwrap(), Value::Number(-3.0));
+    }
+
+    #[test]
+    fn test_rounding_vec3() {
+        let v = Value::Vec3(1.7, 2.2, 3.9);
+        let call = |name| call_math(name, std::slice::from_ref(&v), &span()).unwrap();
+        assert_eq!(call("floor"), Value::Vec3(1.0, 2.0, 3.0));
+        assert_eq!(call("ceil"), Value::Vec3(2.0, 3.0, 4.0));
+        assert_eq!(call("round"), Value::Vec3(2.0, 2.0, 4.0));
+    }
+
+    #[test]
+    fn test_math_min_max_abs() {
+        let v = |x, y, z| Value::Vec3(x, y, z);
+        let n = Value::Number;
+        assert_eq!(call_math("max", &[v(1.0, 5.0, 2.0), v(3.0, 0.0, 2.0)], &span()).unwrap(), v(3.0, 5.0, 2.0));
+        assert_eq!(call_math("min", &[v(1.0, 5.0, 2.0), v(3.0, 0.0, 2.0), v(2.0, 2.0, -1.0)], &span()).unwrap(), v(1.0, 0.0, -1.0));
+        assert_eq!(call_math("abs", &[v(-1.0, 2.0, -0.5)], &span()).unwrap(), v(1.0, 2.0, 0.5));
+        assert_eq!(call_math("max", &[n(4.0), n(9.0), n(-2.0)], &span()).unwrap(), n(9.0));
+        assert_eq!(call_math("min", &[n(4.0)], &span()).unwrap(), n(4.0));
+        assert_eq!(call_math("abs", &[n(-3.0)], &span()).unwrap(), n(3.0));
+
+        let err = call_math("max", &[v(1.0, 1.0, 1.0), n(2.0)], &span()).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Type);
+        assert!(call_math("min", &[], &span()).is_err());
+        assert!(call_math("min", &[Value::Bool(true)], &span()).is_err());
+    }
+
+    #[test]
+    fn test_number_predicates() {
+        let check = |name: &str, n: f64| call_global(name, &[Value::Number(n)], &span()).unwrap().unwrap();
+        let t = Value::Bool(true);
+        let f = Value::Bool(false);
+        assert_eq!(check("is_integer", 3.0), t);
+        assert_eq!(check("is_integer", 3.5), f);
+        assert_eq!(check("is_integer", f64::INFINITY), f);
+        assert_eq!(check("is_finite", 1e300), t);
+        assert_eq!(check("is_finite", f64::NAN), f);
+        assert_eq!(check("is_nan", f64::NAN), t);
+        assert_eq!(check("is_nan", 0.0), f);
+        assert_eq!(check("is_infinite", f64::NEG_INFINITY), t);
+        assert_eq!(check("is_infinite", f64::NAN), f);
+        assert!(call_global("is_nan", &[Value::Nil], &span()).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_math_errors() {
+        assert!(call_math("floor", &[Value::String("1".into())], &span()).is_err());
+        assert!(call_math("floor", &[], &span()).is_err());
+        assert!(call_math("nope", &[], &span()).is_err());
+    }
+
+    fn fmt(args: Vec<Value>) -> String {
+        match call_global("format", &args, &span()).unwrap().unwrap() {
+            Value::String(s) => s,
+            other => panic!("expected string, got {:?}", other),
+        }
+    }
+
+    fn s(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    #[test]
+    fn test_format_deterministic_numbers() {
+        assert_eq!(fmt(vec![s("%g"), Value::Number(100000.0)]), "100000");
+        assert_eq!(fmt(vec![s("%g"), Value::Number(1000000.0)]), "1e+06");
+        assert_eq!(fmt(vec![s("%g"), Value::Number(0.0001)]), "0.0001");
+        assert_eq!(fmt(vec![s("%g"), Value::Number(0.00001)]), "1e-05");
+        assert_eq!(fmt(vec![s("%g"), Value::Number(2.5)]), "2.5");
+        assert_eq!(fmt(vec![s("%.3f"), Value::Number(1.0 / 3.0)]), "0.333");
+        assert_eq!(fmt(vec![s("%.2f"), Value::Number(1e20)]), "100000000000000000000.00");
+        assert_eq!(fmt(vec![s("%e"), Value::Number(12345.678)]), "1.234568e+04");
+        assert_eq!(fmt(vec![s("%.2E"), Value::Number(-0.000123)]), "-1.23E-04");
+        assert_eq!(fmt(vec![s("%f"), Value::Number(f64::NAN)]), "nan");
+    }
+
+    #[test]
+    fn test_format_flags_and_strings() {
+        assert_eq!(
+            fmt(vec![s("%5d|%-5d|%05.1f|%+d"), Value::Number(42.0), Value::Number(42.0),
+                Value::Number(2.66), Value::Number(7.0)]),
+            "   42|42   |002.7|+7"
+        );
+        assert_eq!(fmt(vec![s("%s=%.2s %x %%"), s("key"), s("value"), Value::Number(255.0)]), "key=va ff %");
+        assert_eq!(fmt(vec![s("%-6s|"), Value::Bool(true)]), "true  |");
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let err = |args: Vec<Value>| call_global("format", &args, &span()).unwrap().unwrap_err().message;
+        assert!(err(vec![s("%d"), Value::Number(1.5)]).contains("expects an integer"));
+        assert!(err(vec![s("%d")]).contains("no value"));
+        assert!(err(vec![s("%q"), Value::Nil]).contains("invalid conversion"));
+        assert!(err(vec![s("%99999999999999999999999d"), Value::Number(1.0)]).contains("exceeds 999"));
+        assert!(err(vec![s("%.1000f"), Value::Number(1.0)]).contains("exceeds 999"));
+        let ok = call_global("format", &[s("%999d"), Value::Number(1.0)], &span()).unwrap().unwrap();
+        assert_eq!(ok.as_string().map(str::len), Some(999));
+    }
+
+    #[test]
+    fn test_between() {
+        let between = |x: Value, lo: Value, hi: Value| call_global("between", &[x, lo, hi], &span()).unwrap();
+        let n = Value::Number;
+        let s = |v: &str| Value::String(v.to_string());
+        assert_eq!(between(n(5.0), n(1.0), n(10.0)).unwrap(), Value::Bool(true));
+        assert_eq!(between(n(0.0), n(1.0), n(10.0)).unwrap(), Value::Bool(false));
+        assert_eq!(between(n(10.0), n(1.0), n(10.0)).unwrap(), Value::Bool(true));
+        assert_eq!(between(s("fir"), s("ash"), s("oak")).unwrap(), Value::Bool(true));
+        assert_eq!(between(s("pine"), s("ash"), s("oak")).unwrap(), Value::Bool(false));
+        let err = between(n(5.0), s("a"), n(10.0)).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Type);
+        assert!(between(Value::Nil, Value::Nil, Value::Nil).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let table = |fields: Vec<(&str, Value)>| {
+            Value::table(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        };
+        let n = Value::Number;
+        let base = table(vec![("size", n(1.0)), ("color", n(2.0)), ("leaf", table(vec![("w", n(1.0)), ("h", n(2.0))]))]);
+        let overrides = table(vec![("size", n(5.0)), ("leaf", table(vec![("h", n(9.0))]))]);
+        let field = |t: &Value, k: &str| match t {
+            Value::Table { fields: map, .. } => map.get(k).cloned().unwrap_or(Value::Nil),
+            _ => panic!("not a table"),
+        };
+
+        let merged = call_global("merge", &[base.clone(), overrides.clone()], &span()).unwrap().unwrap();
+        assert_eq!(field(&merged, "size"), n(5.0));
+        assert_eq!(field(&merged, "color"), n(2.0));
+        assert_eq!(field(&field(&merged, "leaf"), "w"), Value::Nil);
+
+        let deep = call_global("deep_merge", &[base.clone(), overrides], &span()).unwrap().unwrap();
+        assert_eq!(field(&field(&deep, "leaf"), "w"), n(1.0));
+        assert_eq!(field(&field(&deep, "leaf"), "h"), n(9.0));
+        assert_eq!(field(&field(&base, "leaf"), "h"), n(2.0));
+
+        let err = call_global("merge", &[base, n(1.0)], &span()).unwrap().unwrap_err();
+        assert_eq!(err.message, "merge() expects two tables, got table and number");
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let approx_eq = |args: &[Value]| call_global("approx_eq", args, &span()).unwrap();
+        let n = Value::Number;
+        let v = Value::Vec3;
+        assert_eq!(approx_eq(&[n(0.1 + 0.2), n(0.3)]).unwrap(), Value::Bool(true));
+        assert_eq!(approx_eq(&[n(1.0), n(1.1)]).unwrap(), Value::Bool(false));
+        assert_eq!(approx_eq(&[n(1.0), n(1.1), n(0.2)]).unwrap(), Value::Bool(true));
+        assert_eq!(approx_eq(&[v(0.1 + 0.2, 1.0, 2.0), v(0.3, 1.0, 2.0)]).unwrap(), Value::Bool(true));
+        assert_eq!(approx_eq(&[v(0.0, 0.0, 0.0), v(0.0, 0.5, 0.0), n(0.1)]).unwrap(), Value::Bool(false));
+        assert_eq!(approx_eq(&[n(f64::INFINITY), n(f64::INFINITY)]).unwrap(), Value::Bool(true));
+        assert!(approx_eq(&[n(1.0), v(1.0, 1.0, 1.0)]).is_err());
+        assert!(approx_eq(&[n(1.0), n(1.0), n(-1.0)]).is_err());
+        assert!(approx_eq(&[n(1.0)]).is_err());
+    }
+
+    #[test]
+    fn test_is_empty_first_last() {
+        let call = |name: &str, v: Value| call_global(name, &[v], &span()).unwrap();
+        let arr = |items: Vec<Value>| Value::array(items);
+        let n = Value::Number;
+        assert_eq!(call("is_empty", arr(vec![])).unwrap(), Value::Bool(true));
+        assert_eq!(call("is_empty", arr(vec![n(1.0)])).unwrap(), Value::Bool(false));
+        assert_eq!(call("is_empty", Value::String(String::new())).unwrap(), Value::Bool(true));
+        assert_eq!(call("is_empty", Value::table(std::collections::HashMap::new())).unwrap(), Value::Bool(true));
+        assert_eq!(call("first", arr(vec![n(1.0), n(2.0), n(3.0)])).unwrap(), n(1.0));
+        assert_eq!(call("last", arr(vec![n(1.0), n(2.0), n(3.0)])).unwrap(), n(3.0));
+        assert_eq!(call("first", arr(vec![])).unwrap(), Value::Nil);
+        assert_eq!(call("last", arr(vec![])).unwrap(), Value::Nil);
+
+        let err = call("first", Value::String("abc".into())).unwrap_err();
+        assert_eq!(err.message, "first() expects an array, got string");
+        assert_eq!(call("is_empty", n(0.0)).unwrap_err().kind, crate::error::ErrorKind::Type);
+    }
+
+    #[test]
+    fn test_contains_and_index_of() {
+        let call = |name: &str, c: Value, x: Value| call_global(name, &[c, x], &span()).unwrap();
+        let n = Value::Number;
+        let s = |v: &str| Value::String(v.to_string());
+        let arr = Value::array(vec![s("oak"), n(2.0), s("pine")]);
+        assert_eq!(call("contains", arr.clone(), s("pine")).unwrap(), Value::Bool(true));
+        assert_eq!(call("contains", arr.clone(), n(3.0)).unwrap(), Value::Bool(false));
+        assert_eq!(call("index_of", arr.clone(), n(2.0)).unwrap(), n(1.0));
+        assert_eq!(call("index_of", arr, s("ash")).unwrap(), n(-1.0));
+
+        assert_eq!(call("contains", s("evergreen"), s("green")).unwrap(), Value::Bool(true));
+        assert_eq!(call("index_of", s("évergreen"), s("green")).unwrap(), n(4.0));
+        assert_eq!(call("index_of", s("oak"), s("x")).unwrap(), n(-1.0));
+        assert!(call("contains", s("oak"), n(1.0)).is_err());
+        assert!(call("index_of", n(1.0), n(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let reverse = |v: Value| call_global("reverse", &[v], &span()).unwrap();
+        let n = Value::Number;
+        let s = |v: &str| Value::String(v.to_string());
+        match reverse(Value::Array { items: vec![n(1.0), n(2.0), n(3.0)], frozen: true }).unwrap() {
+            Value::Array { items: arr, frozen } => {
+                assert_eq!(arr, vec![n(3.0), n(2.0), n(1.0)]);
+                assert!(!frozen);
+            }
+            other => panic!("expected array, got {:?}", other),
+        }
+        assert_eq!(reverse(s("abc")).unwrap(), s("cba"));
+        assert_eq!(reverse(s("añ€😀")).unwrap(), s("😀€ña"));
+        assert_eq!(reverse(s("")).unwrap(), s(""));
+        assert_eq!(reverse(n(1.0)).unwrap_err().message, "reverse() expects an array or string, got number");
+    }
+
+    #[test]
+    fn test_trim_and_split_lines() {
+        let call = |name: &str, v: &str| call_global(name, &[Value::String(v.to_string())], &span()).unwrap();
+        let s = |v: &str| Value::String(v.to_string());
+        assert_eq!(call("trim", "  oak \t\n").unwrap(), s("oak"));
+        assert_eq!(call("trim_start", "  oak  ").unwrap(), s("oak  "));
+        assert_eq!(call("trim_end", "  oak  ").unwrap(), s("  oak"));
+        assert_eq!(call("trim", "   ").unwrap(), s(""));
+
+        let lines = call("split_lines", "a\r\nb\n\nc\n").unwrap();
+        assert_eq!(lines.as_array().unwrap(), &[s("a"), s("b"), s(""), s("c")]);
+        assert!(call("split_lines", "").unwrap().as_array().unwrap().is_empty());
+        let err = call_global("trim", &[Value::Nil], &span()).unwrap().unwrap_err();
+        assert_eq!(err.message, "trim() expects a string, got nil");
+    }
+
+    #[test]
+    fn test_starts_with_ends_with() {
+        let call = |name: &str, a: &str, b: &str| {
+            call_global(name, &[Value::String(a.into()), Value::String(b.into())], &span()).unwrap().unwrap()
+        };
+        assert_eq!(call("starts_with", "tree_oak", "tree_"), Value::Bool(true));
+        assert_eq!(call("starts_with", "tree_oak", "oak"), Value::Bool(false));
+        assert_eq!(call("ends_with", "tree_oak", "_oak"), Value::Bool(true));
+        assert_eq!(call("ends_with", "tree_oak", "tree"), Value::Bool(false));
+        assert_eq!(call("starts_with", "", ""), Value::Bool(true));
+        assert_eq!(call("ends_with", "oak", ""), Value::Bool(true));
+        assert_eq!(call("starts_with", "élan", "é"), Value::Bool(true));
+        assert_eq!(call("starts_with", "oak", "oak tree"), Value::Bool(false));
+        assert!(call_global("ends_with", &[Value::String("a".into()), Value::Nil], &span()).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_char_and_byte() {
+        let n = Value::Number;
+        let s = |v: &str| Value::String(v.to_string());
+        let char_of = |code: f64| call_global("char", &[n(code)], &span()).unwrap();
+        let byte = |text: &str, i: f64| call_global("byte", &[s(text), n(i)], &span()).unwrap().unwrap();
+        assert_eq!(char_of(65.0).unwrap(), s("A"));
+        assert_eq!(char_of(0x1F600 as f64).unwrap(), s("😀"));
+        assert!(char_of(0xD800 as f64).is_err());
+        assert!(char_of(0x110000 as f64).is_err());
+        assert!(char_of(-1.0).is_err());
+        assert!(char_of(65.5).is_err());
+
+        assert_eq!(byte("A", 0.0), n(65.0));
+        assert_eq!(byte("añ", 1.0), n(241.0));
+        assert_eq!(byte("A", 1.0), Value::Nil);
+        assert_eq!(byte("A", -1.0), Value::Nil);
+
+        let to_bytes = |v: Value| call_global("to_bytes", &[v], &span()).unwrap();
+        let from_bytes = |v: Value| call_global("from_bytes", &[v], &span()).unwrap();
+        assert_eq!(to_bytes(s("añ")).unwrap(), Value::Bytes(vec![b'a', 0xC3, 0xB1]));
+        assert_eq!(from_bytes(Value::Bytes(vec![b'a', 0xC3, 0xB1])).unwrap(), s("añ"));
+        let err = from_bytes(Value::Bytes(vec![b'a', 0xFF])).unwrap_err();
+        assert_eq!(err.message, "from_bytes() got invalid UTF-8 at byte 1");
+        assert!(to_bytes(n(1.0)).is_err());
+        assert_eq!(byte("", 0.0), Value::Nil);
+    }
+
+    #[test]
+    fn test_sorted_and_sorted_keys() {
+        let n = Value::Number;
+        let s = |v: &str| Value::String(v.to_string());
+        let table: std::collections::HashMap<String, Value> =
+            [("c", 3.0), ("a", 1.0), ("b", 2.0)].iter().map(|(k, v)| (k.to_string(), n(*v))).collect();
+        let keys = call_global("sorted_keys", &[Value::table(table)], &span()).unwrap().unwrap();
+        assert_eq!(keys.as_array().unwrap(), &[s("a"), s("b"), s("c")]);
+
+        let sorted = |items: Vec<Value>| call_global("sorted", &[Value::array(items)], &span()).unwrap();
+        assert_eq!(sorted(vec![n(3.0), n(-1.0), n(2.0)]).unwrap().as_array().unwrap(), &[n(-1.0), n(2.0), n(3.0)]);
+        assert_eq!(sorted(vec![s("pine"), s("ash")]).unwrap().as_array().unwrap(), &[s("ash"), s("pine")]);
+        assert!(sorted(vec![n(1.0), s("a")]).is_err());
+        assert!(call_global("sorted_keys", &[n(1.0)], &span()).unwrap().is_err());
+    }
+}
@@ -1,33 +1,131 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::types::Value;
 
-#[derive(Debug)]
+/// Most emptied scopes kept around for reuse by `push_scope`.
+const MAX_POOLED_SCOPES: usize = 64;
+
+#[derive(Debug, Clone)]
 pub struct Environment {
     scopes: Vec<HashMap<String, Value>>,
+    /// Names declared `const` in the matching entry of `scopes`.
+    consts: Vec<HashSet<String>>,
+    /// Popped scopes, cleared but keeping their allocations, so a loop body
+    /// doesn't allocate a fresh map on every pass.
+    pool: Vec<(HashMap<String, Value>, HashSet<String>)>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
             scopes: vec![HashMap::new()], // global scope
+            consts: vec![HashSet::new()],
+            pool: Vec::new(),
         }
     }
 
     pub fn push_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+        let (scope, consts) = self.pool.pop().unwrap_or_default();
+        self.scopes.push(scope);
+        self.consts.push(consts);
     }
 
     pub fn pop_scope(&mut self) {
         if self.scopes.len() > 1 {
-            self.scopes.pop();
+            let mut scope = self.scopes.pop().unwrap();
+            let mut consts = self.consts.pop().unwrap();
+            if self.pool.len() < MAX_POOLED_SCOPES {
+                scope.clear();
+                consts.clear();
+                self.pool.push((scope, consts));
+            }
         }
     }
 
-    /// Define a new variable in the current (innermost) scope.
-    pub fn define(&mut self, name: &str, value: Value) {
+    /// Drop every variable and nested scope, keeping the global map's allocation.
+    pub fn clear(&mut self) {
+        self.scopes.truncate(1);
+        self.scopes[0].clear();
+        self.consts.truncate(1);
+        self.consts[0].clear();
+    }
+
+    /// Define a new variable in the current (innermost) scope. Returns false
+    /// (and does nothing) if `name` is a const declared in this same scope;
+    /// shadowing a const from an outer scope is fine.
+    pub fn define(&mut self, name: &str, value: Value) -> bool {
+        if self.consts.last().is_some_and(|consts| consts.contains(name)) {
+            return false;
+        }
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(name.to_string(), value);
         }
+        true
+    }
+
+    /// Define every `(name, value)` pair in the current scope, reserving room
+    /// for them up front. Like `define`, returns false if any name is a const
+    /// of this scope, in which case none of them are defined.
+    pub fn define_all(&mut self, vars: impl IntoIterator<Item = (String, Value)>) -> bool {
+        let (Some(scope), Some(consts)) = (self.scopes.last_mut(), self.consts.last_mut()) else {
+            return true;
+        };
+        if consts.is_empty() {
+            let vars = vars.into_iter();
+            scope.reserve(vars.size_hint().0);
+            scope.extend(vars);
+            return true;
+        }
+        let vars: Vec<(String, Value)> = vars.into_iter().collect();
+        if vars.iter().any(|(name, _)| consts.contains(name)) {
+            return false;
+        }
+        scope.extend(vars);
+        true
+    }
+
+    /// Overwrite `name` in the current scope if it's already defined there,
+    /// like `define` but without allocating a new key. Returns false (and
+    /// does nothing) if the current scope has no such variable.
+    pub fn redefine_local(&mut self, name: &str, value: Value) -> bool {
+        let Some(slot) = self.scopes.last_mut().and_then(|scope| scope.get_mut(name)) else {
+            return false;
+        };
+        *slot = value;
+        if let Some(consts) = self.consts.last_mut() {
+            if !consts.is_empty() {
+                consts.remove(name);
+            }
+        }
+        true
+    }
+
+    /// Define a constant in the current scope. `set` still works on it;
+    /// callers are expected to check `is_const` first. Returns false, like
+    /// `define`, if the scope already has a const of that name.
+    pub fn define_const(&mut self, name: &str, value: Value) -> bool {
+        if !self.define(name, value) {
+            return false;
+        }
+        if let Some(consts) = self.consts.last_mut() {
+            consts.insert(name.to_string());
+        }
+        true
+    }
+
+    /// Whether the binding `name` resolves to was declared `const`.
+    pub fn is_const(&self, name: &str) -> bool {
+        for (scope, consts) in self.scopes.iter().zip(&self.consts).rev() {
+            if scope.contains_key(name) {
+                return consts.contains(name);
+            }
+        }
+        false
     }
 
     /// Set an existing variable, walking up the scope chain.
@@ -42,6 +140,11 @@ impl Environment {
         false
     }
 
+    /// Every scope from the global one (first) to the innermost (last).
+    pub fn scopes(&self) -> &[HashMap<String, Value>] {
+        &self.scopes
+    }
+
     /// Get a variable's value, walking up the scope chain.
     pub fn get(&self, name: &str) -> Option<&Value> {
         for scope in self.scopes.iter().rev() {
@@ -102,4 +205,74 @@ mod tests {
         env.pop_scope();
         assert_eq!(env.get("x"), Some(&Value::Number(1.0)));
     }
+
+    #[test]
+    fn test_clear() {
+        let mut env = Environment::new();
+        env.define("x", Value::Number(1.0));
+        env.push_scope();
+        env.define("y", Value::Number(2.0));
+        env.clear();
+        assert_eq!(env.get("x"), None);
+        assert_eq!(env.get("y"), None);
+        env.define("z", Value::Number(3.0));
+        assert_eq!(env.get("z"), Some(&Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_const_bindings() {
+        let mut env = Environment::new();
+        env.define_const("X", Value::Number(1.0));
+        assert!(env.is_const("X"));
+        env.push_scope();
+        env.define("X", Value::Number(2.0));
+        assert!(!env.is_const("X"));
+        env.pop_scope();
+        assert!(env.is_const("X"));
+        assert!(!env.is_const("missing"));
+
+        // Redeclaring in the const's own scope is refused and changes nothing
+        assert!(!env.define("X", Value::Number(3.0)));
+        assert!(!env.define_const("X", Value::Number(3.0)));
+        assert!(!env.define_all(vec![("y".to_string(), Value::Nil), ("X".to_string(), Value::Nil)]));
+        assert_eq!(env.get("X"), Some(&Value::Number(1.0)));
+        assert_eq!(env.get("y"), None);
+        assert!(env.is_const("X"));
+    }
+
+    #[test]
+    fn test_popped_scopes_are_reused() {
+        let mut env = Environment::new();
+        env.define("x", Value::Number(1.0));
+        for i in 0..100 {
+            env.push_scope();
+            let scope = env.scopes().last().unwrap();
+            assert!(scope.is_empty());
+            if i > 0 {
+                // The map from the previous pass comes back with its allocation
+                assert!(scope.capacity() > 0);
+            }
+            env.define("x", Value::Number(i as f64));
+            env.define_const("k", Value::Nil);
+            assert_eq!(env.get("x"), Some(&Value::Number(i as f64)));
+            env.pop_scope();
+            assert_eq!(env.get("x"), Some(&Value::Number(1.0)));
+            assert!(!env.is_const("k"));
+        }
+        assert_eq!(env.pool.len(), 1);
+    }
+
+    #[test]
+    fn test_redefine_local() {
+        let mut env = Environment::new();
+        env.define("i", Value::Number(0.0));
+        env.push_scope();
+        assert!(!env.redefine_local("i", Value::Number(1.0)));
+        env.define_const("i", Value::Number(1.0));
+        assert!(env.redefine_local("i", Value::Number(2.0)));
+        assert_eq!(env.get("i"), Some(&Value::Number(2.0)));
+        assert!(!env.is_const("i"));
+        env.pop_scope();
+        assert_eq!(env.get("i"), Some(&Value::Number(0.0)));
+    }
 }
@@ -1,12 +1,14 @@
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ErrorKind {
     Syntax,
     Runtime,
     Type,
     NameError,
     InstructionLimit,
+    /// A single loop ran more iterations than allowed.
+    LoopLimit,
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +44,15 @@ impl GroveError {
             column,
         }
     }
+
+    pub fn loop_limit(limit: u64, line: usize, column: usize) -> Self {
+        Self {
+            kind: ErrorKind::LoopLimit,
+            message: format!("loop iteration limit exceeded ({} iterations)", limit),
+            line,
+            column,
+        }
+    }
 }
 
 impl fmt::Display for GroveError {
@@ -1,14 +1,94 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::ast::*;
+use crate::blob;
+use crate::builtins;
 use crate::environment::Environment;
-use crate::error::{GroveError, GroveResult};
-use crate::types::Value;
+use crate::error::{ErrorKind, GroveError, GroveResult};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::rng::{self, Rng};
+use crate::types::{Closure, Function, ObjectNamer, Value};
 
 /// Callback type for host-registered functions.
 /// Takes args and returns a Value or error string.
 pub type HostFn = Box<dyn Fn(&[Value]) -> Result<Value, String>>;
 
+/// Host callbacks as stored, so that forks of an interpreter share them.
+type SharedHostFn = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/// Debugger callback invoked before every statement executes.
+pub type TraceHook = Box<dyn FnMut(&Span, &Stmt)>;
+
+/// Host callback told which limit stopped a script, and where.
+pub type LimitHook = Box<dyn FnMut(ErrorKind, &Span)>;
+
+/// Host callback mapping a module name passed to `import` to its source, or
+/// `None` if there is no such module.
+pub type ImportResolver = Box<dyn FnMut(&str) -> Option<String>>;
+
+/// Severity of a diagnostic message from `info`, `warn` or `error_log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info = 0,
+    Warn = 1,
+    Error = 2,
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/// Host callback receiving leveled diagnostic messages.
+pub type LogHook = Box<dyn FnMut(LogLevel, &str)>;
+
+/// Name of the discard variable. Writes to it are dropped and reads are errors.
+const DISCARD: &str = "_";
+
+/// Deepest array/table nesting a variable may hold. Values are copied on
+/// assignment, so `t.self = t` nests a snapshot rather than forming a real
+/// cycle; repeating it would still grow without bound, so it's cut off here.
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// Stable merge sort with a fallible `less`, stopping at its first error. It
+/// makes no assumptions about `less`, so inconsistent answers can't make it
+/// panic or lose elements the way `slice::sort_by` may.
+fn merge_sort(
+    mut items: Vec<Value>,
+    less: &mut dyn FnMut(&Value, &Value) -> GroveResult<bool>,
+) -> GroveResult<Vec<Value>> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+    let right = merge_sort(items.split_off(items.len() / 2), less)?;
+    let left = merge_sort(items, less)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = if less(r, l)? { right.next() } else { left.next() };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// The globals and blueprints of a script context that isn't running right
+/// now: the main script's while a module's function runs, and each imported
+/// module's otherwise.
+#[derive(Default, Clone)]
+struct ModuleScope {
+    env: Environment,
+    blueprints: HashMap<String, (Vec<String>, Vec<Stmt>)>,
+}
+
 /// Control flow signals that propagate up through the call stack.
 enum ControlFlow {
     Return(Value),
@@ -18,11 +98,82 @@ enum ControlFlow {
 
 pub struct Interpreter {
     pub env: Environment,
-    host_fns: HashMap<String, HostFn>,
+    host_fns: HashMap<String, SharedHostFn>,
+    /// Global tables the host fills with functions, see `register_namespace`.
+    host_namespaces: Vec<String>,
+    /// Host methods callable on any `Value::Object`, keyed by method name.
+    object_methods: HashMap<String, SharedHostFn>,
+    /// Host callbacks for `obj.field` reads, called with `(obj, field)`, and
+    /// `obj.field = value` writes, called with `(obj, field, value)`.
+    object_getter: Option<SharedHostFn>,
+    object_setter: Option<SharedHostFn>,
+    /// Host-provided labels for objects in `tostring` and `..`.
+    object_namer: Option<Rc<ObjectNamer>>,
+    trace_hook: Option<TraceHook>,
+    /// Receives `info`/`warn`/`error_log` messages; without it they go to
+    /// `output` tagged with their level.
+    log_hook: Option<LogHook>,
+    limit_hook: Option<LimitHook>,
+    import_resolver: Option<ImportResolver>,
+    /// Values returned by modules already imported, by name.
+    modules: HashMap<String, Value>,
+    /// Modules being imported, outermost first, to catch circular imports.
+    importing: Vec<String>,
+    /// The module whose globals and blueprints are in `env` and
+    /// `blueprints`, or `None` for the main script.
+    current_module: Option<Rc<str>>,
+    /// The globals and blueprints of every other context, so a closure runs
+    /// against those of the module that made it.
+    parked_scopes: HashMap<Option<Rc<str>>, ModuleScope>,
+    /// Index of the next top-level statement for `run_n_statements`.
+    step_pos: usize,
+    /// When set, the scope chain at the point of a runtime error is kept in
+    /// `error_scopes` until the next run.
+    capture_error_scopes: bool,
+    error_scopes: Option<Vec<HashMap<String, String>>>,
     blueprints: HashMap<String, (Vec<String>, Vec<Stmt>)>,
     instruction_count: u64,
     instruction_limit: u64,
+    /// Most iterations any single loop may run (0 = unlimited).
+    max_loop_iterations: u64,
+    /// Most bytes of global data, by `Value::approx_size`, a script may
+    /// leave behind (0 = unlimited).
+    memory_budget: usize,
+    /// Value of the most recently executed expression statement, which a
+    /// blueprint ending in a bare expression returns.
+    last_expr_value: Value,
+    /// Strict mode: reject what the lenient default lets through, such as
+    /// `..` on values other than strings and numbers or out-of-bounds reads.
+    strict: bool,
+    /// Whether scripts may run source strings with `eval` and `compile`.
+    allow_eval: bool,
+    /// Whether `lexer` accepts `# comment` lines.
+    hash_comments: bool,
+    /// Source size limits for every lex, including `eval`, `compile` and
+    /// `import`, see `set_source_limits`.
+    max_source_chars: usize,
+    max_tokens: usize,
+    /// Seed for `rng`, kept so `reset` can restart the sequence.
+    seed: u64,
+    /// Source for `random_choice`, `shuffle` and other random builtins.
+    rng: Rng,
     pub output: Vec<String>,
+    /// `(kind, data)` pairs queued by `emit`, in order, for the host to drain
+    /// after the script has run.
+    pub commands: Vec<(String, Value)>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Same as `Interpreter::fork`: host functions are shared, not duplicated.
+impl Clone for Interpreter {
+    fn clone(&self) -> Self {
+        self.fork()
+    }
 }
 
 impl Interpreter {
@@ -30,10 +181,37 @@ impl Interpreter {
         Self {
             env: Environment::new(),
             host_fns: HashMap::new(),
+            host_namespaces: Vec::new(),
+            object_methods: HashMap::new(),
+            object_getter: None,
+            object_setter: None,
+            object_namer: None,
+            trace_hook: None,
+            log_hook: None,
+            limit_hook: None,
+            import_resolver: None,
+            modules: HashMap::new(),
+            importing: Vec::new(),
+            current_module: None,
+            parked_scopes: HashMap::new(),
+            step_pos: 0,
+            capture_error_scopes: false,
+            error_scopes: None,
             blueprints: HashMap::new(),
             instruction_count: 0,
             instruction_limit: 1_000_000,
+            max_loop_iterations: 0,
+            memory_budget: 0,
+            last_expr_value: Value::Nil,
+            strict: false,
+            allow_eval: false,
+            hash_comments: false,
+            max_source_chars: usize::MAX,
+            max_tokens: usize::MAX,
+            seed: 0,
+            rng: Rng::new(0),
             output: Vec::new(),
+            commands: Vec::new(),
         }
     }
 
@@ -41,20 +219,358 @@ impl Interpreter {
         self.instruction_limit = limit;
     }
 
+    /// Cap the iterations of each individual loop (0 = unlimited, the
+    /// default). Unlike the instruction limit this catches a single runaway
+    /// loop with an error pointing at it.
+    pub fn set_max_loop_iterations(&mut self, limit: u64) {
+        self.max_loop_iterations = limit;
+    }
+
+    /// Cap the global data a script may leave behind, in bytes as estimated
+    /// by `Value::approx_size` (0 = unlimited, the default). Checked after
+    /// each `execute`, which fails at the last statement run if the globals
+    /// are over budget, discarding any top-level return value; the globals
+    /// are left in place for the host to inspect or `reset`.
+    pub fn set_memory_budget(&mut self, bytes: usize) {
+        self.memory_budget = bytes;
+    }
+
+    /// Estimated bytes held by global variables, see `set_memory_budget`.
+    pub fn globals_size(&self) -> usize {
+        self.env.scopes().first().map_or(0, |globals| {
+            globals.iter().map(|(name, v)| name.len() + v.approx_size()).sum()
+        })
+    }
+
+    /// Error at `span`, the last statement run, if the globals are over the
+    /// memory budget.
+    fn check_memory_budget(&self, span: &Span) -> GroveResult<()> {
+        let size = self.globals_size();
+        if self.memory_budget != 0 && size > self.memory_budget {
+            return Err(GroveError::runtime(
+                format!("global data exceeds memory budget ({} bytes, limit {})", size, self.memory_budget),
+                span.line, span.column,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Turn strict mode on or off (off by default). In strict mode `..` only
+    /// joins strings and numbers, as in Lua, instead of stringifying anything,
+    /// and reading past the end of an array is an error rather than nil, as is
+    /// calling a blueprint with the wrong number of arguments.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Let scripts run source strings with `eval` and `compile` (off by
+    /// default). Evaluated code shares globals, blueprints and the instruction
+    /// budget with the script calling it, so only enable this for trusted
+    /// scripts.
+    pub fn set_allow_eval(&mut self, allow: bool) {
+        self.allow_eval = allow;
+    }
+
+    /// Reject sources longer than `max_chars` characters or lexing to more
+    /// than `max_tokens` tokens (both unlimited by default). Applies to
+    /// source run with `eval`, `compile` and `import` as well as to
+    /// `lexer`, so scripts can't get around it.
+    pub fn set_source_limits(&mut self, max_chars: usize, max_tokens: usize) {
+        self.max_source_chars = max_chars;
+        self.max_tokens = max_tokens;
+    }
+
+    /// Accept shell-style `# comment` lines in every source this
+    /// interpreter lexes (off by default), see `Lexer::allow_hash_comments`.
+    pub fn set_hash_comments(&mut self, allow: bool) {
+        self.hash_comments = allow;
+    }
+
+    /// A lexer for `source` with this interpreter's source limits,
+    /// strictness and comment style.
+    pub fn lexer<'a>(&self, source: &'a str) -> Lexer<'a> {
+        Lexer::with_limits(source, self.max_source_chars, self.max_tokens)
+            .strict(self.strict)
+            .allow_hash_comments(self.hash_comments)
+    }
+
+    /// Seed the random builtins, restarting their sequence. The same seed
+    /// gives the same results for the same script.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    /// Define blueprint `name` as if a script had run
+    /// `blueprint name(params) body end`, replacing any existing one.
+    pub fn define_blueprint(&mut self, name: &str, params: Vec<String>, body: Vec<Stmt>) {
+        self.blueprints.insert(name.to_string(), (params, body));
+    }
+
+    /// Blueprint `name` serialized for `load_blueprint_blob`, or `None` if
+    /// it isn't defined.
+    pub fn blueprint_blob(&self, name: &str) -> Option<Vec<u8>> {
+        let (params, body) = self.blueprints.get(name)?;
+        Some(blob::encode_blueprint(name, params, body))
+    }
+
+    /// Define a blueprint from a blob made by `blueprint_blob`, returning its
+    /// name.
+    pub fn load_blueprint_blob(&mut self, bytes: &[u8]) -> GroveResult<String> {
+        let (name, params, body) = blob::decode_blueprint(bytes)?;
+        self.define_blueprint(&name, params, body);
+        Ok(name)
+    }
+
     pub fn register_fn(&mut self, name: &str, func: HostFn) {
-        self.host_fns.insert(name.to_string(), func);
+        self.host_fns.insert(name.to_string(), Rc::from(func));
+    }
+
+    /// Create a global table `name` for the host to fill with functions via
+    /// `register_namespaced_fn`, so that scripts call them as `name.fn(...)`.
+    pub fn register_namespace(&mut self, name: &str) {
+        if !self.host_namespaces.iter().any(|ns| ns == name) {
+            self.host_namespaces.push(name.to_string());
+        }
+        if !matches!(self.env.get(name), Some(Value::Table { .. })) {
+            self.env.define(name, Value::table(HashMap::new()));
+        }
+    }
+
+    /// Add `func` to the namespace table `ns` as `ns.name`. Returns false if
+    /// `ns` hasn't been registered with `register_namespace`.
+    pub fn register_namespaced_fn(&mut self, ns: &str, name: &str, func: HostFn) -> bool {
+        if !self.host_namespaces.iter().any(|n| n == ns) {
+            return false;
+        }
+        let full_name = format!("{}.{}", ns, name);
+        self.host_fns.insert(full_name.clone(), Rc::from(func));
+        let mut table = match self.env.get(ns) {
+            Some(Value::Table { fields: map, .. }) => map.clone(),
+            _ => HashMap::new(),
+        };
+        table.insert(name.to_string(), Value::Function(Function::Host(full_name)));
+        self.env.define(ns, Value::table(table));
+        true
     }
 
-    pub fn set_global(&mut self, name: &str, value: Value) {
-        self.env.define(name, value);
+    /// Recreate the namespace tables from the registered host functions.
+    fn define_host_namespaces(&mut self) {
+        for ns in &self.host_namespaces {
+            let prefix = format!("{}.", ns);
+            let table = self
+                .host_fns
+                .keys()
+                .filter_map(|full| {
+                    let name = full.strip_prefix(&prefix)?;
+                    Some((name.to_string(), Value::Function(Function::Host(full.clone()))))
+                })
+                .collect();
+            self.env.define(ns, Value::table(table));
+        }
+    }
+
+    /// Register a method callable as `obj:name(args)` on object handles. The
+    /// function receives the object itself as its first argument.
+    pub fn register_object_method(&mut self, name: &str, func: HostFn) {
+        self.object_methods.insert(name.to_string(), Rc::from(func));
+    }
+
+    /// Route field reads and writes on object handles to the host. Without a
+    /// getter or setter the corresponding access is an error.
+    pub fn set_object_accessor(&mut self, getter: Option<HostFn>, setter: Option<HostFn>) {
+        self.object_getter = getter.map(Rc::from);
+        self.object_setter = setter.map(Rc::from);
+    }
+
+    /// Label objects when scripts turn them into strings: `<Tree:42>` where
+    /// `namer` returns `Some("Tree")` instead of `<object:42>`.
+    pub fn set_object_namer(&mut self, namer: Box<ObjectNamer>) {
+        self.object_namer = Some(Rc::from(namer));
+    }
+
+    /// Call `hook` with each statement (and its span) just before it runs.
+    /// The hook only observes; it can't change what executes.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    /// Call `hook` when a script is stopped for exceeding the instruction
+    /// limit (`ErrorKind::InstructionLimit`) or the loop iteration cap
+    /// (`ErrorKind::LoopLimit`), with where it was stopped, before the error
+    /// propagates.
+    pub fn set_limit_hook(&mut self, hook: LimitHook) {
+        self.limit_hook = Some(hook);
+    }
+
+    pub fn clear_limit_hook(&mut self) {
+        self.limit_hook = None;
+    }
+
+    /// Let scripts `import(name)` modules, whose source `resolver` supplies.
+    /// Each module runs once, in globals of its own, and what it returns
+    /// with a top-level `return` is cached and handed to every importer.
+    pub fn set_import_resolver(&mut self, resolver: ImportResolver) {
+        self.import_resolver = Some(resolver);
+    }
+
+    pub fn clear_import_resolver(&mut self) {
+        self.import_resolver = None;
+    }
+
+    /// Send `info`, `warn` and `error_log` messages to `hook` instead of
+    /// `output`, keeping diagnostics apart from data written with `print`.
+    pub fn set_log_hook(&mut self, hook: LogHook) {
+        self.log_hook = Some(hook);
+    }
+
+    pub fn clear_log_hook(&mut self) {
+        self.log_hook = None;
+    }
+
+    /// Variables in scope right now, from the global scope (first) to the
+    /// innermost (last), with values rendered via `Display`.
+    pub fn dump_scopes(&self) -> Vec<HashMap<String, String>> {
+        self.env
+            .scopes()
+            .iter()
+            .map(|scope| scope.iter().map(|(k, v)| (k.clone(), self.display(v))).collect())
+            .collect()
+    }
+
+    /// Record `dump_scopes()` at the failing statement whenever a run errors.
+    pub fn set_capture_error_scopes(&mut self, enabled: bool) {
+        self.capture_error_scopes = enabled;
+    }
+
+    /// Scopes captured by the last failed run, if capturing was enabled.
+    pub fn error_scopes(&self) -> Option<&[HashMap<String, String>]> {
+        self.error_scopes.as_deref()
+    }
+
+    /// Clear all script state (globals, blueprints, imported modules, output,
+    /// commands, the instruction count and the random sequence, which
+    /// restarts from the seed) so the interpreter can be reused from a pool.
+    /// Host functions, object callbacks, the trace, log and limit hooks and
+    /// the import resolver are dropped too unless `keep_host_fns` is set, in
+    /// which case host namespace tables are recreated.
+    pub fn reset(&mut self, keep_host_fns: bool) {
+        self.env.clear();
+        self.blueprints.clear();
+        self.modules.clear();
+        self.current_module = None;
+        self.parked_scopes.clear();
+        self.output.clear();
+        self.commands.clear();
+        self.instruction_count = 0;
+        self.step_pos = 0;
+        self.error_scopes = None;
+        self.rng = Rng::new(self.seed);
+        if keep_host_fns {
+            self.define_host_namespaces();
+        } else {
+            self.host_fns.clear();
+            self.host_namespaces.clear();
+            self.object_methods.clear();
+            self.object_getter = None;
+            self.object_setter = None;
+            self.object_namer = None;
+            self.trace_hook = None;
+            self.log_hook = None;
+            self.limit_hook = None;
+            self.import_resolver = None;
+        }
+    }
+
+    /// A copy of this interpreter's script state (globals, blueprints, limits
+    /// and output) for speculative evaluation: run scripts against the fork and
+    /// keep it or drop it, the original is untouched either way. Host
+    /// functions and object callbacks are shared with the original rather than
+    /// copied; the trace, log and limit hooks and the import resolver can't be
+    /// shared and are not carried over.
+    pub fn fork(&self) -> Self {
+        Self {
+            env: self.env.clone(),
+            host_fns: self.host_fns.clone(),
+            host_namespaces: self.host_namespaces.clone(),
+            object_methods: self.object_methods.clone(),
+            object_getter: self.object_getter.clone(),
+            object_setter: self.object_setter.clone(),
+            object_namer: self.object_namer.clone(),
+            trace_hook: None,
+            log_hook: None,
+            limit_hook: None,
+            import_resolver: None,
+            modules: self.modules.clone(),
+            importing: Vec::new(),
+            current_module: self.current_module.clone(),
+            parked_scopes: self.parked_scopes.clone(),
+            step_pos: self.step_pos,
+            capture_error_scopes: self.capture_error_scopes,
+            error_scopes: self.error_scopes.clone(),
+            blueprints: self.blueprints.clone(),
+            instruction_count: self.instruction_count,
+            instruction_limit: self.instruction_limit,
+            max_loop_iterations: self.max_loop_iterations,
+            memory_budget: self.memory_budget,
+            last_expr_value: Value::Nil,
+            strict: self.strict,
+            allow_eval: self.allow_eval,
+            hash_comments: self.hash_comments,
+            max_source_chars: self.max_source_chars,
+            max_tokens: self.max_tokens,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            output: self.output.clone(),
+            commands: self.commands.clone(),
+        }
+    }
+
+    /// Call a script-defined blueprint by name with already-evaluated
+    /// arguments, so embedders can treat a script as a module of functions.
+    /// Fails with a `NameError` if no such blueprint has been defined.
+    pub fn call(&mut self, name: &str, args: &[Value]) -> GroveResult<Value> {
+        let (params, body) = self.blueprints.get(name).cloned().ok_or_else(|| {
+            GroveError::name_error(format!("undefined blueprint '{}'", name), 0, 0)
+        })?;
+        self.instruction_count = 0;
+        self.call_blueprint(name, &params, args, &body, &Span { line: 0, column: 0 })
+    }
+
+    /// Define global `name`. Returns false (and does nothing) if a script
+    /// has declared `name` const.
+    pub fn set_global(&mut self, name: &str, value: Value) -> bool {
+        self.env.define(name, value)
+    }
+
+    /// `set_global` for many values at once, e.g. injecting a large config.
+    /// If any name is a const, nothing is set and false is returned.
+    pub fn set_globals(&mut self, globals: impl IntoIterator<Item = (String, Value)>) -> bool {
+        self.env.define_all(globals)
     }
 
+    /// Run `program` to completion. A top-level `return expr` stops it and
+    /// becomes the result, so a module script can hand its exports to the
+    /// host (`return {version = 1}`); otherwise the result is nil.
     pub fn execute(&mut self, program: &Program) -> GroveResult<Value> {
         self.instruction_count = 0;
+        self.step_pos = 0;
+        self.error_scopes = None;
         let mut last = Value::Nil;
         for stmt in &program.statements {
             match self.exec_stmt(stmt)? {
-                Some(ControlFlow::Return(v)) => return Ok(v),
+                Some(ControlFlow::Return(v)) => {
+                    self.check_memory_budget(stmt.span())?;
+                    return Ok(v);
+                }
                 Some(ControlFlow::Break) | Some(ControlFlow::Continue) => {
                     return Err(GroveError::runtime(
                         "break/continue outside of loop",
@@ -67,19 +583,85 @@ impl Interpreter {
             }
         }
         let _ = last;
+        if let Some(stmt) = program.statements.last() {
+            self.check_memory_budget(stmt.span())?;
+        }
         Ok(Value::Nil)
     }
 
+    /// Execute up to `n` more top-level statements of `program`, picking up
+    /// where the previous call stopped. Returns `Ok(true)` once the program
+    /// has finished (the next call starts it over) and `Ok(false)` if there
+    /// is more to run. A statement always runs to completion, loops and
+    /// blueprint calls included, so this steps over rather than into them.
+    pub fn run_n_statements(&mut self, program: &Program, n: usize) -> GroveResult<bool> {
+        if self.step_pos == 0 {
+            self.instruction_count = 0;
+            self.error_scopes = None;
+        }
+        let end = self.step_pos.saturating_add(n).min(program.statements.len());
+        while self.step_pos < end {
+            let stmt = &program.statements[self.step_pos];
+            self.step_pos += 1;
+            let flow = self.exec_stmt(stmt).inspect_err(|_| self.step_pos = 0)?;
+            match flow {
+                Some(ControlFlow::Return(_)) => {
+                    self.step_pos = 0;
+                    return Ok(true);
+                }
+                Some(ControlFlow::Break) | Some(ControlFlow::Continue) => {
+                    self.step_pos = 0;
+                    return Err(GroveError::runtime("break/continue outside of loop", 0, 0));
+                }
+                None => {}
+            }
+        }
+        if self.step_pos >= program.statements.len() {
+            self.step_pos = 0;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
     fn tick(&mut self, line: usize, col: usize) -> GroveResult<()> {
         self.instruction_count += 1;
         if self.instruction_count > self.instruction_limit {
-            Err(GroveError::instruction_limit(line, col))
+            Err(self.limit_hit(GroveError::instruction_limit(line, col)))
         } else {
             Ok(())
         }
     }
 
+    /// Error once a loop starts iteration number `iterations` past the cap.
+    fn check_loop_iterations(&mut self, iterations: u64, span: &Span) -> GroveResult<()> {
+        if self.max_loop_iterations != 0 && iterations > self.max_loop_iterations {
+            let err = GroveError::loop_limit(self.max_loop_iterations, span.line, span.column);
+            return Err(self.limit_hit(err));
+        }
+        Ok(())
+    }
+
+    /// Report a limit error to the limit hook on its way out.
+    fn limit_hit(&mut self, err: GroveError) -> GroveError {
+        if let Some(hook) = self.limit_hook.as_mut() {
+            hook(err.kind, &Span { line: err.line, column: err.column });
+        }
+        err
+    }
+
     fn exec_stmt(&mut self, stmt: &Stmt) -> GroveResult<Option<ControlFlow>> {
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(stmt.span(), stmt);
+        }
+        let result = self.dispatch_stmt(stmt);
+        // The innermost failing statement sees the scopes before they unwind.
+        if result.is_err() && self.capture_error_scopes && self.error_scopes.is_none() {
+            self.error_scopes = Some(self.dump_scopes());
+        }
+        result
+    }
+
+    fn dispatch_stmt(&mut self, stmt: &Stmt) -> GroveResult<Option<ControlFlow>> {
         match stmt {
             Stmt::LocalDecl { name, init, span } => {
                 self.tick(span.line, span.column)?;
@@ -87,15 +669,39 @@ impl Interpreter {
                     Some(expr) => self.eval_expr(expr)?,
                     None => Value::Nil,
                 };
-                self.env.define(name, val);
+                check_nesting(name, val.nesting_depth(), span)?;
+                self.define_var(name, val, span)?;
+                Ok(None)
+            }
+
+            Stmt::ConstDecl { name, init, span } => {
+                self.tick(span.line, span.column)?;
+                let val = self.eval_expr(init)?;
+                check_nesting(name, val.nesting_depth(), span)?;
+                if name != DISCARD && !self.env.define_const(name, val) {
+                    return Err(GroveError::runtime(
+                        format!("cannot redeclare const '{}'", name),
+                        span.line, span.column,
+                    ));
+                }
                 Ok(None)
             }
 
             Stmt::Assign { target, value, span } => {
                 self.tick(span.line, span.column)?;
                 let val = self.eval_expr(value)?;
+                if let Some((name, steps)) = place_root(target) {
+                    check_nesting(name, steps + val.nesting_depth(), target.span())?;
+                }
                 match target {
+                    Expr::Ident { name, .. } if name == DISCARD => {}
                     Expr::Ident { name, span: s } => {
+                        if self.env.is_const(name) {
+                            return Err(GroveError::runtime(
+                                format!("cannot assign to const '{}'", name),
+                                s.line, s.column,
+                            ));
+                        }
                         if !self.env.set(name, val) {
                             return Err(GroveError::name_error(
                                 format!("undefined variable '{}'", name),
@@ -105,7 +711,11 @@ impl Interpreter {
                     }
                     Expr::FieldAccess { object, field, span: s } => {
                         let mut obj = self.eval_expr(object)?;
-                        if let Value::Table(ref mut map) = obj {
+                        check_not_frozen(&obj, s)?;
+                        if let Value::Object(_) = obj {
+                            // Host objects own their state; nothing to write back.
+                            self.set_object_field(obj, field, val, s)?;
+                        } else if let Value::Table { fields: ref mut map, .. } = obj {
                             map.insert(field.clone(), val);
                             // We need to write back — re-evaluate the base and set
                             // For now, table field assignment on local tables works
@@ -121,8 +731,9 @@ impl Interpreter {
                     Expr::IndexAccess { object, index, span: s } => {
                         let idx = self.eval_expr(index)?;
                         let mut obj = self.eval_expr(object)?;
+                        check_not_frozen(&obj, s)?;
                         match (&mut obj, &idx) {
-                            (Value::Array(arr), Value::Number(n)) => {
+                            (Value::Array { items: arr, .. }, Value::Number(n)) => {
                                 let i = *n as usize;
                                 if i < arr.len() {
                                     arr[i] = val;
@@ -134,10 +745,14 @@ impl Interpreter {
                                     ));
                                 }
                             }
-                            (Value::Table(map), Value::String(key)) => {
+                            (Value::Table { fields: map, .. }, Value::String(key)) => {
                                 map.insert(key.clone(), val);
                                 self.set_value_at(object, obj)?;
                             }
+                            (Value::Vec3(..), Value::Number(n)) => {
+                                set_vec3_component(&mut obj, *n, val, s)?;
+                                self.set_value_at(object, obj)?;
+                            }
                             _ => {
                                 return Err(GroveError::type_error(
                                     format!("cannot index {} with {}", obj.type_name(), idx.type_name()),
@@ -158,7 +773,7 @@ impl Interpreter {
 
             Stmt::ExprStmt { expr, span } => {
                 self.tick(span.line, span.column)?;
-                self.eval_expr(expr)?;
+                self.last_expr_value = self.eval_expr(expr)?;
                 Ok(None)
             }
 
@@ -182,9 +797,12 @@ impl Interpreter {
 
             Stmt::While { condition, body, span } => {
                 self.tick(span.line, span.column)?;
+                let mut iterations = 0;
                 loop {
                     let cond = self.eval_expr(condition)?;
                     if !cond.is_truthy() { break; }
+                    iterations += 1;
+                    self.check_loop_iterations(iterations, span)?;
                     match self.exec_block(body)? {
                         Some(ControlFlow::Break) => break,
                         Some(ControlFlow::Continue) => continue,
@@ -217,14 +835,23 @@ impl Interpreter {
 
                 self.env.push_scope();
                 let mut i = start_val;
+                let mut iterations = 0;
                 loop {
                     if step_val > 0.0 && i > limit_val { break; }
                     if step_val < 0.0 && i < limit_val { break; }
+                    iterations += 1;
+                    self.check_loop_iterations(iterations, span)?;
 
-                    self.env.define(var, Value::Number(i));
+                    // After the first pass the variable already exists in the
+                    // loop scope, so update it in place rather than re-inserting.
+                    if !self.env.redefine_local(var, Value::Number(i)) {
+                        self.define_var(var, Value::Number(i), span)?;
+                    }
                     self.tick(span.line, span.column)?;
 
-                    match self.exec_block_no_scope(body)? {
+                    // Each pass gets a fresh body scope, as `while` bodies do,
+                    // so its locals and consts start over.
+                    match self.exec_block(body)? {
                         Some(ControlFlow::Break) => break,
                         Some(ControlFlow::Continue) => {}
                         Some(cf @ ControlFlow::Return(_)) => {
@@ -239,17 +866,72 @@ impl Interpreter {
                 Ok(None)
             }
 
-            Stmt::GenericFor { vars: _, iter: _, body: _, span } => {
-                // Stub for M1 — generic for requires iterators
-                Err(GroveError::runtime(
-                    "generic for not yet implemented",
-                    span.line, span.column,
-                ))
+            Stmt::GenericFor { vars, iter, body, span } => {
+                self.tick(span.line, span.column)?;
+                if vars.len() > 2 {
+                    return Err(GroveError::runtime(
+                        format!("generic for takes at most 2 variables, got {}", vars.len()),
+                        span.line, span.column,
+                    ));
+                }
+                // Iterate over a snapshot; the body can't disturb the order.
+                // Tables go in sorted key order so runs are reproducible.
+                let (entries, is_array): (Vec<(Value, Value)>, bool) = match self.eval_expr(iter)? {
+                    Value::Array { items: arr, .. } => {
+                        let entries = arr.into_iter().enumerate().map(|(i, v)| (Value::Number(i as f64), v));
+                        (entries.collect(), true)
+                    }
+                    Value::Table { fields: map, .. } => {
+                        let mut fields: Vec<(String, Value)> = map.into_iter().collect();
+                        fields.sort_by(|a, b| a.0.cmp(&b.0));
+                        (fields.into_iter().map(|(k, v)| (Value::String(k), v)).collect(), false)
+                    }
+                    other => {
+                        return Err(GroveError::type_error(
+                            format!("cannot iterate over a {} value", other.type_name()),
+                            span.line, span.column,
+                        ));
+                    }
+                };
+
+                self.env.push_scope();
+                let mut iterations = 0;
+                for (key, value) in entries {
+                    iterations += 1;
+                    self.check_loop_iterations(iterations, span)?;
+                    // `for v in arr` binds elements; `for k in t` binds keys.
+                    let bindings = match (vars.len(), is_array) {
+                        (1, true) => vec![(&vars[0], value)],
+                        (1, false) => vec![(&vars[0], key)],
+                        _ => vec![(&vars[0], key), (&vars[1], value)],
+                    };
+                    for (var, val) in bindings {
+                        if !self.env.redefine_local(var, val.clone()) {
+                            self.define_var(var, val, span)?;
+                        }
+                    }
+                    self.tick(span.line, span.column)?;
+
+                    match self.exec_block(body)? {
+                        Some(ControlFlow::Break) => break,
+                        Some(ControlFlow::Continue) => {}
+                        Some(cf @ ControlFlow::Return(_)) => {
+                            self.env.pop_scope();
+                            return Ok(Some(cf));
+                        }
+                        None => {}
+                    }
+                }
+                self.env.pop_scope();
+                Ok(None)
             }
 
             Stmt::RepeatUntil { body, condition, span } => {
                 self.tick(span.line, span.column)?;
+                let mut iterations = 0;
                 loop {
+                    iterations += 1;
+                    self.check_loop_iterations(iterations, span)?;
                     match self.exec_block(body)? {
                         Some(ControlFlow::Break) => break,
                         Some(ControlFlow::Continue) => {}
@@ -271,19 +953,7 @@ impl Interpreter {
 
             Stmt::Build { name, args, span } => {
                 self.tick(span.line, span.column)?;
-                let (params, body) = self.blueprints.get(name).cloned().ok_or_else(|| {
-                    GroveError::name_error(
-                        format!("undefined blueprint '{}'", name),
-                        span.line, span.column,
-                    )
-                })?;
-
-                let mut arg_vals = Vec::new();
-                for arg in args {
-                    arg_vals.push(self.eval_expr(arg)?);
-                }
-
-                self.call_blueprint(&params, &arg_vals, &body, span)?;
+                self.build(name, args, span)?;
                 Ok(None)
             }
 
@@ -305,6 +975,11 @@ impl Interpreter {
                 self.tick(span.line, span.column)?;
                 Ok(Some(ControlFlow::Continue))
             }
+
+            Stmt::Pass { span } => {
+                self.tick(span.line, span.column)?;
+                Ok(None)
+            }
         }
     }
 
@@ -324,15 +999,41 @@ impl Interpreter {
         Ok(None)
     }
 
-    fn call_blueprint(&mut self, params: &[String], args: &[Value], body: &[Stmt], _span: &Span) -> GroveResult<Value> {
+    fn call_blueprint(
+        &mut self,
+        name: &str,
+        params: &[String],
+        args: &[Value],
+        body: &[Stmt],
+        span: &Span,
+    ) -> GroveResult<Value> {
+        // Outside strict mode missing arguments are nil and extras are dropped.
+        if self.strict && args.len() != params.len() {
+            return Err(GroveError::type_error(
+                format!(
+                    "blueprint '{}' expects {} argument{}, got {}",
+                    name,
+                    params.len(),
+                    if params.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span.line, span.column,
+            ));
+        }
         self.env.push_scope();
         for (i, param) in params.iter().enumerate() {
             let val = args.get(i).cloned().unwrap_or(Value::Nil);
-            self.env.define(param, val);
+            self.define_var(param, val, span)?;
         }
 
+        // Without a `return`, a body ending in a bare expression returns its
+        // value: `blueprint double(x) x * 2 end`. A trailing call is run for
+        // its effect and still returns nil, as bodies always have.
         let result = match self.exec_block_no_scope(body)? {
             Some(ControlFlow::Return(v)) => v,
+            None if matches!(body.last(), Some(Stmt::ExprStmt { expr, .. }) if !is_call(expr)) => {
+                std::mem::replace(&mut self.last_expr_value, Value::Nil)
+            }
             _ => Value::Nil,
         };
 
@@ -340,19 +1041,79 @@ impl Interpreter {
         Ok(result)
     }
 
+    /// Define a variable in the current scope, dropping writes to the discard
+    /// `_`. Redeclaring a const of the same scope is an error.
+    fn define_var(&mut self, name: &str, value: Value, span: &Span) -> GroveResult<()> {
+        if name != DISCARD && !self.env.define(name, value) {
+            return Err(GroveError::runtime(
+                format!("cannot redeclare const '{}'", name),
+                span.line, span.column,
+            ));
+        }
+        Ok(())
+    }
+
     /// Helper to write back a value to the variable that an expression refers to.
+    /// Nested places (`a.b[i] = x`) are rebuilt from the inside out. Writing
+    /// back into anything rooted at a `const` binding is an error.
     fn set_value_at(&mut self, expr: &Expr, value: Value) -> GroveResult<()> {
-        if let Expr::Ident { name, span } = expr {
-            if !self.env.set(name, value) {
-                return Err(GroveError::name_error(
-                    format!("undefined variable '{}'", name),
-                    span.line, span.column,
-                ));
+        match expr {
+            Expr::Ident { name, span } => {
+                if self.env.is_const(name) {
+                    return Err(GroveError::runtime(
+                        format!("cannot modify const '{}'", name),
+                        span.line, span.column,
+                    ));
+                }
+                if !self.env.set(name, value) {
+                    return Err(GroveError::name_error(
+                        format!("undefined variable '{}'", name),
+                        span.line, span.column,
+                    ));
+                }
+                Ok(())
+            }
+            Expr::FieldAccess { object, field, span } => {
+                let mut parent = self.eval_expr(object)?;
+                check_not_frozen(&parent, span)?;
+                if let Value::Object(_) = parent {
+                    self.set_object_field(parent, field, value, span)
+                } else if let Value::Table { fields: ref mut map, .. } = parent {
+                    map.insert(field.clone(), value);
+                    self.set_value_at(object, parent)
+                } else {
+                    Err(GroveError::type_error(
+                        format!("cannot set field '{}' on {}", field, parent.type_name()),
+                        span.line, span.column,
+                    ))
+                }
+            }
+            Expr::IndexAccess { object, index, span } => {
+                let idx = self.eval_expr(index)?;
+                let mut parent = self.eval_expr(object)?;
+                check_not_frozen(&parent, span)?;
+                match (&mut parent, &idx) {
+                    (Value::Array { items: arr, .. }, Value::Number(n)) if (*n as usize) < arr.len() => {
+                        arr[*n as usize] = value;
+                    }
+                    (Value::Table { fields: map, .. }, Value::String(key)) => {
+                        map.insert(key.clone(), value);
+                    }
+                    (Value::Vec3(..), Value::Number(n)) => {
+                        set_vec3_component(&mut parent, *n, value, span)?;
+                    }
+                    _ => {
+                        return Err(GroveError::type_error(
+                            format!("cannot index {} with {}", parent.type_name(), idx.type_name()),
+                            span.line, span.column,
+                        ));
+                    }
+                }
+                self.set_value_at(object, parent)
             }
+            // Temporaries (call results, literals) have nowhere to write back to.
+            _ => Ok(()),
         }
-        // For nested access (e.g., a.b.c = x), a full implementation would
-        // recursively walk. For M1, single-level works.
-        Ok(())
     }
 
     // ── Expression evaluation ───────────────────────────
@@ -364,13 +1125,37 @@ impl Interpreter {
             Expr::BoolLit { value, .. } => Ok(Value::Bool(*value)),
             Expr::NilLit { .. } => Ok(Value::Nil),
 
+            Expr::Ident { name, span } if name == DISCARD => Err(GroveError::name_error(
+                "cannot read from discard variable '_'",
+                span.line, span.column,
+            )),
+
             Expr::Ident { name, span } => {
-                self.env.get(name).cloned().ok_or_else(|| {
-                    GroveError::name_error(
-                        format!("undefined variable '{}'", name),
-                        span.line, span.column,
-                    )
-                })
+                if let Some(val) = self.env.get(name) {
+                    return Ok(val.clone());
+                }
+                // Blueprints and host functions are values too, so they can
+                // be passed around: `partial(add, 5)`.
+                if let Some((params, body)) = self.blueprints.get(name) {
+                    // By name a module's blueprint would be looked up among
+                    // the caller's, so it leaves the module as a closure
+                    if self.current_module.is_some() {
+                        return Ok(Value::Function(Function::Closure(Rc::new(Closure {
+                            params: params.clone(),
+                            body: body.clone(),
+                            captured: Vec::new(),
+                            module: self.current_module.clone(),
+                        }))));
+                    }
+                    return Ok(Value::Function(Function::Blueprint(name.clone())));
+                }
+                if self.host_fns.contains_key(name) {
+                    return Ok(Value::Function(Function::Host(name.clone())));
+                }
+                Err(GroveError::name_error(
+                    format!("undefined variable '{}'", name),
+                    span.line, span.column,
+                ))
             }
 
             Expr::BinaryOp { left, op, right, span } => {
@@ -411,10 +1196,18 @@ impl Interpreter {
                     UnaryOp::Len => {
                         match &val {
                             Value::String(s) => Ok(Value::Number(s.len() as f64)),
-                            Value::Array(arr) => Ok(Value::Number(arr.len() as f64)),
-                            Value::Table(map) => Ok(Value::Number(map.len() as f64)),
+                            Value::Bytes(b) => Ok(Value::Number(b.len() as f64)),
+                            Value::Array { items: arr, .. } => Ok(Value::Number(arr.len() as f64)),
+                            Value::Table { fields: map, .. } => Ok(Value::Number(map.len() as f64)),
+                            Value::Vec3(..) => Err(GroveError::type_error(
+                                "cannot get length of vec3; use v:length() for its magnitude",
+                                span.line, span.column,
+                            )),
                             _ => Err(GroveError::type_error(
-                                format!("cannot get length of {}", val.type_name()),
+                                format!(
+                                    "cannot get length of {}; '#' only applies to strings, bytes, arrays and tables",
+                                    val.type_name()
+                                ),
                                 span.line, span.column,
                             )),
                         }
@@ -423,39 +1216,123 @@ impl Interpreter {
             }
 
             Expr::Call { callee, args, span } => {
-                // Evaluate arguments
-                let mut arg_vals = Vec::new();
-                for arg in args {
-                    arg_vals.push(self.eval_expr(arg)?);
-                }
-
-                // Check for built-in vec3 constructor
-                if let Expr::Ident { name, .. } = callee.as_ref() {
-                    if name == "vec3" {
-                        return self.builtin_vec3(&arg_vals, span);
-                    }
-                    // Check host functions
-                    if let Some(func) = self.host_fns.get(name) {
-                        // We need to call the host function. Since it's behind a shared ref
-                        // and we have &mut self, we need to temporarily extract it.
-                        // Use a raw pointer trick to avoid borrow issues.
-                        let func_ptr = func as *const HostFn;
-                        let result = unsafe { (*func_ptr)(&arg_vals) };
-                        return result.map_err(|msg| {
-                            GroveError::runtime(msg, span.line, span.column)
-                        });
-                    }
-                    // Check blueprints (callable as functions)
-                    if let Some((params, body)) = self.blueprints.get(name).cloned() {
-                        return self.call_blueprint(&params, &arg_vals, &body, span);
-                    }
+                if args.iter().any(|arg| matches!(arg, Expr::KeywordArg { .. })) {
+                    return self.call_with_keywords(callee, args, span);
                 }
+                let arg_vals = self.eval_args(args)?;
 
-                Err(GroveError::name_error(
-                    format!("undefined function '{}'", self.expr_name(callee)),
-                    span.line, span.column,
-                ))
-            }
+                // `math.fn(...)` and other built-in namespaces, unless a script
+                // variable of the same name shadows them.
+                if let Expr::FieldAccess { object, field, .. } = callee.as_ref() {
+                    if let Some(ns) = self.builtin_namespace(object) {
+                        return builtins::call_namespaced(ns, field, &arg_vals, span);
+                    }
+                }
+
+                match callee.as_ref() {
+                    // Name resolution for `name(args)`, first match wins:
+                    //   1. variables (locals, then globals); only function values
+                    //      are callable
+                    //   2. blueprints defined by the script
+                    //   3. the built-in `vec3` constructor
+                    //   4. host functions
+                    //   5. built-in functions (`tostring`, `format`, ...)
+                    // so a script overrides a host function or `vec3` by defining a
+                    // blueprint with the same name, and a host can replace any
+                    // builtin except `vec3`, which engine code relies on.
+                    Expr::Ident { name, .. } => {
+                        if let Some(val) = self.env.get(name) {
+                            if let Value::Function(func) = val {
+                                let func = func.clone();
+                                return self.call_function(&func, &arg_vals, span);
+                            }
+                            return Err(GroveError::type_error(
+                                format!("attempt to call a {} value (variable '{}')", val.type_name(), name),
+                                span.line, span.column,
+                            ));
+                        }
+                        // Check blueprints (callable as functions)
+                        if let Some((params, body)) = self.blueprints.get(name).cloned() {
+                            return self.call_blueprint(name, &params, &arg_vals, &body, span);
+                        }
+                        if name == "vec3" {
+                            return self.builtin_vec3(&arg_vals, span);
+                        }
+                        if name == "color" {
+                            return self.builtin_color(&arg_vals, span);
+                        }
+                        // Check host functions
+                        if let Some(func) = self.host_fns.get(name).cloned() {
+                            return func(&arg_vals).map_err(|msg| {
+                                GroveError::runtime(msg, span.line, span.column)
+                            });
+                        }
+                        if name == "freeze" {
+                            return self.builtin_freeze(args, arg_vals, span);
+                        }
+                        if name == "tostring" {
+                            builtins::expect_args("tostring", &arg_vals, 1, span)?;
+                            return Ok(Value::String(self.display(&arg_vals[0])));
+                        }
+                        if name == "print" || name == "log" {
+                            let line: Vec<String> = arg_vals.iter().map(|v| self.display(v)).collect();
+                            self.output.push(line.join(" "));
+                            return Ok(Value::Nil);
+                        }
+                        if let Some(level) = log_level(name) {
+                            let line: Vec<String> = arg_vals.iter().map(|v| self.display(v)).collect();
+                            self.log(level, &line.join(" "));
+                            return Ok(Value::Nil);
+                        }
+                        if name == "random_choice" || name == "shuffle" {
+                            return self.builtin_random(name, &arg_vals, span);
+                        }
+                        if name == "sort" {
+                            return self.builtin_sort(&arg_vals, span);
+                        }
+                        if name == "min_by" || name == "max_by" {
+                            return self.builtin_extreme_by(name, &arg_vals, span);
+                        }
+                        if name == "group_by" {
+                            return self.builtin_group_by(&arg_vals, span);
+                        }
+                        if name == "noise" || name == "noise3" {
+                            return self.builtin_noise(name, &arg_vals, span);
+                        }
+                        if name == "eval" {
+                            return self.builtin_eval(&arg_vals, span);
+                        }
+                        if name == "import" {
+                            return self.builtin_import(&arg_vals, span);
+                        }
+                        if name == "compile" {
+                            return self.builtin_compile(&arg_vals, span);
+                        }
+                        if name == "emit" {
+                            return self.builtin_emit(&arg_vals, span);
+                        }
+                        if name == "globals" {
+                            builtins::expect_args("globals", &arg_vals, 0, span)?;
+                            return Ok(self.globals_snapshot());
+                        }
+                        if let Some(result) = builtins::call_global(name, &arg_vals, span) {
+                            return result;
+                        }
+                        Err(GroveError::name_error(
+                            format!("undefined function '{}'", name),
+                            span.line, span.column,
+                        ))
+                    }
+                    // `ns.fn(...)`, `fns[i](...)` and other callee expressions
+                    _ => match self.eval_expr(callee)? {
+                        Value::Function(func) => self.call_function(&func, &arg_vals, span),
+                        val => Err(GroveError::type_error(
+                            format!("attempt to call a {} value", val.type_name()),
+                            span.line, span.column,
+                        )),
+                    },
+                }
+            }
 
             Expr::FieldAccess { object, field, span } => {
                 let obj = self.eval_expr(object)?;
@@ -471,9 +1348,27 @@ impl Interpreter {
                             )),
                         }
                     }
-                    Value::Table(map) => {
+                    Value::Color(r, g, b, a) => {
+                        match field.as_str() {
+                            "r" => Ok(Value::Number(*r)),
+                            "g" => Ok(Value::Number(*g)),
+                            "b" => Ok(Value::Number(*b)),
+                            "a" => Ok(Value::Number(*a)),
+                            _ => Err(GroveError::runtime(
+                                format!("color has no field '{}'", field),
+                                span.line, span.column,
+                            )),
+                        }
+                    }
+                    Value::Table { fields: map, .. } => {
                         Ok(map.get(field).cloned().unwrap_or(Value::Nil))
                     }
+                    Value::Object(_) if self.object_getter.is_some() => {
+                        let getter = self.object_getter.as_ref().unwrap();
+                        getter(&[obj.clone(), Value::String(field.clone())]).map_err(|msg| {
+                            GroveError::runtime(msg, span.line, span.column)
+                        })
+                    }
                     _ => Err(GroveError::type_error(
                         format!("cannot access field '{}' on {}", field, obj.type_name()),
                         span.line, span.column,
@@ -485,13 +1380,27 @@ impl Interpreter {
                 let obj = self.eval_expr(object)?;
                 let idx = self.eval_expr(index)?;
                 match (&obj, &idx) {
-                    (Value::Array(arr), Value::Number(n)) => {
+                    (Value::Array { items: arr, .. }, Value::Number(n)) => {
+                        let in_bounds = *n >= 0.0 && (*n as usize) < arr.len();
+                        if self.strict && !in_bounds {
+                            return Err(GroveError::runtime(
+                                format!("array index {} out of bounds (length {})", n, arr.len()),
+                                span.line, span.column,
+                            ));
+                        }
                         let i = *n as usize;
                         Ok(arr.get(i).cloned().unwrap_or(Value::Nil))
                     }
-                    (Value::Table(map), Value::String(key)) => {
+                    (Value::Table { fields: map, .. }, Value::String(key)) => {
                         Ok(map.get(key).cloned().unwrap_or(Value::Nil))
                     }
+                    (Value::Vec3(x, y, z), Value::Number(n)) => {
+                        Ok(Value::Number([*x, *y, *z][vec3_axis(*n, span)?]))
+                    }
+                    (Value::Bytes(b), Value::Number(n)) => {
+                        let byte = if *n >= 0.0 { b.get(*n as usize) } else { None };
+                        Ok(byte.map_or(Value::Nil, |&byte| Value::Number(byte as f64)))
+                    }
                     (Value::String(s), Value::Number(n)) => {
                         let i = *n as usize;
                         Ok(s.chars().nth(i)
@@ -507,15 +1416,62 @@ impl Interpreter {
 
             Expr::MethodCall { object, method, args, span } => {
                 let obj = self.eval_expr(object)?;
-                let mut arg_vals = Vec::new();
-                for arg in args {
-                    arg_vals.push(self.eval_expr(arg)?);
+                let arg_vals = self.eval_args(args)?;
+                match (&obj, method.as_str()) {
+                    (Value::Vec3(x, y, z), "length") if arg_vals.is_empty() => {
+                        Ok(Value::Number((x * x + y * y + z * z).sqrt()))
+                    }
+                    // Mutating array methods write the updated array back to `object`.
+                    (Value::Array { items: arr, .. }, "push") => {
+                        check_not_frozen(&obj, span)?;
+                        if let Some((name, steps)) = place_root(object) {
+                            let depth = arg_vals.iter().map(Value::nesting_depth).max().unwrap_or(0);
+                            check_nesting(name, steps + 1 + depth, span)?;
+                        }
+                        let mut arr = arr.clone();
+                        arr.extend(arg_vals);
+                        self.set_value_at(object, Value::array(arr))?;
+                        Ok(Value::Nil)
+                    }
+                    (Value::Array { items: arr, .. }, "pop") if arg_vals.is_empty() => {
+                        check_not_frozen(&obj, span)?;
+                        let mut arr = arr.clone();
+                        let last = arr.pop().unwrap_or(Value::Nil);
+                        self.set_value_at(object, Value::array(arr))?;
+                        Ok(last)
+                    }
+                    // Built-in methods on numbers and strings: `(-5):abs()`, `"hi":len()`.
+                    (Value::Number(n), "abs" | "neg" | "floor" | "ceil" | "round") if arg_vals.is_empty() => {
+                        Ok(Value::Number(match method.as_str() {
+                            "abs" => n.abs(),
+                            "neg" => -n,
+                            "floor" => n.floor(),
+                            "ceil" => n.ceil(),
+                            _ => n.round(),
+                        }))
+                    }
+                    (Value::String(s), "len") if arg_vals.is_empty() => Ok(Value::Number(s.len() as f64)),
+                    // Methods on host objects are dispatched to the host, handle first.
+                    (Value::Object(_), _) => {
+                        let func = self.object_methods.get(method).ok_or_else(|| {
+                            GroveError::runtime(
+                                format!("object has no method '{}'", method),
+                                span.line, span.column,
+                            )
+                        })?;
+                        let mut call_args = Vec::with_capacity(arg_vals.len() + 1);
+                        call_args.push(obj.clone());
+                        call_args.extend(arg_vals);
+                        func(&call_args).map_err(|msg| {
+                            GroveError::runtime(msg, span.line, span.column)
+                        })
+                    }
+                    // For M1, other method calls are not implemented
+                    _ => Err(GroveError::runtime(
+                        format!("method call '{}' on {} not yet implemented", method, obj.type_name()),
+                        span.line, span.column,
+                    )),
                 }
-                // For M1, method calls are not fully implemented
-                Err(GroveError::runtime(
-                    format!("method call '{}' on {} not yet implemented", method, obj.type_name()),
-                    span.line, span.column,
-                ))
             }
 
             Expr::ArrayLit { elements, .. } => {
@@ -523,7 +1479,7 @@ impl Interpreter {
                 for elem in elements {
                     arr.push(self.eval_expr(elem)?);
                 }
-                Ok(Value::Array(arr))
+                Ok(Value::array(arr))
             }
 
             Expr::TableLit { fields, .. } => {
@@ -532,9 +1488,148 @@ impl Interpreter {
                     let val = self.eval_expr(val_expr)?;
                     map.insert(key.clone(), val);
                 }
-                Ok(Value::Table(map))
+                Ok(Value::table(map))
+            }
+
+            Expr::Build { name, args, span } => self.build(name, args, span),
+
+            Expr::FunctionLit { params, body, .. } => {
+                // Copy the locals in scope, innermost first so shadowing
+                // wins; globals are still looked up when the closure runs.
+                let mut captured: Vec<(String, Value)> = Vec::new();
+                for scope in self.env.scopes()[1..].iter().rev() {
+                    for (name, value) in scope {
+                        if !captured.iter().any(|(n, _)| n == name) {
+                            captured.push((name.clone(), value.clone()));
+                        }
+                    }
+                }
+                Ok(Value::Function(Function::Closure(Rc::new(Closure {
+                    params: params.clone(),
+                    body: body.clone(),
+                    captured,
+                    module: self.current_module.clone(),
+                }))))
+            }
+
+            Expr::Spread { span, .. } => Err(GroveError::syntax(
+                "'...' is only allowed in call arguments",
+                span.line, span.column,
+            )),
+            Expr::KeywordArg { span, .. } => Err(GroveError::syntax(
+                "keyword arguments are only allowed in call arguments",
+                span.line, span.column,
+            )),
+        }
+    }
+
+    /// `build name(args)`: call blueprint `name` whatever else shares its name.
+    fn build(&mut self, name: &str, args: &[Expr], span: &Span) -> GroveResult<Value> {
+        let (params, body) = self.blueprints.get(name).cloned().ok_or_else(|| {
+            GroveError::name_error(
+                format!("undefined blueprint '{}'", name),
+                span.line, span.column,
+            )
+        })?;
+        let arg_vals = self.bind_args(name, &params, args)?;
+        self.call_blueprint(name, &params, &arg_vals, &body, span)
+    }
+
+    /// Evaluate call arguments, expanding `...arr` into the array's elements.
+    fn eval_args(&mut self, args: &[Expr]) -> GroveResult<Vec<Value>> {
+        let mut vals = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg {
+                Expr::Spread { expr, span } => match self.eval_expr(expr)? {
+                    Value::Array { items: elements, .. } => vals.extend(elements),
+                    other => {
+                        return Err(GroveError::type_error(
+                            format!("cannot spread {} into arguments, expected an array", other.type_name()),
+                            span.line, span.column,
+                        ));
+                    }
+                },
+                Expr::KeywordArg { name, span, .. } => {
+                    return Err(GroveError::type_error(
+                        format!("keyword argument '{}' can only be passed to a blueprint", name),
+                        span.line, span.column,
+                    ));
+                }
+                _ => vals.push(self.eval_expr(arg)?),
             }
         }
+        Ok(vals)
+    }
+
+    /// Evaluate the arguments of a call to blueprint `name`, placing keyword
+    /// arguments at their parameter's position. Parameters left unbound are nil.
+    fn bind_args(&mut self, name: &str, params: &[String], args: &[Expr]) -> GroveResult<Vec<Value>> {
+        let split = args
+            .iter()
+            .position(|arg| matches!(arg, Expr::KeywordArg { .. }))
+            .unwrap_or(args.len());
+        let (positional, keywords) = args.split_at(split);
+        let mut vals = self.eval_args(positional)?;
+        if keywords.is_empty() {
+            return Ok(vals);
+        }
+        let mut bound = vec![true; vals.len()];
+        if vals.len() < params.len() {
+            vals.resize(params.len(), Value::Nil);
+            bound.resize(params.len(), false);
+        }
+        for arg in keywords {
+            let Expr::KeywordArg { name: param, value, span } = arg else { unreachable!() };
+            let idx = params.iter().position(|p| p == param).ok_or_else(|| {
+                GroveError::type_error(
+                    format!("blueprint '{}' has no parameter '{}'", name, param),
+                    span.line, span.column,
+                )
+            })?;
+            if bound[idx] {
+                return Err(GroveError::type_error(
+                    format!("argument '{}' given more than once", param),
+                    span.line, span.column,
+                ));
+            }
+            vals[idx] = self.eval_expr(value)?;
+            bound[idx] = true;
+        }
+        if self.strict {
+            if let Some(i) = bound.iter().position(|&b| !b) {
+                let span = args[0].span();
+                return Err(GroveError::type_error(
+                    format!("blueprint '{}' is missing argument '{}'", name, params[i]),
+                    span.line, span.column,
+                ));
+            }
+        }
+        Ok(vals)
+    }
+
+    /// A call with keyword arguments, which only blueprints accept since host
+    /// functions have no parameter names.
+    fn call_with_keywords(&mut self, callee: &Expr, args: &[Expr], span: &Span) -> GroveResult<Value> {
+        let name = match self.eval_expr(callee)? {
+            Value::Function(Function::Blueprint(name)) => name,
+            Value::Function(func) => {
+                return Err(GroveError::type_error(
+                    format!("keyword arguments can only be passed to a blueprint, not '{}'", func.name()),
+                    span.line, span.column,
+                ));
+            }
+            val => {
+                return Err(GroveError::type_error(
+                    format!("attempt to call a {} value", val.type_name()),
+                    span.line, span.column,
+                ));
+            }
+        };
+        let (params, body) = self.blueprints.get(&name).cloned().ok_or_else(|| {
+            GroveError::name_error(format!("undefined blueprint '{}'", name), span.line, span.column)
+        })?;
+        let arg_vals = self.bind_args(&name, &params, args)?;
+        self.call_blueprint(&name, &params, &arg_vals, &body, span)
     }
 
     fn eval_binary_op(&self, op: &BinOp, left: &Value, right: &Value, span: &Span) -> GroveResult<Value> {
@@ -556,8 +1651,18 @@ impl Interpreter {
 
             // String concatenation
             BinOp::Concat => {
-                let l = format!("{}", left);
-                let r = format!("{}", right);
+                if self.strict {
+                    for operand in [left, right] {
+                        if !matches!(operand, Value::String(_) | Value::Number(_)) {
+                            return Err(GroveError::type_error(
+                                format!("cannot concatenate a {} value", operand.type_name()),
+                                span.line, span.column,
+                            ));
+                        }
+                    }
+                }
+                let l = self.display(left);
+                let r = self.display(right);
                 Ok(Value::String(format!("{}{}", l, r)))
             }
 
@@ -615,6 +1720,407 @@ impl Interpreter {
         }
     }
 
+    /// Write `obj.field = value` through the host's object setter.
+    fn set_object_field(&self, obj: Value, field: &str, value: Value, span: &Span) -> GroveResult<()> {
+        let setter = self.object_setter.as_ref().ok_or_else(|| {
+            GroveError::type_error(
+                format!("cannot set field '{}' on {}", field, obj.type_name()),
+                span.line, span.column,
+            )
+        })?;
+        setter(&[obj, Value::String(field.to_string()), value])
+            .map(|_| ())
+            .map_err(|msg| GroveError::runtime(msg, span.line, span.column))
+    }
+
+    /// Call a function value with already-evaluated arguments.
+    fn call_function(&mut self, func: &Function, args: &[Value], span: &Span) -> GroveResult<Value> {
+        match func {
+            Function::Host(name) => {
+                let host_fn = self.host_fns.get(name).ok_or_else(|| {
+                    GroveError::name_error(format!("undefined function '{}'", name), span.line, span.column)
+                })?;
+                host_fn(args).map_err(|msg| GroveError::runtime(msg, span.line, span.column))
+            }
+            Function::Blueprint(name) => {
+                let (params, body) = self.blueprints.get(name).cloned().ok_or_else(|| {
+                    GroveError::name_error(format!("undefined blueprint '{}'", name), span.line, span.column)
+                })?;
+                self.call_blueprint(name, &params, args, &body, span)
+            }
+            Function::Partial(inner, bound) => {
+                let mut all_args = bound.clone();
+                all_args.extend_from_slice(args);
+                self.call_function(inner, &all_args, span)
+            }
+            Function::Closure(closure) => {
+                let caller = self.switch_module(closure.module.clone());
+                self.env.push_scope();
+                for (name, value) in &closure.captured {
+                    self.env.define(name, value.clone());
+                }
+                let result = self.call_blueprint("fn", &closure.params, args, &closure.body, span);
+                self.env.pop_scope();
+                self.switch_module(caller);
+                result
+            }
+            Function::Compiled(statements) => {
+                if self.strict && !args.is_empty() {
+                    return Err(GroveError::type_error(
+                        format!("compiled function expects 0 arguments, got {}", args.len()),
+                        span.line, span.column,
+                    ));
+                }
+                self.run_chunk(statements)
+            }
+        }
+    }
+
+    /// `freeze(t)` — mark an array or table read-only. The frozen value is
+    /// written back to `t` when it names a variable, field or element, and is
+    /// also returned.
+    fn builtin_freeze(&mut self, args: &[Expr], arg_vals: Vec<Value>, span: &Span) -> GroveResult<Value> {
+        builtins::expect_args("freeze", &arg_vals, 1, span)?;
+        let val = arg_vals.into_iter().next().unwrap();
+        let frozen = match val {
+            Value::Array { items, frozen: false } => Value::Array { items, frozen: true },
+            Value::Table { fields, frozen: false } => Value::Table { fields, frozen: true },
+            already @ (Value::Array { frozen: true, .. } | Value::Table { frozen: true, .. }) => return Ok(already),
+            other => {
+                return Err(GroveError::type_error(
+                    format!("freeze() expects an array or table, got {}", other.type_name()),
+                    span.line, span.column,
+                ));
+            }
+        };
+        self.set_value_at(&args[0], frozen.clone())?;
+        Ok(frozen)
+    }
+
+    /// Deliver a leveled message to the log hook, or to `output` as
+    /// `[level] message` when there is none.
+    pub fn log(&mut self, level: LogLevel, message: &str) {
+        match &mut self.log_hook {
+            Some(hook) => hook(level, message),
+            None => self.output.push(format!("[{}] {}", level.as_str(), message)),
+        }
+    }
+
+    /// `random_choice(arr)` — a uniformly chosen element, nil if `arr` is
+    /// empty. `shuffle(arr)` — a shuffled copy of `arr` (Fisher-Yates).
+    fn builtin_random(&mut self, name: &str, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        builtins::expect_args(name, arg_vals, 1, span)?;
+        let Value::Array { items: arr, .. } = &arg_vals[0] else {
+            return Err(GroveError::type_error(
+                format!("{}() expects an array, got {}", name, arg_vals[0].type_name()),
+                span.line, span.column,
+            ));
+        };
+        if name == "random_choice" {
+            if arr.is_empty() {
+                return Ok(Value::Nil);
+            }
+            return Ok(arr[self.rng.below(arr.len())].clone());
+        }
+        let mut shuffled = arr.clone();
+        for i in (1..shuffled.len()).rev() {
+            let j = self.rng.below(i + 1);
+            shuffled.swap(i, j);
+        }
+        Ok(Value::array(shuffled))
+    }
+
+    /// `sort(arr, less)` — a copy of `arr` in the order given by `less(a, b)`,
+    /// which says whether `a` goes before `b`; `sorted(arr)` covers the
+    /// natural order. Elements `less` can't tell apart keep their order, and
+    /// a comparator that isn't a consistent ordering gives some permutation
+    /// of `arr` rather than an error.
+    fn builtin_sort(&mut self, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        builtins::expect_args("sort", arg_vals, 2, span)?;
+        let Value::Array { items: arr, .. } = &arg_vals[0] else {
+            return Err(GroveError::type_error(
+                format!("sort() expects an array, got {}", arg_vals[0].type_name()),
+                span.line, span.column,
+            ));
+        };
+        let Value::Function(less) = &arg_vals[1] else {
+            return Err(GroveError::type_error(
+                format!("sort() expects a comparator function, got {}", arg_vals[1].type_name()),
+                span.line, span.column,
+            ));
+        };
+        let sorted = merge_sort(arr.clone(), &mut |a, b| {
+            Ok(self.call_function(less, &[a.clone(), b.clone()], span)?.is_truthy())
+        })?;
+        Ok(Value::array(sorted))
+    }
+
+    /// `min_by(arr, key)` / `max_by(arr, key)` — the element of `arr` whose
+    /// `key(element)` number is smallest / largest, the first one on ties, or
+    /// nil for an empty array. Errors from `key` name the element they hit.
+    fn builtin_extreme_by(&mut self, name: &str, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        builtins::expect_args(name, arg_vals, 2, span)?;
+        let Value::Array { items: arr, .. } = &arg_vals[0] else {
+            return Err(GroveError::type_error(
+                format!("{}() expects an array, got {}", name, arg_vals[0].type_name()),
+                span.line, span.column,
+            ));
+        };
+        let Value::Function(key) = &arg_vals[1] else {
+            return Err(GroveError::type_error(
+                format!("{}() expects a key function, got {}", name, arg_vals[1].type_name()),
+                span.line, span.column,
+            ));
+        };
+        let mut best: Option<(f64, &Value)> = None;
+        for (i, item) in arr.iter().enumerate() {
+            let k = match self.call_function(key, std::slice::from_ref(item), span) {
+                Ok(Value::Number(k)) => k,
+                Ok(other) => {
+                    return Err(GroveError::type_error(
+                        format!("{}() key must be a number, got {} for element {}", name, other.type_name(), i),
+                        span.line, span.column,
+                    ));
+                }
+                Err(mut e) => {
+                    e.message = format!("{}() key failed on element {}: {}", name, i, e.message);
+                    return Err(e);
+                }
+            };
+            let better = match best {
+                None => true,
+                Some((b, _)) if name == "min_by" => k < b,
+                Some((b, _)) => k > b,
+            };
+            if better {
+                best = Some((k, item));
+            }
+        }
+        Ok(best.map_or(Value::Nil, |(_, item)| item.clone()))
+    }
+
+    /// `group_by(arr, key)` — a table mapping each `key(element)` to the
+    /// array of elements with that key, in their order in `arr`. Keys must
+    /// be strings; anything else is a type error rather than being converted,
+    /// so `1` and `"1"` can't silently share a group.
+    fn builtin_group_by(&mut self, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        builtins::expect_args("group_by", arg_vals, 2, span)?;
+        let Value::Array { items: arr, .. } = &arg_vals[0] else {
+            return Err(GroveError::type_error(
+                format!("group_by() expects an array, got {}", arg_vals[0].type_name()),
+                span.line, span.column,
+            ));
+        };
+        let Value::Function(key) = &arg_vals[1] else {
+            return Err(GroveError::type_error(
+                format!("group_by() expects a key function, got {}", arg_vals[1].type_name()),
+                span.line, span.column,
+            ));
+        };
+        let mut groups: HashMap<String, Vec<Value>> = HashMap::new();
+        for (i, item) in arr.iter().enumerate() {
+            match self.call_function(key, std::slice::from_ref(item), span) {
+                Ok(Value::String(k)) => groups.entry(k).or_default().push(item.clone()),
+                Ok(other) => {
+                    return Err(GroveError::type_error(
+                        format!("group_by() key must be a string, got {} for element {}", other.type_name(), i),
+                        span.line, span.column,
+                    ));
+                }
+                Err(mut e) => {
+                    e.message = format!("group_by() key failed on element {}: {}", i, e.message);
+                    return Err(e);
+                }
+            }
+        }
+        let table = groups.into_iter().map(|(k, items)| (k, Value::array(items))).collect();
+        Ok(Value::table(table))
+    }
+
+    /// `noise(x, y)` / `noise3(x, y, z)` — smooth value noise in [-1, 1] for
+    /// terrain, determined by the coordinates and the VM seed.
+    fn builtin_noise(&self, name: &str, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        let dims = if name == "noise" { 2 } else { 3 };
+        builtins::expect_args(name, arg_vals, dims, span)?;
+        let mut coords = [0.0; 3];
+        for (coord, val) in coords.iter_mut().zip(arg_vals) {
+            *coord = val.as_number().ok_or_else(|| {
+                GroveError::type_error(
+                    format!("{}() expects numbers, got {}", name, val.type_name()),
+                    span.line, span.column,
+                )
+            })?;
+        }
+        Ok(Value::Number(rng::noise3(self.seed, coords[0], coords[1], coords[2])))
+    }
+
+    /// `eval(source)` — run `source` in a scope of its own, see `run_chunk`.
+    fn builtin_eval(&mut self, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        let program = self.parse_source_arg("eval", arg_vals, span)?;
+        self.run_chunk(&program.statements)
+    }
+
+    /// Run source from `eval` or `compile` in a scope of its own. It returns
+    /// what the source returns, or else the value of its trailing expression
+    /// statement. Unlike in a blueprint body a trailing call counts too, so
+    /// `eval("add(1)")` is the sum.
+    fn run_chunk(&mut self, statements: &[Stmt]) -> GroveResult<Value> {
+        self.env.push_scope();
+        let result = self.exec_block_no_scope(statements);
+        self.env.pop_scope();
+        Ok(match result? {
+            Some(ControlFlow::Return(v)) => v,
+            None if matches!(statements.last(), Some(Stmt::ExprStmt { .. })) => {
+                std::mem::replace(&mut self.last_expr_value, Value::Nil)
+            }
+            _ => Value::Nil,
+        })
+    }
+
+    /// `compile(source)` — `source` as a function taking no arguments, which
+    /// runs it the way `eval` would each time it's called. Syntax errors are
+    /// raised by `compile` itself.
+    fn builtin_compile(&mut self, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        let program = self.parse_source_arg("compile", arg_vals, span)?;
+        Ok(Value::Function(Function::Compiled(program.statements.into())))
+    }
+
+    /// Parse the source string passed to `eval` or `compile`, if allowed.
+    /// Syntax errors are reported at the call, quoting their position in
+    /// the source.
+    fn parse_source_arg(&self, name: &str, arg_vals: &[Value], span: &Span) -> GroveResult<Program> {
+        if !self.allow_eval {
+            return Err(GroveError::runtime(format!("{}() is disabled", name), span.line, span.column));
+        }
+        builtins::expect_args(name, arg_vals, 1, span)?;
+        let source = arg_vals[0].as_string().ok_or_else(|| {
+            GroveError::type_error(
+                format!("{}() expects a string, got {}", name, arg_vals[0].type_name()),
+                span.line, span.column,
+            )
+        })?;
+        self.lexer(source)
+            .tokenize()
+            .and_then(|tokens| Parser::new(tokens).parse())
+            .map_err(|e| GroveError {
+                message: format!("in {}() at {}:{}: {}", name, e.line, e.column, e.message),
+                line: span.line,
+                column: span.column,
+                ..e
+            })
+    }
+
+    /// `import(name)` — the value module `name` returns, running it the first
+    /// time. The module can't see the importer's variables, nor the importer
+    /// the module's; errors inside it are reported at the `import` call.
+    fn builtin_import(&mut self, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        builtins::expect_args("import", arg_vals, 1, span)?;
+        let Some(name) = arg_vals[0].as_string() else {
+            return Err(GroveError::type_error(
+                format!("import() expects a module name string, got {}", arg_vals[0].type_name()),
+                span.line, span.column,
+            ));
+        };
+        if let Some(exports) = self.modules.get(name) {
+            return Ok(exports.clone());
+        }
+        if self.importing.iter().any(|m| m == name) {
+            let chain = self.importing.join(" -> ");
+            return Err(GroveError::runtime(
+                format!("circular import: {} -> {}", chain, name),
+                span.line, span.column,
+            ));
+        }
+        let Some(resolver) = self.import_resolver.as_mut() else {
+            return Err(GroveError::runtime("import() has no module resolver", span.line, span.column));
+        };
+        let Some(source) = resolver(name) else {
+            return Err(GroveError::runtime(format!("module '{}' not found", name), span.line, span.column));
+        };
+        let in_module = |e: GroveError| GroveError {
+            message: format!("in module '{}' at {}:{}: {}", name, e.line, e.column, e.message),
+            line: span.line,
+            column: span.column,
+            ..e
+        };
+        let program = self
+            .lexer(&source)
+            .tokenize()
+            .and_then(|tokens| Parser::new(tokens).parse())
+            .map_err(in_module)?;
+
+        let importer = self.switch_module(Some(Rc::from(name)));
+        self.importing.push(name.to_string());
+        let result = self.exec_block_no_scope(&program.statements);
+        self.importing.pop();
+        self.switch_module(importer);
+        if result.is_err() {
+            self.parked_scopes.remove(&Some(Rc::from(name)));
+        }
+        let exports = match result.map_err(in_module)? {
+            Some(ControlFlow::Return(v)) => v,
+            _ => Value::Nil,
+        };
+        self.modules.insert(name.to_string(), exports.clone());
+        Ok(exports)
+    }
+
+    /// Make `module`'s globals and blueprints the live ones, parking the
+    /// current module's, and return the module that was live so the caller
+    /// can switch back. A module seen for the first time starts empty.
+    fn switch_module(&mut self, module: Option<Rc<str>>) -> Option<Rc<str>> {
+        if module == self.current_module {
+            return module;
+        }
+        let scope = self.parked_scopes.remove(&module).unwrap_or_default();
+        let env = std::mem::replace(&mut self.env, scope.env);
+        let blueprints = std::mem::replace(&mut self.blueprints, scope.blueprints);
+        let previous = std::mem::replace(&mut self.current_module, module);
+        self.parked_scopes.insert(previous.clone(), ModuleScope { env, blueprints });
+        previous
+    }
+
+    /// `emit(kind, data)` — queue a command for the host instead of calling
+    /// into it, so generation can run to completion before any effects apply.
+    fn builtin_emit(&mut self, arg_vals: &[Value], span: &Span) -> GroveResult<Value> {
+        builtins::expect_args("emit", arg_vals, 2, span)?;
+        let kind = arg_vals[0].as_string().ok_or_else(|| {
+            GroveError::type_error(
+                format!("emit() expects a string kind, got {}", arg_vals[0].type_name()),
+                span.line, span.column,
+            )
+        })?;
+        self.commands.push((kind.to_string(), arg_vals[1].clone()));
+        Ok(Value::Nil)
+    }
+
+    /// `globals()` — a copy of the global scope as a table. It's a snapshot:
+    /// writing to it doesn't define or change any variable.
+    fn globals_snapshot(&self) -> Value {
+        let globals = self.env.scopes().first().cloned().unwrap_or_default();
+        Value::table(globals)
+    }
+
+    /// Format a value for script-visible strings, naming objects via the host.
+    fn display(&self, value: &Value) -> String {
+        match &self.object_namer {
+            Some(namer) => value.display_named(namer.as_ref()).to_string(),
+            None => value.to_string(),
+        }
+    }
+
+    /// The built-in namespace `expr` names, if it isn't shadowed by a variable.
+    fn builtin_namespace<'e>(&self, expr: &'e Expr) -> Option<&'e str> {
+        match expr {
+            Expr::Ident { name, .. }
+                if builtins::NAMESPACES.contains(&name.as_str()) && self.env.get(name).is_none() =>
+            {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
     fn builtin_vec3(&self, args: &[Value], span: &Span) -> GroveResult<Value> {
         if args.len() != 3 {
             return Err(GroveError::runtime(
@@ -634,24 +2140,122 @@ impl Interpreter {
         Ok(Value::Vec3(x, y, z))
     }
 
-    fn expr_name(&self, expr: &Expr) -> String {
-        match expr {
-            Expr::Ident { name, .. } => name.clone(),
-            _ => "<expression>".to_string(),
+    /// `color()` is opaque black; `color(r, g, b)` is opaque, and
+    /// `color(r, g, b, a)` sets alpha too.
+    fn builtin_color(&self, args: &[Value], span: &Span) -> GroveResult<Value> {
+        if !matches!(args.len(), 0 | 3 | 4) {
+            return Err(GroveError::runtime(
+                format!("color() expects 0, 3 or 4 arguments, got {}", args.len()),
+                span.line, span.column,
+            ));
+        }
+        let mut rgba = [0.0, 0.0, 0.0, 1.0];
+        for (i, arg) in args.iter().enumerate() {
+            rgba[i] = arg.as_number().ok_or_else(|| {
+                GroveError::type_error(
+                    format!("color {} must be a number", ["r", "g", "b", "a"][i]),
+                    span.line, span.column,
+                )
+            })?;
         }
+        let [r, g, b, a] = rgba;
+        Ok(Value::Color(r, g, b, a))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Lexer;
-    use crate::parser::Parser;
+/// The level logged by builtin `name`, if it's one of the leveled loggers.
+/// `error_log` rather than `error` so it can't be mistaken for raising one.
+fn log_level(name: &str) -> Option<LogLevel> {
+    match name {
+        "info" => Some(LogLevel::Info),
+        "warn" => Some(LogLevel::Warn),
+        "error_log" => Some(LogLevel::Error),
+        _ => None,
+    }
+}
 
-    fn run(src: &str) -> (GroveResult<Value>, Vec<String>) {
-        let mut lex = Lexer::new(src);
-        let tokens = lex.tokenize().unwrap();
-        let mut parser = Parser::new(tokens);
+/// Whether `expr` is a call, which as a statement is run for its effect.
+fn is_call(expr: &Expr) -> bool {
+    matches!(expr, Expr::Call { .. } | Expr::MethodCall { .. } | Expr::Build { .. })
+}
+
+/// The variable a place like `a.b[i]` is rooted at, and the number of field
+/// and index steps from it to the place. `None` for temporaries.
+fn place_root(expr: &Expr) -> Option<(&str, usize)> {
+    match expr {
+        Expr::Ident { name, .. } => Some((name, 0)),
+        Expr::FieldAccess { object, .. } | Expr::IndexAccess { object, .. } => {
+            place_root(object).map(|(name, steps)| (name, steps + 1))
+        }
+        _ => None,
+    }
+}
+
+/// Error if storing a value would leave variable `name` nested `depth`
+/// levels deep. Callers measure only the stored value plus its path, not
+/// the whole structure it lands in.
+fn check_nesting(name: &str, depth: usize, span: &Span) -> GroveResult<()> {
+    if depth > MAX_NESTING_DEPTH {
+        return Err(GroveError::runtime(
+            format!(
+                "cyclic structure: '{}' nests deeper than {} levels (is a table being stored inside itself?)",
+                name, MAX_NESTING_DEPTH
+            ),
+            span.line, span.column,
+        ));
+    }
+    Ok(())
+}
+
+/// Error if `value` is an array or table that has been frozen.
+fn check_not_frozen(value: &Value, span: &Span) -> GroveResult<()> {
+    if value.is_frozen() {
+        return Err(GroveError::runtime(
+            format!("cannot modify frozen {}", value.type_name()),
+            span.line, span.column,
+        ));
+    }
+    Ok(())
+}
+
+/// The component `v[n]` refers to: 0 for x, 1 for y, 2 for z.
+fn vec3_axis(n: f64, span: &Span) -> GroveResult<usize> {
+    match n {
+        0.0 => Ok(0),
+        1.0 => Ok(1),
+        2.0 => Ok(2),
+        _ => Err(GroveError::runtime(
+            format!("vec3 index {} out of range (expected 0, 1 or 2)", Value::Number(n)),
+            span.line, span.column,
+        )),
+    }
+}
+
+/// `v[n] = val` on a vec3 value, in place.
+fn set_vec3_component(vec: &mut Value, n: f64, val: Value, span: &Span) -> GroveResult<()> {
+    let axis = vec3_axis(n, span)?;
+    let Some(component) = val.as_number() else {
+        return Err(GroveError::type_error(
+            format!("cannot set vec3 component to {}", val.type_name()),
+            span.line, span.column,
+        ));
+    };
+    if let Value::Vec3(x, y, z) = vec {
+        *[x, y, z][axis] = component;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn run(src: &str) -> (GroveResult<Value>, Vec<String>) {
+        let mut lex = Lexer::new(src);
+        let tokens = lex.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
         let program = parser.parse().unwrap();
         let mut interp = Interpreter::new();
 
@@ -792,6 +2396,114 @@ log(t.size)
         assert_eq!(output, vec!["foo", "4"]);
     }
 
+    #[test]
+    fn test_empty_literals() {
+        let (result, output) = run(r#"
+local t = {}
+local a = []
+log(#t)
+log(#a)
+log(t)
+log(a)
+"#);
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["0", "0", "{}", "[]"]);
+    }
+
+    #[test]
+    fn test_len_errors_suggest_alternatives() {
+        let (result, _) = run("local v = vec3(1, 2, 3)\nlog(#v)");
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Type);
+        assert!(err.message.contains("v:length()"), "got: {}", err.message);
+
+        let (result, _) = run("log(#5)");
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.message,
+            "cannot get length of number; '#' only applies to strings, bytes, arrays and tables"
+        );
+    }
+
+    #[test]
+    fn test_color() {
+        let (result, output) = run(r#"
+log(color(1, 0, 0).r == 1)
+local c = color(0.5, 0.25, 1, 0.5)
+log(c.g)
+log(c.a)
+log(color(1, 0, 0).a)
+log(color())
+"#);
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["true", "0.25", "0.5", "1", "color(0, 0, 0, 1)"]);
+
+        let (result, _) = run("local c = color(1, 0)");
+        assert_eq!(result.unwrap_err().message, "color() expects 0, 3 or 4 arguments, got 2");
+        let (result, _) = run("log(color(1, 0, 0).x)");
+        assert_eq!(result.unwrap_err().message, "color has no field 'x'");
+    }
+
+    #[test]
+    fn test_vec3_indexing() {
+        let (result, output) = run(r#"
+local v = vec3(1, 2, 3)
+for i = 0, 2 do
+    log(v[i])
+end
+v[1] = 5
+log(v)
+local t = {pos = vec3(0, 0, 0)}
+t.pos[2] = 7
+log(t.pos.z)
+"#);
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["1", "2", "3", "vec3(1, 5, 3)", "7"]);
+
+        let (result, _) = run("local v = vec3(1, 2, 3)
+log(v[3])");
+        assert_eq!(result.unwrap_err().message, "vec3 index 3 out of range (expected 0, 1 or 2)");
+        let (result, _) = run("local v = vec3(1, 2, 3)
+v[-1] = 0");
+        assert_eq!(result.unwrap_err().message, "vec3 index -1 out of range (expected 0, 1 or 2)");
+        let (result, _) = run("local v = vec3(1, 2, 3)
+v[0] = \"a\"");
+        assert_eq!(result.unwrap_err().kind, crate::error::ErrorKind::Type);
+    }
+
+    #[test]
+    fn test_vec3_length_method() {
+        let (_, output) = run("log(vec3(3, 4, 0):length())");
+        assert_eq!(output, vec!["5"]);
+    }
+
+    #[test]
+    fn test_discard_variable() {
+        let (result, output) = run(r#"
+blueprint pick(_, _, x)
+    return x
+end
+local _ = pick(1, 2, 3)
+local _ = 4
+_ = pick(5, 6, 7)
+_ = 8
+for _ = 1, 2 do
+    log("tick")
+end
+log(pick(9, 10, 11))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["tick", "tick", "11"]);
+    }
+
+    #[test]
+    fn test_discard_variable_read_error() {
+        let (result, _) = run("local _ = 1\nlog(_)");
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::NameError);
+        assert!(err.message.contains("cannot read from discard variable"));
+    }
+
     #[test]
     fn test_boolean_ops() {
         let (_, output) = run(r#"
@@ -921,4 +2633,1198 @@ log(nil ~= 5)
         let (_, output) = run(r#"log("hello\tworld\n")"#);
         assert_eq!(output, vec!["hello\tworld\n"]);
     }
+
+    #[test]
+    fn test_call_non_function_value() {
+        let (result, _) = run("local x = 5\nx(1)");
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Type);
+        assert_eq!(err.message, "attempt to call a number value (variable 'x')");
+
+        let (result, _) = run("local t = {name = \"a\"}\nt.name()");
+        assert_eq!(result.unwrap_err().message, "attempt to call a string value");
+
+        let (result, _) = run("missing(1)");
+        assert_eq!(result.unwrap_err().message, "undefined function 'missing'");
+    }
+
+    #[test]
+    fn test_and_or_return_operands() {
+        let (_, output) = run(r#"
+log((nil or 5) == 5)
+log((false and 5) == false)
+log((3 and 4) == 4)
+log(nil and 1)
+log(false or nil)
+log(nil or false or "third" or "fourth")
+local maybe = nil
+local x = maybe or "default"
+log(x)
+local y = true and "value"
+log(y)
+"#);
+        assert_eq!(output, vec!["true", "true", "true", "nil", "nil", "third", "default", "value"]);
+    }
+
+    #[test]
+    fn test_reset_keeps_host_fns() {
+        let output = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let out_clone = output.clone();
+        let mut interp = Interpreter::new();
+        interp.register_fn("log", Box::new(move |args: &[Value]| {
+            out_clone.borrow_mut().push(format!("{}", args[0]));
+            Ok(Value::Nil)
+        }));
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+
+        interp.execute(&parse("local x = 1\nblueprint f() return 2 end")).unwrap();
+        interp.reset(true);
+        interp.execute(&parse("log(\"still here\")")).unwrap();
+        assert_eq!(*output.borrow(), vec!["still here"]);
+        assert_eq!(interp.execute(&parse("log(x)")).unwrap_err().kind, crate::error::ErrorKind::NameError);
+        assert_eq!(interp.execute(&parse("f()")).unwrap_err().kind, crate::error::ErrorKind::NameError);
+
+        interp.reset(false);
+        interp.execute(&parse("log(1)")).unwrap();
+        assert_eq!(*output.borrow(), vec!["still here"]);
+        assert_eq!(interp.output, vec!["1"]);
+    }
+
+    #[test]
+    fn test_blueprint_shadows_host_fn() {
+        let (result, output) = run(r#"
+log("from host")
+blueprint log(msg)
+    return msg
+end
+log("swallowed by blueprint")
+"#);
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["from host"]);
+    }
+
+    #[test]
+    fn test_variable_shadows_blueprint() {
+        let (result, _) = run(r#"
+blueprint size()
+    return 1
+end
+local size = 2
+size()
+"#);
+        assert!(result.unwrap_err().message.contains("attempt to call a number value"));
+    }
+
+    #[test]
+    fn test_math_rounding_on_vec3() {
+        let (result, output) = run(r#"
+log(math.floor(vec3(1.7, 2.2, 3.9)) == vec3(1, 2, 3))
+log(math.floor(2.5))
+log(math.ceil(vec3(0.1, 1.5, 2)))
+log(math.round(2.5))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["true", "2", "vec3(1, 2, 2)", "3"]);
+    }
+
+    #[test]
+    fn test_const_table() {
+        let (result, output) = run(r#"
+const Dir = {North = 0, East = 1, South = 2, West = 3}
+log(Dir.North)
+log(Dir.West)
+"#);
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["0", "3"]);
+
+        let (result, _) = run("const Dir = {North = 0}\nDir.North = 9");
+        assert_eq!(result.unwrap_err().message, "cannot modify const 'Dir'");
+
+        let (result, _) = run("const Dir = {North = 0}\nDir[\"North\"] = 9");
+        assert!(result.is_err());
+
+        let (result, _) = run("const Dirs = [0, 1]\nDirs:push(2)");
+        assert_eq!(result.unwrap_err().message, "cannot modify const 'Dirs'");
+
+        let (result, _) = run("const Max = 5\nMax = 6");
+        assert_eq!(result.unwrap_err().message, "cannot assign to const 'Max'");
+
+        let (result, _) = run("const Max = 5\nlocal Max = 6");
+        assert_eq!(result.unwrap_err().message, "cannot redeclare const 'Max'");
+        let (result, _) = run("const Max = 5\nconst Max = 6");
+        assert_eq!(result.unwrap_err().message, "cannot redeclare const 'Max'");
+
+        // Loop bodies start a new scope on every pass
+        let (result, output) = run("for i = 1, 3 do\n    const x = i\n    log(x)\nend");
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["1", "2", "3"]);
+        let (result, output) = run("for _, v in [1, 2] do\n    const y = v\n    log(y)\nend");
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["1", "2"]);
+
+        // Shadowing in an inner scope is still allowed
+        let (result, output) = run("const Max = 5\nif true then\n    local Max = 6\n    log(Max)\nend\nlog(Max)");
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["6", "5"]);
+    }
+
+    #[test]
+    fn test_nested_write_back() {
+        let (result, output) = run(r#"
+local t = {inner = {list = [1, 2]}}
+t.inner.list[1] = 20
+t.inner.list:push(3)
+t.inner.name = "x"
+log(t.inner.list)
+log(t.inner.name)
+local last = t.inner.list:pop()
+log(last)
+log(#t.inner.list)
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["[1, 20, 3]", "x", "3", "2"]);
+    }
+
+    #[test]
+    fn test_multi_line_expressions() {
+        let (result, output) = run("local a = 1 +\n    2 *\n    3\nlog(a)\nlocal b = 10 \\\n    - 4\nlog(b)\nlog((1\n+ 1))");
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["7", "6", "2"]);
+    }
+
+    #[test]
+    fn test_format_builtin() {
+        let (result, output) = run(r#"
+log(format("%g", 100000))
+log(string.format("%.3f", 1.0 / 3.0))
+log(format("%s has %d items", "crate", 3))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["100000", "0.333", "crate has 3 items"]);
+    }
+
+    #[test]
+    fn test_self_referential_table_errors() {
+        let (result, _) = run(r#"
+local t = {}
+while true do
+    t.self = t
+end
+"#);
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Runtime);
+        assert!(err.message.starts_with("cyclic structure"), "got: {}", err.message);
+
+        let (result, _) = run("local a = [0]\nfor i = 1, 1000 do\n    a[0] = a\nend");
+        assert!(result.unwrap_err().message.starts_with("cyclic structure"));
+
+        // Rebinding and defining are checked as well as stores into a place
+        for src in [
+            "local t = {}\nwhile true do\n    t = {s = t}\nend",
+            "local t = {}\nwhile true do\n    t.a = {s = t.a}\nend",
+            "local a = []\nwhile true do\n    a:push([a:pop()])\nend",
+        ] {
+            let (result, _) = run(src);
+            assert!(result.unwrap_err().message.starts_with("cyclic structure"), "{}", src);
+        }
+
+        // A single self-insert is just a snapshot and is fine
+        let (result, output) = run("local t = {n = 1}\nt.copy = t\nlog(t.copy.n)");
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["1"]);
+    }
+
+    #[test]
+    fn test_unreachable_reports_call_site() {
+        let (result, _) = run(r#"
+local kind = "oak"
+if kind == "pine" then
+    log("pine")
+else
+    unreachable("should not happen")
+end
+"#);
+        let err = result.unwrap_err();
+        assert_eq!(err.message, "unreachable: should not happen");
+        assert_eq!(err.line, 6);
+    }
+
+    #[test]
+    fn test_assert_multiple_conditions() {
+        let (result, output) = run("local a = 1\nassert(a, a > 0, \"yes\")\nlog(\"ok\")");
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["ok"]);
+
+        let (result, _) = run("local a = 1\nassert(true, a > 5, true)");
+        let err = result.unwrap_err();
+        assert_eq!(err.message, "assertion failed: argument 2 is false");
+        assert_eq!(err.line, 2);
+
+        let (result, _) = run("assert(nil)");
+        assert_eq!(result.unwrap_err().message, "assertion failed: got nil");
+    }
+
+    #[test]
+    fn test_pass_is_noop() {
+        let (result, output) = run(r#"
+blueprint stub()
+    pass
+end
+local x = 1
+if x == 1 then
+    pass
+end
+stub()
+log(x)
+"#);
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["1"]);
+    }
+
+    #[test]
+    fn test_freeze() {
+        let (result, output) = run(r#"
+local cfg = {seed = 7, sizes = [1, 2]}
+log(is_frozen(cfg))
+freeze(cfg)
+log(is_frozen(cfg))
+local copy = cfg
+log(is_frozen(copy), cfg.seed)
+cfg = {seed = 8}
+log(is_frozen(cfg))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["false", "true", "true 7", "false"]);
+
+        for src in [
+            "local t = freeze({a = 1})\nt.a = 2",
+            "local t = freeze({a = 1})\nt[\"b\"] = 2",
+            "local a = freeze([1, 2])\na[0] = 5",
+            "local a = freeze([1, 2])\na:push(3)",
+            "local a = freeze([1, 2])\na:pop()",
+            "local t = freeze({inner = {x = 1}})\nt.inner.x = 2",
+        ] {
+            let (result, _) = run(src);
+            let err = result.unwrap_err();
+            assert!(err.message.starts_with("cannot modify frozen"), "{}: {}", src, err.message);
+            assert_eq!(err.line, 2);
+        }
+
+        let (result, _) = run("freeze(5)");
+        assert_eq!(result.unwrap_err().kind, crate::error::ErrorKind::Type);
+    }
+
+    #[test]
+    fn test_trace_hook_fires_per_statement() {
+        let program = Parser::new(Lexer::new(r#"
+local x = 1
+blueprint bump(n)
+    return n + 1
+end
+if x == 1 then
+    x = bump(x)
+end
+"#).tokenize().unwrap()).parse().unwrap();
+        let lines = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen = lines.clone();
+        let mut interp = Interpreter::new();
+        interp.set_trace_hook(Box::new(move |span, _stmt| seen.borrow_mut().push(span.line)));
+        interp.execute(&program).unwrap();
+        // local, blueprint, if, assign, and the return inside bump
+        assert_eq!(*lines.borrow(), vec![2, 3, 6, 7, 4]);
+
+        interp.clear_trace_hook();
+        interp.execute(&program).unwrap();
+        assert_eq!(lines.borrow().len(), 5);
+    }
+
+    #[test]
+    fn test_run_n_statements_steps_to_completion() {
+        let program = Parser::new(Lexer::new(r#"
+local total = 0
+for i = 1, 3 do
+    total = total + i
+end
+log(total)
+log("done")
+"#).tokenize().unwrap()).parse().unwrap();
+        let output = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let out_clone = output.clone();
+        let mut interp = Interpreter::new();
+        interp.register_fn("log", Box::new(move |args: &[Value]| {
+            out_clone.borrow_mut().push(format!("{}", args[0]));
+            Ok(Value::Nil)
+        }));
+
+        let mut steps: usize = 0;
+        while !interp.run_n_statements(&program, 1).unwrap() {
+            steps += 1;
+            assert_eq!(output.borrow().len(), steps.saturating_sub(2));
+            assert!(steps < 10, "stepping never finished");
+        }
+        assert_eq!(steps, 3);
+        assert_eq!(*output.borrow(), vec!["6", "done"]);
+
+        // Finishing rewinds, so the program can be stepped again
+        assert!(!interp.run_n_statements(&program, 2).unwrap());
+        assert!(interp.run_n_statements(&program, 2).unwrap());
+    }
+
+    #[test]
+    fn test_error_captures_loop_locals() {
+        let program = Parser::new(Lexer::new(r#"
+local items = [3, 2, 1, 0]
+local total = 0
+for i = 0, 3 do
+    local item = items[i]
+    total = total + 6 / item
+end
+"#).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.set_capture_error_scopes(true);
+        assert!(interp.execute(&program).is_err());
+
+        let scopes = interp.error_scopes().expect("scopes captured on error");
+        assert_eq!(scopes[0]["total"], "11");
+        // The loop variable's scope, then the body's
+        let [.., loop_scope, body] = scopes else { panic!("expected loop scopes, got {:?}", scopes) };
+        assert_eq!(loop_scope["i"], "3");
+        assert_eq!(body["item"], "0");
+        assert!(interp.dump_scopes()[0].contains_key("items"));
+
+        interp.set_capture_error_scopes(false);
+        assert!(interp.execute(&program).is_err());
+        assert!(interp.error_scopes().is_none());
+    }
+
+    #[test]
+    fn test_loop_iteration_cap() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.set_max_loop_iterations(100);
+
+        let err = interp.execute(&parse("local n = 0\nwhile true do\n    n = n + 1\nend")).unwrap_err();
+        assert_eq!(err.message, "loop iteration limit exceeded (100 iterations)");
+        assert_eq!(err.line, 2);
+        assert_eq!(interp.env.get("n"), Some(&Value::Number(100.0)));
+
+        let err = interp.execute(&parse("repeat\n    pass\nuntil false")).unwrap_err();
+        assert!(err.message.starts_with("loop iteration limit exceeded"));
+        let err = interp.execute(&parse("for i = 1, 1000 do end")).unwrap_err();
+        assert!(err.message.starts_with("loop iteration limit exceeded"));
+
+        // Exactly at the cap is fine, and so are many loops under it
+        assert!(interp.execute(&parse("for i = 1, 100 do end")).is_ok());
+        assert!(interp.execute(&parse("for j = 1, 50 do\n    for i = 1, 100 do end\nend")).is_ok());
+
+        interp.set_max_loop_iterations(0);
+        assert!(interp.execute(&parse("for i = 1, 1000 do end")).is_ok());
+    }
+
+    #[test]
+    fn test_zip_pairs_to_shorter_length() {
+        let (result, output) = run(r#"
+local pairs = zip([1, 2, 3], ["a", "b"])
+log(#pairs)
+log(pairs)
+log(pairs[1][0], pairs[1][1])
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["2", "[[1, a], [2, b]]", "2 b"]);
+
+        let (result, _) = run("zip([1], 2)");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_enumerate_pairs() {
+        let (result, output) = run(r#"
+log(enumerate(["a", "b"]))
+log(#enumerate([]))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["[[0, a], [1, b]]", "0"]);
+    }
+
+    #[test]
+    fn test_namespaced_host_fns() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.register_namespace("game");
+        assert!(interp.register_namespaced_fn("game", "double", Box::new(|args: &[Value]| {
+            Ok(Value::Number(args[0].as_number().unwrap_or(0.0) * 2.0))
+        })));
+        assert!(!interp.register_namespaced_fn("nope", "f", Box::new(|_: &[Value]| Ok(Value::Nil))));
+
+        interp.execute(&parse("local a = game.double(4)\nlocal f = game.double\nlocal b = f(a)")).unwrap();
+        assert_eq!(interp.env.get("b"), Some(&Value::Number(16.0)));
+
+        let err = interp.execute(&parse("game.missing(1)")).unwrap_err();
+        assert_eq!(err.message, "attempt to call a nil value");
+
+        interp.reset(true);
+        interp.execute(&parse("local c = game.double(1)")).unwrap();
+        assert_eq!(interp.env.get("c"), Some(&Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_numeric_for_variable_semantics() {
+        let (result, output) = run(r#"
+local i = "outer"
+local seen = []
+for i = 1, 3 do
+    seen:push(i)
+    i = i + 100
+end
+log(i, seen)
+for i = 1, 2 do
+    for i = 10, 11 do
+        seen:push(i)
+    end
+    seen:push(i)
+end
+log(seen)
+for i = 1, 2 do
+    local i = i * 10
+    log(i)
+end
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec![
+            "outer [1, 2, 3]",
+            "[1, 2, 3, 10, 11, 1, 10, 11, 2]",
+            "10",
+            "20",
+        ]);
+    }
+
+    #[test]
+    fn test_fork_leaves_original_untouched() {
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = calls.clone();
+        let mut interp = Interpreter::new();
+        interp.register_fn("tick", Box::new(move |_args: &[Value]| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Nil)
+        }));
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        interp.execute(&parse("local x = 1\nlocal t = {a = 1}\nblueprint f() return x end")).unwrap();
+
+        let mut fork = interp.fork();
+        fork.execute(&parse("tick()\nx = 2\nt.a = 5\nblueprint f() return 99 end")).unwrap();
+        assert_eq!(fork.execute(&parse("return f()")).unwrap(), Value::Number(99.0));
+
+        assert_eq!(interp.env.get("x"), Some(&Value::Number(1.0)));
+        assert_eq!(interp.execute(&parse("return t.a")).unwrap(), Value::Number(1.0));
+        assert_eq!(interp.execute(&parse("return f()")).unwrap(), Value::Number(1.0));
+        interp.execute(&parse("tick()")).unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_call_blueprint_by_name() {
+        let mut interp = Interpreter::new();
+        let program = Parser::new(Lexer::new("blueprint add(a, b) return a + b end").tokenize().unwrap())
+            .parse()
+            .unwrap();
+        interp.execute(&program).unwrap();
+        let sum = interp.call("add", &[Value::Number(2.0), Value::Number(3.0)]).unwrap();
+        assert_eq!(sum, Value::Number(5.0));
+        let err = interp.call("missing", &[]).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::NameError);
+    }
+
+    #[test]
+    fn test_partial_application() {
+        let (result, output) = run(r#"
+blueprint add(a, b)
+    return a + b
+end
+local add5 = partial(add, 5)
+log(add5(3) == 8, add5)
+local greet = partial(log, "hello")
+greet("world")
+local n = 1
+local add_n = partial(add, n)
+n = 100
+return add_n(1)
+"#);
+        assert_eq!(result.unwrap(), Value::Number(2.0));
+        assert_eq!(output, vec!["true <function:add>", "hello world"]);
+        assert!(run("partial(1, 2)").0.is_err());
+    }
+
+    #[test]
+    fn test_spread_arguments() {
+        let (result, output) = run(r#"
+blueprint count(a, b, c, d, e)
+    log(a, b, c, d, e)
+end
+local rest = [2, 3]
+count(...[1, 2, 3])
+count(1, ...rest, 9)
+build count(...rest, ...rest)
+log(math.max(...[4, 7, 5]))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["1 2 3 nil nil", "1 2 3 9 nil", "2 3 2 3 nil", "7"]);
+
+        let err = run("log(...5)").0.unwrap_err();
+        assert_eq!(err.message, "cannot spread number into arguments, expected an array");
+    }
+
+    #[test]
+    fn test_keyword_arguments() {
+        let (result, output) = run(r#"
+blueprint tree(height, kind, leaves)
+    log(height, kind, leaves)
+end
+build tree(height: 10, kind: "oak")
+build tree(kind: "pine", leaves: false, height: 3)
+tree(7, leaves: true)
+local t = tree
+t(1, "elm", leaves: 0)
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["10 oak nil", "3 pine false", "7 nil true", "1 elm 0"]);
+
+        let err = |src: &str| run(&format!("blueprint f(a, b) end\n{}", src)).0.unwrap_err().message;
+        assert_eq!(err("f(c: 1)"), "blueprint 'f' has no parameter 'c'");
+        assert_eq!(err("f(1, a: 2)"), "argument 'a' given more than once");
+        assert_eq!(err("build f(b: 1, b: 2)"), "argument 'b' given more than once");
+        assert_eq!(err("log(a: 1)"), "keyword arguments can only be passed to a blueprint, not 'log'");
+    }
+
+    #[test]
+    fn test_globals_snapshot() {
+        let (result, output) = run(r#"
+local x = 5
+local g = globals()
+log(g.x == 5, g.nope)
+g.x = 6
+log(x)
+if true then
+    local inner = 1
+    log(globals().inner)
+end
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["true nil", "5", "nil"]);
+    }
+
+    #[test]
+    fn test_default_print_and_log() {
+        let mut interp = Interpreter::new();
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        interp.execute(&parse("print(\"a\", 1, nil)\nlog([1, 2])")).unwrap();
+        assert_eq!(interp.output, vec!["a 1 nil", "[1, 2]"]);
+
+        // A host-registered `log` wins over the default.
+        let (result, output) = run("log(\"hi\")");
+        assert!(result.is_ok());
+        assert_eq!(output, vec!["hi"]);
+    }
+
+    #[test]
+    fn test_script_bindings_shadow_vec3() {
+        let (result, output) = run(r#"
+log(vec3(1, 2, 3))
+blueprint vec3(x, y, z)
+    return x + y + z
+end
+log(vec3(1, 2, 3))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["vec3(1, 2, 3)", "6"]);
+
+        let (result, output) = run(r#"
+if true then
+    local vec3 = partial(log, "local")
+    vec3(1)
+end
+log(vec3(0, 0, 1).z)
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["local 1", "1"]);
+    }
+
+    #[test]
+    fn test_seeded_random_choice_and_shuffle() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let program = parse(r#"
+local items = [1, 2, 3, 4, 5, 6, 7, 8]
+log(random_choice(items), shuffle(items), random_choice([]))
+"#);
+        let run_seeded = |seed: u64| {
+            let mut interp = Interpreter::new();
+            interp.set_seed(seed);
+            interp.execute(&program).unwrap();
+            interp.output
+        };
+        assert_eq!(run_seeded(42), run_seeded(42));
+        assert_ne!(run_seeded(42), run_seeded(43));
+
+        let mut interp = Interpreter::new();
+        interp.set_seed(9);
+        let shuffled = interp.execute(&parse("return shuffle([5, 1, 4, 2, 3])")).unwrap();
+        let mut nums: Vec<f64> = shuffled.as_array().unwrap().iter().map(|v| v.as_number().unwrap()).collect();
+        nums.sort_by(f64::total_cmp);
+        assert_eq!(nums, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(interp.execute(&parse("shuffle(\"abc\")")).is_err());
+    }
+
+    #[test]
+    fn test_noise_builtins() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let program = parse("return [noise(1.5, 2.25), noise3(0.5, 0.5, 7.1), noise(1.5, 2.25)]");
+        let sample = |seed: u64| {
+            let mut interp = Interpreter::new();
+            interp.set_seed(seed);
+            let vals = interp.execute(&program).unwrap();
+            vals.as_array().unwrap().iter().map(|v| v.as_number().unwrap()).collect::<Vec<f64>>()
+        };
+        let a = sample(11);
+        assert_eq!(a, sample(11));
+        assert_eq!(a[0], a[2]);
+        assert_ne!(a, sample(12));
+        assert!(a.iter().all(|n| (-1.0..=1.0).contains(n)));
+
+        let mut interp = Interpreter::new();
+        assert!(interp.execute(&parse("noise(1)")).is_err());
+        assert!(interp.execute(&parse("noise3(1, 2, \"z\")")).is_err());
+    }
+
+    #[test]
+    fn test_strict_concat() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.execute(&parse("return \"x: \" .. nil")).unwrap(), Value::String("x: nil".into()));
+
+        interp.set_strict(true);
+        assert_eq!(interp.execute(&parse("return \"x: \" .. 1.5 .. \"!\"")).unwrap(), Value::String("x: 1.5!".into()));
+        let err = interp.execute(&parse("return \"x: \" .. nil")).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Type);
+        assert_eq!(err.message, "cannot concatenate a nil value");
+        assert!(interp.execute(&parse("return {a = 1} .. \"\"")).is_err());
+    }
+
+    #[test]
+    fn test_trailing_expression_is_blueprint_result() {
+        let (result, output) = run(r#"
+blueprint double(x) x * 2 end
+blueprint early(x)
+    if x then return "early" end
+    "late"
+end
+blueprint ends_with_local(x)
+    x * 2
+    local y = x
+end
+blueprint ends_with_if(x)
+    if true then x * 2 end
+end
+blueprint ends_with_call(x)
+    log(x)
+end
+blueprint ends_with_method(t)
+    t:push(1)
+end
+log(double(21), early(true), early(false), ends_with_local(1), ends_with_if(1))
+log(ends_with_call("called"), ends_with_method([]))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["42 early late nil nil", "called", "nil nil"]);
+    }
+
+    #[test]
+    fn test_leveled_logging() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let program = parse("print(\"data\")\ninfo(\"loaded\", 3)\nwarn(\"low\")\nerror_log(\"bad\")");
+        let mut interp = Interpreter::new();
+        interp.execute(&program).unwrap();
+        assert_eq!(interp.output, vec!["data", "[info] loaded 3", "[warn] low", "[error] bad"]);
+
+        let logged = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = logged.clone();
+        let mut interp = Interpreter::new();
+        interp.set_log_hook(Box::new(move |level, msg| sink.borrow_mut().push((level, msg.to_string()))));
+        interp.execute(&program).unwrap();
+        assert_eq!(interp.output, vec!["data"]);
+        assert_eq!(*logged.borrow(), vec![
+            (LogLevel::Info, "loaded 3".to_string()),
+            (LogLevel::Warn, "low".to_string()),
+            (LogLevel::Error, "bad".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_generic_for() {
+        let (result, output) = run(r#"
+local t = {}
+t.zeta = 1
+t.alpha = 2
+t.mid = 3
+for k, v in pairs(t) do
+    log(k, v)
+end
+for k in t do
+    log(k)
+end
+for i, v in ["a", "b"] do
+    log(i, v)
+end
+for v in [10, 20, 30] do
+    if v == 20 then continue end
+    log(v)
+end
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec![
+            "alpha 2", "mid 3", "zeta 1", "alpha", "mid", "zeta", "0 a", "1 b", "10", "30",
+        ]);
+        let err = run("for x in 5 do end").0.unwrap_err();
+        assert_eq!(err.message, "cannot iterate over a number value");
+    }
+
+    #[test]
+    fn test_generic_for_respects_loop_limit() {
+        let program = Parser::new(Lexer::new("for v in [1, 2, 3, 4] do end").tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.set_max_loop_iterations(3);
+        let err = interp.execute(&program).unwrap_err();
+        assert_eq!(err.message, "loop iteration limit exceeded (3 iterations)");
+    }
+
+    #[test]
+    fn test_number_and_string_methods() {
+        let (result, output) = run(r#"
+log((-5):abs() == 5, "hi":len() == 2)
+log(3.7:floor(), 3.2:ceil(), 2.5:round(), (4):neg())
+local n = -1.5
+log(n:abs(), #"hello" == "hello":len())
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["true true", "3 4 3 -4", "1.5 true"]);
+        let err = run("log((1):len())").0.unwrap_err();
+        assert_eq!(err.message, "method call 'len' on number not yet implemented");
+    }
+
+    #[test]
+    fn test_strict_out_of_bounds_reads() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.execute(&parse("return [1, 2][5]")).unwrap(), Value::Nil);
+
+        interp.set_strict(true);
+        assert_eq!(interp.execute(&parse("return [1, 2][1]")).unwrap(), Value::Number(2.0));
+        let err = interp.execute(&parse("return [1, 2][5]")).unwrap_err();
+        assert_eq!(err.message, "array index 5 out of bounds (length 2)");
+        assert!(interp.execute(&parse("return [][0]")).is_err());
+        assert!(interp.execute(&parse("return [1][-1]")).is_err());
+    }
+
+    #[test]
+    fn test_for_in_sorted_table() {
+        let (result, output) = run(r#"
+local t = {c = 3, a = 1, b = 2}
+for k, v in sorted(t) do
+    log(k, v)
+end
+log(sorted_keys(t))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["a 1", "b 2", "c 3", "[a, b, c]"]);
+    }
+
+    #[test]
+    fn test_build_expression_returns_result() {
+        let (result, output) = run(r#"
+blueprint make_tree(height)
+    return {height = height, kind = "oak"}
+end
+local tree = build make_tree(4)
+log(tree.height, tree.kind, build make_tree(2).height)
+build make_tree(1)
+local make_tree = 5
+log(build make_tree(7).height)
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["4 oak 2", "7"]);
+        let err = run("local x = build missing()").0.unwrap_err();
+        assert_eq!(err.message, "undefined blueprint 'missing'");
+    }
+
+    #[test]
+    fn test_strict_blueprint_arity() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.execute(&parse("blueprint add(a, b) return a + b end")).unwrap();
+        assert_eq!(interp.execute(&parse("return add(1, 2, 3)")).unwrap(), Value::Number(3.0));
+
+        interp.set_strict(true);
+        assert_eq!(interp.execute(&parse("return add(1, 2)")).unwrap(), Value::Number(3.0));
+        let err = interp.execute(&parse("return add(1)")).unwrap_err();
+        assert_eq!(err.message, "blueprint 'add' expects 2 arguments, got 1");
+        assert!(interp.execute(&parse("return add(1, 2, 3)")).is_err());
+        assert!(interp.execute(&parse("build add(1)")).is_err());
+        assert!(interp.call("add", &[Value::Number(1.0)]).is_err());
+        let err = interp.execute(&parse("return add(b: 2)")).unwrap_err();
+        assert_eq!(err.message, "blueprint 'add' is missing argument 'a'");
+        assert_eq!(interp.execute(&parse("return add(b: 2, a: 1)")).unwrap(), Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_emit_queues_commands() {
+        let tokens = Lexer::new(r#"
+for i = 1, 3 do
+    emit("place", {x = i, block = "stone"})
+end
+"#).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.execute(&program).unwrap();
+        assert_eq!(interp.commands.len(), 3);
+        for (i, (kind, data)) in interp.commands.iter().enumerate() {
+            assert_eq!(kind, "place");
+            let Value::Table { fields: map, .. } = data else { panic!("expected table, got {:?}", data) };
+            assert_eq!(map["x"], Value::Number(i as f64 + 1.0));
+        }
+        assert!(run(r#"emit(1, {})"#).0.is_err());
+        assert!(run(r#"emit("place")"#).0.is_err());
+    }
+
+    #[test]
+    fn test_deepest_allowed_nesting_runs() {
+        // Both limits at once: the deepest expression in the deepest block.
+        // Below the last `if` are its `log(...)` statement, then the call,
+        // its argument and the `#` operand, each one expression level.
+        let blocks = crate::parser::DEFAULT_MAX_DEPTH - 1;
+        let exprs = crate::parser::DEFAULT_MAX_EXPRESSION_DEPTH - 3;
+        let src = format!(
+            "{}log(#{}1{}){}",
+            "if true then\n".repeat(blocks),
+            "[".repeat(exprs),
+            "]".repeat(exprs),
+            "\nend".repeat(blocks),
+        );
+        let (result, output) = crate::parser::on_main_thread_stack(move || {
+            let (result, output) = run(&src);
+            (result.map(|_| ()).map_err(|e| e.to_string()), output)
+        });
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["1"]);
+    }
+
+    #[test]
+    fn test_eval() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        let err = interp.execute(&parse(r#"return eval("return 2+3")"#)).unwrap_err();
+        assert_eq!(err.message, "eval() is disabled");
+
+        interp.set_allow_eval(true);
+        assert_eq!(interp.execute(&parse(r#"return eval("return 2+3")"#)).unwrap(), Value::Number(5.0));
+        let shared = r#"
+local base = 10
+eval("blueprint add(x) return base + x end  base = 20")
+return eval("add(1)")
+"#;
+        assert_eq!(interp.execute(&parse(shared)).unwrap(), Value::Number(21.0));
+
+        let err = interp.execute(&parse(r#"eval("local = 1")"#)).unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Syntax);
+        assert!(err.message.starts_with("in eval() at 1:7: "), "{}", err.message);
+
+        interp.set_instruction_limit(1000);
+        assert!(interp.execute(&parse(r#"eval("while true do end")"#)).is_err());
+    }
+
+    #[test]
+    fn test_compile() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        let err = interp.execute(&parse(r#"compile("return 42")"#)).unwrap_err();
+        assert_eq!(err.message, "compile() is disabled");
+
+        // The host's source limits hold for evaluated code too
+        interp.set_allow_eval(true);
+        interp.set_source_limits(usize::MAX, 4);
+        let err = interp.execute(&parse(r#"eval("return 1 + 2 + 3")"#)).unwrap_err();
+        assert!(err.message.contains("too many tokens"), "{}", err.message);
+        let err = interp.execute(&parse(r#"compile("return 1 + 2 + 3")"#)).unwrap_err();
+        assert!(err.message.contains("too many tokens"), "{}", err.message);
+        interp.set_source_limits(usize::MAX, usize::MAX);
+        interp.set_allow_eval(false);
+        let err = interp.execute(&parse(r#"compile("return 42")"#)).unwrap_err();
+        assert_eq!(err.message, "compile() is disabled");
+
+        interp.set_allow_eval(true);
+        let src = r#"
+local f = compile("return 42")
+local count = 0
+local inc = compile("count = count + 1")
+inc()
+inc()
+return f() == 42 and count == 2
+"#;
+        assert_eq!(interp.execute(&parse(src)).unwrap(), Value::Bool(true));
+        let err = interp.execute(&parse(r#"compile("return (")"#)).unwrap_err();
+        assert!(err.message.starts_with("in compile() at 1:9: "), "{}", err.message);
+
+        // Compiled programs live in their values, not in the blueprints.
+        let src = "local i = 0\nwhile i < 100 do\n  compile(\"return 1\")\n  i = i + 1\nend\nreturn tostring(compile(\"return 1\"))";
+        assert_eq!(interp.execute(&parse(src)).unwrap(), Value::String("<function:compiled>".into()));
+        assert!(interp.blueprints.is_empty());
+
+        interp.set_strict(true);
+        let err = interp.execute(&parse("compile(\"return 1\")(5)")).unwrap_err();
+        assert_eq!(err.message, "compiled function expects 0 arguments, got 1");
+    }
+
+    #[test]
+    fn test_min_by_max_by() {
+        let (result, output) = run(r#"
+local len = fn(s) return #s end
+local names = ["oak", "birch", "elm", "cedar"]
+log(max_by(names, len))
+log(min_by(names, len))
+log(max_by([], len))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["birch", "oak", "nil"]);
+
+        let (result, _) = run("local len = fn(s) return #s end
+max_by([\"a\", 5], len)");
+        let err = result.unwrap_err();
+        assert!(err.message.starts_with("max_by() key failed on element 1: "), "{}", err.message);
+
+        let (result, _) = run("min_by([1, 2], fn(x) return \"a\" end)");
+        assert_eq!(result.unwrap_err().message, "min_by() key must be a number, got string for element 0");
+    }
+
+    #[test]
+    fn test_group_by() {
+        let (result, output) = run(r#"
+local parity = fn(n) if n % 2 == 0 then return "even" end return "odd" end
+local groups = group_by([5, 2, 8, 3, 1, 4], parity)
+log(groups.even)
+log(groups.odd)
+log(#group_by([], parity))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["[2, 8, 4]", "[5, 3, 1]", "0"]);
+
+        let (result, _) = run("group_by([1, 2], fn(n) return n end)");
+        assert_eq!(result.unwrap_err().message, "group_by() key must be a string, got number for element 0");
+    }
+
+    #[test]
+    fn test_sort_with_comparator() {
+        let (result, output) = run(r#"
+blueprint by_height(a, b)
+    return a.height < b.height
+end
+local trees = [{name = "oak", height = 12}, {name = "fir", height = 30}, {name = "ash", height = 8}, {name = "elm", height = 12}]
+local sorted_trees = sort(trees, by_height)
+for _, t in sorted_trees do
+    log(t.name)
+end
+log(trees[0].name)
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["ash", "oak", "elm", "fir", "oak"]);
+
+        // Comparator errors propagate.
+        let (result, _) = run(r#"
+blueprint broken(a, b) return a < nil end
+sort([3, 1, 2], broken)
+"#);
+        assert!(result.is_err());
+
+        let (result, output) = run(r#"
+blueprint coin(a, b) return true end
+log(#sort([5, 4, 3, 2, 1, 0], coin))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["6"]);
+        assert!(run("sort([1, 2], 3)").0.is_err());
+    }
+
+    #[test]
+    fn test_blueprint_blob_round_trip() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut library = Interpreter::new();
+        library.execute(&parse(r#"
+blueprint tree(height, kind)
+    local parts = []
+    for i = 1, height do
+        parts:push(kind .. i)
+    end
+    return parts
+end
+"#)).unwrap();
+        let blob = library.blueprint_blob("tree").unwrap();
+        assert!(library.blueprint_blob("missing").is_none());
+
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.load_blueprint_blob(&blob).unwrap(), "tree");
+        let result = interp.call("tree", &[Value::Number(2.0), Value::String("oak".into())]).unwrap();
+        assert_eq!(result.as_array().unwrap(), [Value::String("oak1".into()), Value::String("oak2".into())]);
+        assert!(interp.load_blueprint_blob(&blob[1..]).is_err());
+
+        let body = parse("return x * 2").statements;
+        interp.define_blueprint("double", vec!["x".to_string()], body);
+        assert_eq!(interp.execute(&parse("return double(21)")).unwrap(), Value::Number(42.0));
+    }
+
+    #[test]
+    fn test_closures_capture_values_per_iteration() {
+        let (result, output) = run(r#"
+local fns = []
+for i = 1, 3 do
+    fns:push(fn() return i * 10 end)
+end
+for name, _ in {a = 1, b = 2} do
+    fns:push(fn(suffix) return name .. suffix end)
+end
+log(fns[0](), fns[1](), fns[2](), fns[3]("!"), fns[4]("?"))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, vec!["10 20 30 a! b?"]);
+    }
+
+    #[test]
+    fn test_closure_capture_is_a_snapshot() {
+        let (result, output) = run(r#"
+blueprint counter()
+    local n = 0
+    local f = fn() n = n + 1 return n end
+    n = 100
+    return f
+end
+local count = counter()
+log(count(), count())
+local base = 1
+local add = fn(x) return base + x end
+base = 5
+log(add(1))
+local scale = 2
+local twice = fn(x) return x * scale end
+log(sort([3, 1, 2], fn(a, b) return a > b end), twice(4))
+"#);
+        assert!(result.is_ok(), "{:?}", result);
+        // Captured when created; writes inside the closure don't persist.
+        // Top-level locals are globals, which are looked up at call time.
+        assert_eq!(output, vec!["1 1", "6", "[3, 2, 1] 8"]);
+    }
+
+    #[test]
+    fn test_limit_hook_fires_once() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let hits = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut interp = Interpreter::new();
+        let seen = hits.clone();
+        interp.set_limit_hook(Box::new(move |kind, span| seen.borrow_mut().push((kind, span.line))));
+        interp.set_instruction_limit(1000);
+
+        let err = interp.execute(&parse("local x = 0\nwhile true do\n    x = x + 1\nend")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InstructionLimit);
+        assert_eq!(*hits.borrow(), [(ErrorKind::InstructionLimit, 3)]);
+
+        hits.borrow_mut().clear();
+        interp.set_max_loop_iterations(5);
+        let err = interp.execute(&parse("for i = 1, 10 do end")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LoopLimit);
+        assert_eq!(*hits.borrow(), [(ErrorKind::LoopLimit, 1)]);
+
+        hits.borrow_mut().clear();
+        interp.execute(&parse("for i = 1, 3 do end")).unwrap();
+        assert!(interp.execute(&parse("error(1)")).is_err());
+        assert!(hits.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        interp.execute(&parse("local small = [1, 2, 3]")).unwrap();
+        let used = interp.globals_size();
+        assert!(used > 0);
+
+        interp.set_memory_budget(used + 64);
+        // Data that doesn't outlive the script isn't counted.
+        interp.execute(&parse("blueprint scratch()\n    local tmp = []\n    for i = 1, 100 do tmp:push(i) end\nend\nscratch()")).unwrap();
+        let err = interp
+            .execute(&parse("local big = []\nfor i = 1, 100 do big:push(\"payload\") end"))
+            .unwrap_err();
+        assert!(err.message.starts_with("global data exceeds memory budget"), "{}", err.message);
+        assert_eq!((err.line, err.column), (2, 1));
+        let err = interp.execute(&parse("local more = big\nreturn 1")).unwrap_err();
+        assert!(err.message.starts_with("global data exceeds memory budget"), "{}", err.message);
+        assert_eq!(err.line, 2);
+
+        interp.set_memory_budget(0);
+        interp.execute(&parse("big = nil")).unwrap();
+    }
+
+    #[test]
+    fn test_top_level_return_exports_value() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let mut interp = Interpreter::new();
+        let exports = interp.execute(&parse("local v = 1\nreturn {version = v}\nlog(\"unreachable\")")).unwrap();
+        match exports {
+            Value::Table { fields: map, .. } => assert_eq!(map.get("version"), Some(&Value::Number(1.0))),
+            other => panic!("expected table, got {:?}", other),
+        }
+        assert!(interp.output.is_empty());
+        assert_eq!(interp.execute(&parse("local x = 1")).unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn test_import_modules() {
+        let parse = |src: &str| Parser::new(Lexer::new(src).tokenize().unwrap()).parse().unwrap();
+        let loads = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut interp = Interpreter::new();
+        let seen = loads.clone();
+        interp.set_import_resolver(Box::new(move |name| {
+            seen.borrow_mut().push(name.to_string());
+            match name {
+                "utils" => Some("local secret = 7\nreturn {answer = 42, double = fn(x) return x * 2 end}".into()),
+                "a" => Some("return import(\"b\")".into()),
+                "b" => Some("return import(\"a\")".into()),
+                "broken" => Some("local x = nil + 1".into()),
+                "shapes" => Some(
+                    "local helper = fn(x) return x + 1 end\n\
+                     blueprint scale(x) return helper(x) * 10 end\n\
+                     return {f = fn(x) return helper(x) end, scale = scale, apply = fn(g, x) return g(x) end}"
+                        .into(),
+                ),
+                _ => None,
+            }
+        }));
+
+        interp.execute(&parse("local u = import(\"utils\")\nlog(u.answer, u.double(5), import(\"utils\").answer)")).unwrap();
+        assert_eq!(interp.output, vec!["42 10 42"]);
+        assert_eq!(*loads.borrow(), ["utils"]);
+        assert!(interp.execute(&parse("log(secret)")).is_err());
+
+        // Exported functions see the module's globals and blueprints, which
+        // stay out of the importer's; callbacks still see the importer's.
+        interp.output.clear();
+        let src = "local helper = fn(x) return x - 1 end\n\
+                   local s = import(\"shapes\")\n\
+                   log(s.f(1), s.scale(1), s.apply(helper, 5), s.apply(s.f, 5))";
+        interp.execute(&parse(src)).unwrap();
+        assert_eq!(interp.output, vec!["2 20 4 6"]);
+        let err = interp.execute(&parse("scale(1)")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NameError);
+
+        let err = interp.execute(&parse("import(\"a\")")).unwrap_err();
+        assert_eq!(err.message, "in module 'a' at 1:14: in module 'b' at 1:14: circular import: a -> b -> a");
+        let err = interp.execute(&parse("import(\"missing\")")).unwrap_err();
+        assert_eq!(err.message, "module 'missing' not found");
+        let err = interp.execute(&parse("\nimport(\"broken\")")).unwrap_err();
+        assert!(err.message.starts_with("in module 'broken' at 1:"), "{}", err.message);
+        assert_eq!(err.line, 2);
+    }
 }
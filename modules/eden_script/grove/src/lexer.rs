@@ -15,6 +15,7 @@ pub enum TokenKind {
     // Keywords
     Local,
     Let,
+    Const,
     Fn,
     Blueprint,
     Build,
@@ -32,6 +33,7 @@ pub enum TokenKind {
     Return,
     Break,
     Continue,
+    Pass,
     And,
     Or,
     Not,
@@ -44,6 +46,8 @@ pub enum TokenKind {
     Percent,
     Caret,
     DotDot,
+    /// `...` — spreads an array into call arguments
+    DotDotDot,
     Hash,
 
     // Comparison
@@ -70,6 +74,9 @@ pub enum TokenKind {
     Colon,
 
     // Special
+    /// `-- text` comment, only produced in keep-comments mode. Holds the raw
+    /// comment text including the leading dashes.
+    Comment(String),
     Eof,
 }
 
@@ -78,36 +85,170 @@ pub struct Token {
     pub kind: TokenKind,
     pub line: usize,
     pub column: usize,
+    /// Position just past the token's last character.
+    pub end_line: usize,
+    pub end_column: usize,
 }
 
 impl Token {
     pub fn new(kind: TokenKind, line: usize, column: usize) -> Self {
-        Self { kind, line, column }
+        Self { kind, line, column, end_line: line, end_column: column }
     }
 }
 
-pub struct Lexer {
-    source: Vec<char>,
+/// Coarse token classes for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenCategory {
+    /// Reserved words, including `and`/`or`/`not`.
+    Keyword,
+    Operator,
+    /// Numbers, strings, `true`, `false` and `nil`.
+    Literal,
+    Identifier,
+    /// Brackets, separators and the end-of-input marker.
+    Punctuation,
+    Comment,
+}
+
+impl TokenKind {
+    pub fn category(&self) -> TokenCategory {
+        use TokenKind::*;
+        match self {
+            Number(_) | StringLit(_) | True | False | Nil => TokenCategory::Literal,
+            Identifier(_) => TokenCategory::Identifier,
+            Local | Let | Const | Fn | Blueprint | Build | End | If | Then | ElseIf | Else | For
+            | In | Do | While | Repeat | Until | Return | Break | Continue | Pass | And | Or | Not => {
+                TokenCategory::Keyword
+            }
+            Plus | Minus | Star | Slash | Percent | Caret | DotDot | DotDotDot | Hash | Equal
+            | NotEqual | TildeEqual | Less | LessEqual | Greater | GreaterEqual | Assign => {
+                TokenCategory::Operator
+            }
+            LeftParen | RightParen | LeftBracket | RightBracket | LeftBrace | RightBrace | Comma
+            | Dot | Colon | Eof => TokenCategory::Punctuation,
+            Comment(_) => TokenCategory::Comment,
+        }
+    }
+
+    /// Whether an operand must follow this token, as after an operator, an
+    /// opening bracket or a separator.
+    fn expects_operand(&self) -> bool {
+        use TokenKind::*;
+        matches!(
+            self,
+            Plus | Minus | Star | Slash | Percent | Caret | DotDot | DotDotDot | Hash | Equal | NotEqual
+                | TildeEqual | Less | LessEqual | Greater | GreaterEqual | Assign | And | Or | Not
+                | LeftParen | LeftBracket | LeftBrace | Comma | Dot | Colon
+        )
+    }
+}
+
+/// Tokens of `src` with their positions, comments included, for editors that
+/// highlight without parsing.
+pub fn tokenize_with_spans(src: &str) -> GroveResult<Vec<Token>> {
+    Lexer::new(src).keep_comments(true).tokenize()
+}
+
+/// Tokenizes source text in place. `pos` is a byte offset into `source`, so
+/// even megabyte-scale generated scripts are never copied.
+pub struct Lexer<'a> {
+    source: &'a str,
     pos: usize,
     line: usize,
     column: usize,
+    max_chars: usize,
+    max_tokens: usize,
+    /// Set when the source exceeded `max_chars`.
+    oversized: bool,
+    keep_comments: bool,
+    /// Treat a `#` that starts a statement as a comment, see
+    /// `allow_hash_comments`.
+    hash_comments: bool,
+    /// Whether the last token needs an operand after it (`=`, `+`, `(`, ...),
+    /// so a statement can't begin at the next one.
+    mid_expression: bool,
+    /// Reject integer literals `f64` can't hold exactly instead of warning.
+    strict: bool,
+    warnings: Vec<GroveError>,
 }
 
-impl Lexer {
-    pub fn new(source: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self::with_limits(source, usize::MAX, usize::MAX)
+    }
+
+    /// Create a lexer that rejects sources longer than `max_chars` characters
+    /// or producing more than `max_tokens` tokens (EOF included).
+    pub fn with_limits(source: &'a str, max_chars: usize, max_tokens: usize) -> Self {
+        let oversized = max_chars != usize::MAX && source.chars().nth(max_chars).is_some();
         Self {
-            source: source.chars().collect(),
+            source,
             pos: 0,
             line: 1,
             column: 1,
+            max_chars,
+            max_tokens,
+            oversized,
+            keep_comments: false,
+            hash_comments: false,
+            mid_expression: false,
+            strict: false,
+            warnings: Vec::new(),
         }
     }
 
+    /// Emit `TokenKind::Comment` tokens instead of discarding comments, for
+    /// documentation tools. The parser skips comment tokens either way.
+    pub fn keep_comments(mut self, keep: bool) -> Self {
+        self.keep_comments = keep;
+        self
+    }
+
+    /// Also accept shell-style `# comment` lines: a `#` that is the first
+    /// thing on its line, where a statement could begin, starts a comment
+    /// running to the end of the line. Anywhere else `#` is still the length
+    /// operator, so `log(#arr)` works, and so does a continuation line such as
+    /// `local n = 1 +` followed by `#arr`.
+    pub fn allow_hash_comments(mut self, allow: bool) -> Self {
+        self.hash_comments = allow;
+        self
+    }
+
+    /// Make integer literals that would lose precision as `f64` (beyond 2^53)
+    /// a syntax error rather than a warning.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Problems found while tokenizing that don't stop it, such as integer
+    /// literals rounded to the nearest `f64`.
+    pub fn warnings(&self) -> &[GroveError] {
+        &self.warnings
+    }
+
     pub fn tokenize(&mut self) -> GroveResult<Vec<Token>> {
+        if self.oversized {
+            return Err(GroveError::syntax(
+                format!("source too large (limit {} characters)", self.max_chars),
+                1, 1,
+            ));
+        }
         let mut tokens = Vec::new();
         loop {
-            let tok = self.next_token()?;
+            let mut tok = self.next_token()?;
+            tok.end_line = self.line;
+            tok.end_column = self.column;
             let is_eof = tok.kind == TokenKind::Eof;
+            if tokens.len() >= self.max_tokens {
+                return Err(GroveError::syntax(
+                    format!("too many tokens (limit {})", self.max_tokens),
+                    tok.line, tok.column,
+                ));
+            }
+            if !matches!(tok.kind, TokenKind::Comment(_)) {
+                self.mid_expression = tok.kind.expects_operand();
+            }
             tokens.push(tok);
             if is_eof { break; }
         }
@@ -115,24 +256,37 @@ impl Lexer {
     }
 
     fn peek(&self) -> char {
-        if self.pos < self.source.len() {
-            self.source[self.pos]
-        } else {
-            '\0'
-        }
+        self.rest().chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.pos + 1 < self.source.len() {
-            self.source[self.pos + 1]
-        } else {
-            '\0'
+        self.rest().chars().nth(1).unwrap_or('\0')
+    }
+
+    /// Whether a comment starts at `pos`: `--`, or a `#` starting a line
+    /// outside an unfinished expression when hash comments are allowed.
+    fn at_comment(&self) -> bool {
+        match self.peek() {
+            '-' => self.peek_next() == '-',
+            '#' => self.hash_comments && !self.mid_expression && self.at_line_start(),
+            _ => false,
         }
     }
 
+    /// Whether only whitespace precedes `pos` on its line.
+    fn at_line_start(&self) -> bool {
+        let before = &self.source[..self.pos];
+        before[before.rfind('\n').map_or(0, |i| i + 1)..].trim().is_empty()
+    }
+
+    /// Source from `pos` on; empty once past the end.
+    fn rest(&self) -> &'a str {
+        self.source.get(self.pos..).unwrap_or("")
+    }
+
     fn advance(&mut self) -> char {
         let ch = self.peek();
-        self.pos += 1;
+        self.pos += ch.len_utf8();
         if ch == '\n' {
             self.line += 1;
             self.column = 1;
@@ -142,14 +296,31 @@ impl Lexer {
         ch
     }
 
+    /// Newlines are plain whitespace, so an expression continues across lines
+    /// for as long as the parser needs more input (`1 +\n 2` is one expression).
+    /// A `\` right before a newline is also accepted as an explicit line
+    /// continuation for authors who want to mark the break.
     fn skip_whitespace_and_comments(&mut self) {
         loop {
-            // Skip whitespace
-            while self.pos < self.source.len() && self.peek().is_ascii_whitespace() {
-                self.advance();
+            // Skip whitespace and `\` line continuations
+            loop {
+                if self.pos < self.source.len() && self.peek().is_ascii_whitespace() {
+                    self.advance();
+                } else if self.peek() == '\\' && self.peek_next() == '\n' {
+                    self.advance();
+                    self.advance();
+                } else if self.peek() == '\\' && self.peek_next() == '\r'
+                    && self.source.as_bytes().get(self.pos + 2) == Some(&b'\n')
+                {
+                    self.advance();
+                    self.advance();
+                    self.advance();
+                } else {
+                    break;
+                }
             }
-            // Skip single-line comments: --
-            if self.peek() == '-' && self.peek_next() == '-' {
+            // Skip single-line comments: -- (and # if enabled)
+            if self.at_comment() && !self.keep_comments {
                 while self.pos < self.source.len() && self.peek() != '\n' {
                     self.advance();
                 }
@@ -171,8 +342,18 @@ impl Lexer {
 
         let ch = self.peek();
 
-        // Numbers
-        if ch.is_ascii_digit() {
+        // Comments (only reached in keep-comments mode)
+        if self.at_comment() {
+            let start = self.pos;
+            while self.pos < self.source.len() && self.peek() != '\n' {
+                self.advance();
+            }
+            let text = &self.source[start..self.pos];
+            return Ok(Token::new(TokenKind::Comment(text.trim_end().to_string()), line, col));
+        }
+
+        // Numbers, including a leading-dot fraction like `.5`
+        if ch.is_ascii_digit() || (ch == '.' && self.peek_next().is_ascii_digit()) {
             return self.read_number(line, col);
         }
 
@@ -182,7 +363,7 @@ impl Lexer {
         }
 
         // Identifiers and keywords
-        if ch.is_ascii_alphabetic() || ch == '_' {
+        if is_ident_start(ch) {
             return self.read_identifier(line, col);
         }
 
@@ -207,6 +388,10 @@ impl Lexer {
             '.' => {
                 if self.peek() == '.' {
                     self.advance();
+                    if self.peek() == '.' {
+                        self.advance();
+                        return Ok(Token::new(TokenKind::DotDotDot, line, col));
+                    }
                     Ok(Token::new(TokenKind::DotDot, line, col))
                 } else {
                     Ok(Token::new(TokenKind::Dot, line, col))
@@ -226,7 +411,7 @@ impl Lexer {
                     Ok(Token::new(TokenKind::TildeEqual, line, col))
                 } else {
                     Err(GroveError::syntax(
-                        format!("unexpected character '~'"),
+                        "unexpected character '~'",
                         line, col,
                     ))
                 }
@@ -237,7 +422,7 @@ impl Lexer {
                     Ok(Token::new(TokenKind::NotEqual, line, col))
                 } else {
                     Err(GroveError::syntax(
-                        format!("unexpected character '!'"),
+                        "unexpected character '!'",
                         line, col,
                     ))
                 }
@@ -265,24 +450,108 @@ impl Lexer {
         }
     }
 
+    /// Decimal numbers, with `_` allowed between digits (`1_000.5`), and
+    /// `0x`/`0b` hex and binary integers.
     fn read_number(&mut self, line: usize, col: usize) -> GroveResult<Token> {
         let start = self.pos;
-        while self.pos < self.source.len() && self.peek().is_ascii_digit() {
-            self.advance();
+        if self.peek() == '0' && matches!(self.peek_next(), 'x' | 'X' | 'b' | 'B') {
+            return self.read_radix_number(line, col);
         }
+        self.skip_digits();
         if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance(); // consume '.'
-            while self.pos < self.source.len() && self.peek().is_ascii_digit() {
+            self.skip_digits();
+        } else if self.peek() == '.' {
+            // A trailing `.` makes `5.` a whole number, unless it starts `..`
+            // (`5..x` concatenates) or a field name.
+            let next = self.peek_next();
+            if next != '.' && !is_ident_start(next) {
                 self.advance();
             }
         }
-        let text: String = self.source[start..self.pos].iter().collect();
-        let value: f64 = text.parse().map_err(|_| {
+        let text = &self.source[start..self.pos];
+        check_separators(text, text, line, col)?;
+        let digits = text.replace('_', "");
+        let value: f64 = digits.parse().map_err(|_| {
             GroveError::syntax(format!("invalid number '{}'", text), line, col)
         })?;
+        if !digits.contains('.') {
+            // Too long for u128 is certainly too long for an exact f64.
+            let exact = digits.parse::<u128>().ok();
+            self.check_exact_integer(exact, value, text, line, col)?;
+        }
         Ok(Token::new(TokenKind::Number(value), line, col))
     }
 
+    fn skip_digits(&mut self) {
+        while self.peek().is_ascii_digit() || self.peek() == '_' {
+            self.advance();
+        }
+    }
+
+    /// `0x1F` / `0b1010`, positioned at the leading `0`.
+    fn read_radix_number(&mut self, line: usize, col: usize) -> GroveResult<Token> {
+        let start = self.pos;
+        self.advance(); // '0'
+        let (radix, kind) = match self.advance() {
+            'x' | 'X' => (16, "hex"),
+            _ => (2, "binary"),
+        };
+        let digits_start = self.pos;
+        // Take the whole alphanumeric run so `0x1g` is reported, not split.
+        while is_ident_continue(self.peek()) {
+            self.advance();
+        }
+        let text = &self.source[start..self.pos];
+        let digits = &self.source[digits_start..self.pos];
+        if let Some(bad) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+            return Err(GroveError::syntax(
+                format!("invalid digit '{}' in {} literal '{}'", bad, kind, text),
+                line, col,
+            ));
+        }
+        if !digits.chars().any(|c| c != '_') {
+            return Err(GroveError::syntax(
+                format!("{} literal requires at least one digit: '{}'", kind, text),
+                line, col,
+            ));
+        }
+        check_separators(digits, text, line, col)?;
+        let value = u64::from_str_radix(&digits.replace('_', ""), radix).map_err(|_| {
+            GroveError::syntax(format!("{} literal '{}' is too large", kind, text), line, col)
+        })?;
+        self.check_exact_integer(Some(value as u128), value as f64, text, line, col)?;
+        Ok(Token::new(TokenKind::Number(value as f64), line, col))
+    }
+
+    /// Warn, or in strict mode error, when integer literal `text` (exactly
+    /// `exact`, if that fits in a u128) rounds to a different `value`, which
+    /// would silently corrupt IDs and object handles.
+    fn check_exact_integer(
+        &mut self,
+        exact: Option<u128>,
+        value: f64,
+        text: &str,
+        line: usize,
+        col: usize,
+    ) -> GroveResult<()> {
+        if exact == Some(value as u128) {
+            return Ok(());
+        }
+        let err = GroveError::syntax(
+            format!(
+                "integer literal '{}' is too large to represent exactly (becomes {}); use a string for IDs",
+                text, value
+            ),
+            line, col,
+        );
+        if self.strict {
+            return Err(err);
+        }
+        self.warnings.push(err);
+        Ok(())
+    }
+
     fn read_string(&mut self, line: usize, col: usize) -> GroveResult<Token> {
         let quote = self.advance(); // consume opening quote
         let mut s = String::new();
@@ -305,6 +574,12 @@ impl Lexer {
                     '\\' => s.push('\\'),
                     '\'' => s.push('\''),
                     '"' => s.push('"'),
+                    // `\` before a newline continues the literal on the next
+                    // line without inserting the newline
+                    '\n' => {}
+                    '\r' if self.peek() == '\n' => {
+                        self.advance();
+                    }
                     _ => {
                         s.push('\\');
                         s.push(esc);
@@ -320,43 +595,93 @@ impl Lexer {
     fn read_identifier(&mut self, line: usize, col: usize) -> GroveResult<Token> {
         let start = self.pos;
         while self.pos < self.source.len()
-            && (self.peek().is_ascii_alphanumeric() || self.peek() == '_')
+            && is_ident_continue(self.peek())
         {
             self.advance();
         }
-        let text: String = self.source[start..self.pos].iter().collect();
-        let kind = match text.as_str() {
-            "local" => TokenKind::Local,
-            "let" => TokenKind::Let,
-            "fn" => TokenKind::Fn,
-            "blueprint" => TokenKind::Blueprint,
-            "build" => TokenKind::Build,
-            "end" => TokenKind::End,
-            "if" => TokenKind::If,
-            "then" => TokenKind::Then,
-            "elseif" => TokenKind::ElseIf,
-            "else" => TokenKind::Else,
-            "for" => TokenKind::For,
-            "in" => TokenKind::In,
-            "do" => TokenKind::Do,
-            "while" => TokenKind::While,
-            "repeat" => TokenKind::Repeat,
-            "until" => TokenKind::Until,
-            "return" => TokenKind::Return,
-            "break" => TokenKind::Break,
-            "continue" => TokenKind::Continue,
-            "and" => TokenKind::And,
-            "or" => TokenKind::Or,
-            "not" => TokenKind::Not,
-            "true" => TokenKind::True,
-            "false" => TokenKind::False,
-            "nil" => TokenKind::Nil,
-            _ => TokenKind::Identifier(text),
-        };
+        let text = self.source[start..self.pos].to_string();
+        let kind = keyword(&text).unwrap_or(TokenKind::Identifier(text));
         Ok(Token::new(kind, line, col))
     }
 }
 
+/// Check the `_` separators in `digits`, reporting errors against the whole
+/// literal `text`.
+fn check_separators(digits: &str, text: &str, line: usize, col: usize) -> GroveResult<()> {
+    if digits.contains("__") {
+        return Err(GroveError::syntax(
+            format!("number has consecutive underscores: '{}'", text),
+            line, col,
+        ));
+    }
+    if digits.ends_with('_') || digits.contains("_.") {
+        return Err(GroveError::syntax(
+            format!("underscore must be followed by a digit: '{}'", text),
+            line, col,
+        ));
+    }
+    Ok(())
+}
+
+/// The keyword token for `word`, or `None` if it's an ordinary identifier.
+fn keyword(word: &str) -> Option<TokenKind> {
+    let kind = match word {
+        "local" => TokenKind::Local,
+        "let" => TokenKind::Let,
+        "const" => TokenKind::Const,
+        "fn" => TokenKind::Fn,
+        "blueprint" => TokenKind::Blueprint,
+        "build" => TokenKind::Build,
+        "end" => TokenKind::End,
+        "if" => TokenKind::If,
+        "then" => TokenKind::Then,
+        "elseif" => TokenKind::ElseIf,
+        "else" => TokenKind::Else,
+        "for" => TokenKind::For,
+        "in" => TokenKind::In,
+        "do" => TokenKind::Do,
+        "while" => TokenKind::While,
+        "repeat" => TokenKind::Repeat,
+        "until" => TokenKind::Until,
+        "return" => TokenKind::Return,
+        "break" => TokenKind::Break,
+        "continue" => TokenKind::Continue,
+        "pass" => TokenKind::Pass,
+        "and" => TokenKind::And,
+        "or" => TokenKind::Or,
+        "not" => TokenKind::Not,
+        "true" => TokenKind::True,
+        "false" => TokenKind::False,
+        "nil" => TokenKind::Nil,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Whether `name` is a Grove keyword and so can't be used as an identifier.
+pub fn is_reserved_keyword(name: &str) -> bool {
+    keyword(name).is_some()
+}
+
+/// Identifiers start with a letter (any script, so `café` and
+/// `местоположение` work) or `_`...
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+/// ...and continue with letters, digits or `_`.
+fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `name` lexes as a single identifier that isn't a keyword.
+pub fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if is_ident_start(c))
+        && chars.all(is_ident_continue)
+        && !is_reserved_keyword(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -435,9 +760,225 @@ mod tests {
     }
 
     #[test]
+    fn test_line_continuation() {
+        let mut lex = Lexer::new("1 + \\\n2 \\\r\n+ 3");
+        let tokens = lex.tokenize().unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::Number(1.0), TokenKind::Plus, TokenKind::Number(2.0),
+            TokenKind::Plus, TokenKind::Number(3.0), TokenKind::Eof,
+        ]);
+        assert_eq!(tokens[2].line, 2);
+        assert_eq!(tokens[4].line, 3);
+
+        // A stray backslash is still an error
+        assert!(Lexer::new("1 \\ 2").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        let mut lex = Lexer::new("--- Builds a tree\nblueprint tree() end -- trailing").keep_comments(true);
+        let tokens = lex.tokenize().unwrap();
+        assert_eq!(tokens[0].kind, TokenKind::Comment("--- Builds a tree".to_string()));
+        assert_eq!(tokens[1].kind, TokenKind::Blueprint);
+        assert_eq!(tokens[1].line, 2);
+        assert_eq!(tokens[6].kind, TokenKind::Comment("-- trailing".to_string()));
+
+        // Default mode still drops them
+        let tokens = Lexer::new("--- doc\nx").tokenize().unwrap();
+        assert!(matches!(&tokens[0].kind, TokenKind::Identifier(s) if s == "x"));
+    }
+
+    #[test]
+    fn test_hash_comments() {
+        let src = "# setup\nlocal arr = [1, 2]\n  # indented\nlog(#arr)";
+        let kinds = |lex: &mut Lexer| -> Vec<TokenKind> {
+            lex.tokenize().unwrap().into_iter().map(|t| t.kind).collect()
+        };
+        let tokens = kinds(&mut Lexer::new(src).allow_hash_comments(true));
+        assert_eq!(tokens[0], TokenKind::Local);
+        assert_eq!(tokens.iter().filter(|k| **k == TokenKind::Hash).count(), 1);
+        let hash = tokens.iter().position(|k| *k == TokenKind::Hash).unwrap();
+        assert!(matches!(&tokens[hash + 1], TokenKind::Identifier(s) if s == "arr"));
+
+        let tokens = kinds(&mut Lexer::new(src).allow_hash_comments(true).keep_comments(true));
+        assert_eq!(tokens[0], TokenKind::Comment("# setup".to_string()));
+        assert!(tokens.contains(&TokenKind::Comment("# indented".to_string())));
+
+        // Mid-expression a line-leading `#` is the length operator
+        let src = "local n = 1 +\n#arr\nf(\n  #arr)";
+        let tokens = kinds(&mut Lexer::new(src).allow_hash_comments(true));
+        assert_eq!(tokens.iter().filter(|k| **k == TokenKind::Hash).count(), 2);
+
+        // Off by default: `#` is always the length operator
+        assert_eq!(kinds(&mut Lexer::new("# x"))[0], TokenKind::Hash);
+    }
+
+    #[test]
+    fn test_source_limits() {
+        let mut lex = Lexer::with_limits("local x = 1", 5, usize::MAX);
+        let err = lex.tokenize().unwrap_err();
+        assert_eq!(err.kind, crate::error::ErrorKind::Syntax);
+        assert!(err.message.contains("source too large"));
+
+        // local, x, =, 1, EOF — five tokens
+        let mut lex = Lexer::with_limits("local x = 1", usize::MAX, 4);
+        let err = lex.tokenize().unwrap_err();
+        assert!(err.message.contains("too many tokens"));
+
+        let mut lex = Lexer::with_limits("local x = 1", 11, 5);
+        assert_eq!(lex.tokenize().unwrap().len(), 5);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
     fn test_float_number() {
         let mut lex = Lexer::new("3.14");
         let tokens = lex.tokenize().unwrap();
         assert!(matches!(tokens[0].kind, TokenKind::Number(n) if (n - 3.14).abs() < 1e-10));
     }
+
+    #[test]
+    fn test_leading_and_trailing_dot_numbers() {
+        let kinds = |src: &str| -> Vec<TokenKind> {
+            Lexer::new(src).tokenize().unwrap().into_iter().map(|t| t.kind).collect()
+        };
+        assert_eq!(kinds(".5"), vec![TokenKind::Number(0.5), TokenKind::Eof]);
+        assert_eq!(kinds("5."), vec![TokenKind::Number(5.0), TokenKind::Eof]);
+        assert_eq!(kinds("x = 5. + .25"), vec![
+            TokenKind::Identifier("x".into()), TokenKind::Assign, TokenKind::Number(5.0),
+            TokenKind::Plus, TokenKind::Number(0.25), TokenKind::Eof,
+        ]);
+        assert_eq!(kinds("a..b"), vec![
+            TokenKind::Identifier("a".into()), TokenKind::DotDot, TokenKind::Identifier("b".into()), TokenKind::Eof,
+        ]);
+        assert_eq!(kinds("a.b"), vec![
+            TokenKind::Identifier("a".into()), TokenKind::Dot, TokenKind::Identifier("b".into()), TokenKind::Eof,
+        ]);
+        assert_eq!(kinds("5..a"), vec![
+            TokenKind::Number(5.0), TokenKind::DotDot, TokenKind::Identifier("a".into()), TokenKind::Eof,
+        ]);
+        assert_eq!(kinds("f(...a)"), vec![
+            TokenKind::Identifier("f".into()), TokenKind::LeftParen, TokenKind::DotDotDot,
+            TokenKind::Identifier("a".into()), TokenKind::RightParen, TokenKind::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_string_line_continuation() {
+        let tokens = Lexer::new("x = \"foo\\\nbar\"\ny").tokenize().unwrap();
+        assert_eq!(tokens[2].kind, TokenKind::StringLit("foobar".into()));
+        assert_eq!(tokens[3].kind, TokenKind::Identifier("y".into()));
+        assert_eq!(tokens[3].line, 3);
+
+        let tokens = Lexer::new("\"foo\\\r\nbar\"").tokenize().unwrap();
+        assert_eq!(tokens[0].kind, TokenKind::StringLit("foobar".into()));
+    }
+
+    #[test]
+    fn test_large_source_matches_line_by_line() {
+        // A megabyte-scale generated script, tokenized whole, must give the
+        // same tokens as lexing each line on its own.
+        let line = "local tree_7 = spawn(\"ßirch ✓\", vec3(1.5, .25, 3.)) -- ünïcode note";
+        let source = vec![line; 20_000].join("\n");
+        assert!(source.len() > 1_000_000);
+
+        let whole = Lexer::new(&source).keep_comments(true).tokenize().unwrap();
+        let single = Lexer::new(line).keep_comments(true).tokenize().unwrap();
+        let per_line = single.len() - 1; // without EOF
+        assert_eq!(whole.len(), per_line * 20_000 + 1);
+        for (i, tok) in whole[..whole.len() - 1].iter().enumerate() {
+            let expected = &single[i % per_line];
+            assert_eq!(tok.kind, expected.kind);
+            assert_eq!(tok.line, i / per_line + 1);
+            assert_eq!(tok.column, expected.column);
+        }
+        assert!(single.iter().any(|t| t.kind == TokenKind::StringLit("ßirch ✓".into())));
+    }
+
+    #[test]
+    fn test_reserved_keywords() {
+        for kw in ["fn", "if", "while", "pass", "nil", "const"] {
+            assert!(is_reserved_keyword(kw), "{}", kw);
+            assert!(!is_valid_identifier(kw), "{}", kw);
+        }
+        assert!(!is_reserved_keyword("spawn"));
+        assert!(is_valid_identifier("spawn"));
+        assert!(is_valid_identifier("_tmp2"));
+        assert!(is_valid_identifier("ünï"));
+        for bad in ["", "2d", "game.spawn", "has space", "🌲"] {
+            assert!(!is_valid_identifier(bad), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let tokens = Lexer::new("local местоположение = café + x_é2").tokenize().unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::Identifier("местоположение".into()));
+        assert_eq!(tokens[3].kind, TokenKind::Identifier("café".into()));
+        assert_eq!(tokens[5].kind, TokenKind::Identifier("x_é2".into()));
+        assert_eq!(tokens[5].column, 31);
+
+        // Keywords only match exactly
+        let tokens = Lexer::new("ïf IF").tokenize().unwrap();
+        assert_eq!(tokens[0].kind, TokenKind::Identifier("ïf".into()));
+        assert_eq!(tokens[1].kind, TokenKind::Identifier("IF".into()));
+    }
+
+    #[test]
+    fn test_radix_and_separator_numbers() {
+        let number = |src: &str| match Lexer::new(src).tokenize().unwrap()[0].kind {
+            TokenKind::Number(n) => n,
+            ref other => panic!("expected number, got {:?}", other),
+        };
+        assert_eq!(number("0xFF"), 255.0);
+        assert_eq!(number("0X1f"), 31.0);
+        assert_eq!(number("0b1010"), 10.0);
+        assert_eq!(number("0xFF_FF"), 65535.0);
+        assert_eq!(number("1_000_000"), 1_000_000.0);
+        assert_eq!(number("1_000.25"), 1000.25);
+        assert_eq!(number("0"), 0.0);
+    }
+
+    #[test]
+    fn test_malformed_number_messages() {
+        let err = |src: &str| Lexer::new(src).tokenize().unwrap_err().message;
+        assert_eq!(err("0x"), "hex literal requires at least one digit: '0x'");
+        assert_eq!(err("0b_"), "binary literal requires at least one digit: '0b_'");
+        assert_eq!(err("0x1g"), "invalid digit 'g' in hex literal '0x1g'");
+        assert_eq!(err("0b102"), "invalid digit '2' in binary literal '0b102'");
+        assert_eq!(err("1__0"), "number has consecutive underscores: '1__0'");
+        assert_eq!(err("0xF__F"), "number has consecutive underscores: '0xF__F'");
+        assert_eq!(err("10_"), "underscore must be followed by a digit: '10_'");
+        assert_eq!(err("1_.5"), "underscore must be followed by a digit: '1_.5'");
+        assert_eq!(err("0x1_0000_0000_0000_0000"), "hex literal '0x1_0000_0000_0000_0000' is too large");
+    }
+
+    #[test]
+    fn test_token_categories() {
+        let tokens = tokenize_with_spans("local x = f(\"a\", 1) and nil -- hi").unwrap();
+        let categories: Vec<TokenCategory> = tokens.iter().map(|t| t.kind.category()).collect();
+        use TokenCategory::*;
+        assert_eq!(categories, vec![
+            Keyword, Identifier, Operator, Identifier, Punctuation, Literal, Punctuation,
+            Literal, Punctuation, Keyword, Literal, Comment, Punctuation,
+        ]);
+        assert_eq!((tokens[3].line, tokens[3].column), (1, 11));
+    }
+
+    #[test]
+    fn test_imprecise_integer_literals() {
+        let mut lexer = Lexer::new("x = 9007199254740992\ny = 9007199254740993");
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[5].kind, TokenKind::Number(9007199254740992.0));
+        assert_eq!(lexer.warnings().len(), 1);
+        assert_eq!(lexer.warnings()[0].line, 2);
+        assert!(lexer.warnings()[0].message.starts_with("integer literal '9007199254740993' is too large"));
+
+        assert!(Lexer::new("1.5 + 9007199254740992").strict(true).tokenize().is_ok());
+        for src in ["9007199254740993", "0x20000000000001", "123456789012345678901234567890123456789012"] {
+            let err = Lexer::new(src).strict(true).tokenize().unwrap_err();
+            assert_eq!(err.kind, crate::error::ErrorKind::Syntax, "{}", src);
+        }
+    }
 }
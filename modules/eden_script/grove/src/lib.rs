@@ -4,14 +4,22 @@ pub mod lexer;
 pub mod ast;
 pub mod parser;
 pub mod environment;
+pub mod builtins;
 pub mod interpreter;
+pub mod rng;
+pub mod blob;
 
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_void};
 use std::ptr;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
 
-use crate::interpreter::{HostFn, Interpreter};
-use crate::lexer::Lexer;
+use crate::error::ErrorKind;
+use crate::interpreter::{HostFn, Interpreter, LogLevel};
+use crate::lexer::{is_reserved_keyword, is_valid_identifier};
 use crate::parser::Parser;
 use crate::types::Value;
 
@@ -26,6 +34,9 @@ pub enum GroveValueTag {
     String = 3,
     Vec3 = 4,
     Object = 5,
+    /// Raw bytes, in `string_val` but not necessarily UTF-8.
+    Bytes = 6,
+    Color = 7,
 }
 
 #[repr(C)]
@@ -43,6 +54,15 @@ pub struct GroveVec3Val {
     pub z: f64,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GroveColorVal {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    pub a: f64,
+}
+
 #[repr(C)]
 pub union GroveValueData {
     pub bool_val: i32,
@@ -50,6 +70,7 @@ pub union GroveValueData {
     pub string_val: GroveStringVal,
     pub vec3_val: GroveVec3Val,
     pub object_handle: u64,
+    pub color_val: GroveColorVal,
 }
 
 #[repr(C)]
@@ -58,6 +79,28 @@ pub struct GroveValue {
     pub data: GroveValueData,
 }
 
+/// Object namer callback type from C: returns a null-terminated display name
+/// for `handle`, or null to fall back to `object`.
+pub type GroveObjectNamer = extern "C" fn(handle: u64, userdata: *mut c_void) -> *const c_char;
+
+/// Trace callback type from C, called with the position of each statement.
+pub type GroveTraceFn = extern "C" fn(line: u32, column: u32, userdata: *mut c_void);
+
+/// Log callback type from C: a `GROVE_LOG_*` level and a null-terminated
+/// message, valid only for the duration of the call.
+pub type GroveLogFn = extern "C" fn(level: i32, message: *const c_char, userdata: *mut c_void);
+
+/// Limit callback type from C: a `GROVE_LIMIT_*` code and where the script
+/// was stopped.
+pub type GroveLimitFn = extern "C" fn(kind: i32, line: u32, column: u32, userdata: *mut c_void);
+
+/// Import resolver type from C: returns the null-terminated source of module
+/// `name`, or null if there is none.
+pub type GroveImportResolver = extern "C" fn(name: *const c_char, userdata: *mut c_void) -> *const c_char;
+
+/// Userdata destructor from C, see `grove_register_fn_with_destructor`.
+pub type GroveDestroyFn = extern "C" fn(userdata: *mut c_void);
+
 /// Host function callback type from C.
 pub type GroveHostFn = extern "C" fn(
     args: *const GroveValue,
@@ -66,14 +109,138 @@ pub type GroveHostFn = extern "C" fn(
     userdata: *mut c_void,
 ) -> i32;
 
+/// Callback run by `grove_shared_with` while holding the shared VM's lock.
+pub type GroveSharedFn = extern "C" fn(vm: *mut GroveVm, userdata: *mut c_void) -> i32;
+
 // ── VM struct ───────────────────────────────────────
 
+/// A VM is single-threaded: it must only be used by one thread at a time and
+/// not re-entered from its own host callbacks. Debug builds assert this in
+/// `grove_eval` and `grove_call_fn`. Hosts that share a VM between threads
+/// should use `GroveVmShared` instead.
 pub struct GroveVm {
     interp: Interpreter,
     last_error: Option<CString>,
     last_error_line: u32,
     /// Temporary storage for strings returned via FFI
     _temp_strings: Vec<CString>,
+    /// Temporary storage for bytes values, cleared along with `_temp_strings`
+    _temp_bytes: Vec<Vec<u8>>,
+    /// Scopes at the failing statement of the last `grove_eval`, as JSON
+    error_locals_json: Option<CString>,
+    /// Set while a script runs, see `EntryGuard`
+    in_use: AtomicBool,
+    /// The last blob from `grove_save_blueprint_blob`
+    blueprint_blob: Vec<u8>,
+}
+
+/// Marks a VM as running a script until dropped, so debug builds catch a VM
+/// used from two threads at once or re-entered from a host callback, either
+/// of which would corrupt it. The VM is used through the guard meanwhile.
+struct EntryGuard<'a>(&'a mut GroveVm);
+
+impl<'a> EntryGuard<'a> {
+    fn new(vm: &'a mut GroveVm) -> Self {
+        let was_in_use = vm.in_use.swap(true, Ordering::Acquire);
+        debug_assert!(
+            !was_in_use,
+            "GroveVm entered while already running a script; VMs are single-threaded and not re-entrant"
+        );
+        Self(vm)
+    }
+}
+
+impl Deref for EntryGuard<'_> {
+    type Target = GroveVm;
+
+    fn deref(&self) -> &GroveVm {
+        self.0
+    }
+}
+
+impl DerefMut for EntryGuard<'_> {
+    fn deref_mut(&mut self) -> &mut GroveVm {
+        self.0
+    }
+}
+
+impl Drop for EntryGuard<'_> {
+    fn drop(&mut self) {
+        self.0.in_use.store(false, Ordering::Release);
+    }
+}
+
+/// A VM behind a mutex, for hosts that use one VM from several threads.
+/// Every `grove_shared_*` call holds the lock for its whole duration.
+pub struct GroveVmShared {
+    vm: Mutex<SendVm>,
+}
+
+struct SendVm(Box<GroveVm>);
+
+// SAFETY: `GroveVm` is not `Send` because its interpreter holds `Rc`s (host
+// functions, closures inside `Value`s, compiled programs) and non-`Send`
+// boxed closures (the import resolver and the trace, log and limit hooks).
+// Moving the whole VM to another thread is sound only while no clone of any
+// of those `Rc`s lives outside the VM: FFI VMs are never forked, and values
+// cross the C boundary as plain copies, never as `Rc` handles. The mutex then
+// ensures one thread uses the VM at a time. The boxed closures wrap C
+// callbacks and their userdata, which must therefore tolerate being called
+// from whichever thread holds the lock; that is the host's to guarantee.
+unsafe impl Send for SendVm {}
+
+impl GroveVm {
+    /// Copy `s` into temp storage and return a view that stays valid until
+    /// the temp strings are next cleared.
+    fn temp_string(&mut self, s: &str) -> GroveStringVal {
+        let owned = CString::new(s).unwrap_or_else(|e| {
+            let pos = e.nul_position();
+            CString::new(&s.as_bytes()[..pos]).unwrap()
+        });
+        let val = GroveStringVal {
+            ptr: owned.as_ptr(),
+            len: owned.as_bytes().len() as u32,
+        };
+        self._temp_strings.push(owned);
+        val
+    }
+
+    /// Copy `bytes` into temp storage, see `temp_string`. Unlike strings,
+    /// bytes are kept whole, including any NULs.
+    fn temp_bytes(&mut self, bytes: &[u8]) -> GroveStringVal {
+        let owned = bytes.to_vec();
+        let val = GroveStringVal {
+            ptr: owned.as_ptr() as *const c_char,
+            len: owned.len() as u32,
+        };
+        self._temp_bytes.push(owned);
+        val
+    }
+
+    /// Drop the temp strings and bytes. Every entry point that hands out
+    /// pointers into them clears them first (or, for those that take made
+    /// values as arguments, once the arguments are copied), so a host that
+    /// only evals or reads commands doesn't grow them without bound.
+    fn clear_temp(&mut self) {
+        self._temp_strings.clear();
+        self._temp_bytes.clear();
+    }
+
+    /// Whether scripts could refer to a host function called `name`. If not,
+    /// the reason is recorded as the last error.
+    fn check_script_name(&mut self, name: &str) -> bool {
+        if is_valid_identifier(name) {
+            return true;
+        }
+        let msg = if is_reserved_keyword(name) {
+            format!("cannot register '{}': it is a reserved keyword", name)
+        } else {
+            format!("cannot register '{}': not a valid identifier", name)
+        };
+        self.last_error = CString::new(msg).ok();
+        self.last_error_line = 0;
+        false
+    }
 }
 
 // ── Conversion helpers ──────────────────────────────
@@ -93,11 +260,23 @@ fn grove_value_to_value(gv: &GroveValue) -> Value {
                     Value::String(String::from_utf8_lossy(slice).into_owned())
                 }
             }
+            GroveValueTag::Bytes => {
+                let sv = &gv.data.string_val;
+                if sv.ptr.is_null() {
+                    Value::Bytes(Vec::new())
+                } else {
+                    Value::Bytes(std::slice::from_raw_parts(sv.ptr as *const u8, sv.len as usize).to_vec())
+                }
+            }
             GroveValueTag::Vec3 => {
                 let v = &gv.data.vec3_val;
                 Value::Vec3(v.x, v.y, v.z)
             }
             GroveValueTag::Object => Value::Object(gv.data.object_handle),
+            GroveValueTag::Color => {
+                let c = &gv.data.color_val;
+                Value::Color(c.r, c.g, c.b, c.a)
+            }
         }
     }
 }
@@ -129,6 +308,15 @@ fn value_to_grove_value(val: &Value) -> GroveValue {
                 },
             }
         }
+        Value::Bytes(b) => GroveValue {
+            tag: GroveValueTag::Bytes,
+            data: GroveValueData {
+                string_val: GroveStringVal {
+                    ptr: b.as_ptr() as *const c_char,
+                    len: b.len() as u32,
+                },
+            },
+        },
         Value::Vec3(x, y, z) => GroveValue {
             tag: GroveValueTag::Vec3,
             data: GroveValueData {
@@ -139,6 +327,12 @@ fn value_to_grove_value(val: &Value) -> GroveValue {
             tag: GroveValueTag::Object,
             data: GroveValueData { object_handle: *handle },
         },
+        Value::Color(r, g, b, a) => GroveValue {
+            tag: GroveValueTag::Color,
+            data: GroveValueData {
+                color_val: GroveColorVal { r: *r, g: *g, b: *b, a: *a },
+            },
+        },
         // Array and Table don't have FFI representation — return Nil
         _ => GroveValue {
             tag: GroveValueTag::Nil,
@@ -149,6 +343,47 @@ fn value_to_grove_value(val: &Value) -> GroveValue {
 
 // ── C FFI exports ───────────────────────────────────
 
+/// Language features this build supports, as queried by `grove_has_feature`.
+const FEATURES: &[&str] = &[
+    "const",
+    "freeze",
+    "generic_for",
+    "first_class_functions",
+    "partial",
+    "spread_args",
+    "keyword_args",
+    "namespaces",
+    "seeded_random",
+    "noise",
+    "commands",
+    "eval",
+    "closures",
+    "bytes",
+    "import",
+    "color",
+];
+
+/// Version of this Grove build as a static null-terminated string.
+#[no_mangle]
+pub extern "C" fn grove_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// 1 if this build supports the language feature `name` (e.g. "keyword_args"),
+/// 0 if it doesn't or `name` is null.
+///
+/// # Safety
+/// `name` must be null or a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn grove_has_feature(name: *const c_char) -> i32 {
+    if name.is_null() { return 0; }
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(s) => s,
+        Err(_) => return 0,
+    };
+    FEATURES.contains(&name) as i32
+}
+
 #[no_mangle]
 pub extern "C" fn grove_new() -> *mut GroveVm {
     let vm = Box::new(GroveVm {
@@ -156,10 +391,18 @@ pub extern "C" fn grove_new() -> *mut GroveVm {
         last_error: None,
         last_error_line: 0,
         _temp_strings: Vec::new(),
+        _temp_bytes: Vec::new(),
+        error_locals_json: None,
+        in_use: AtomicBool::new(false),
+        blueprint_blob: Vec::new(),
     });
     Box::into_raw(vm)
 }
 
+/// Destroy a VM created by `grove_new`.
+///
+/// # Safety
+/// `vm` must be null or a pointer returned by `grove_new` that has not been destroyed.
 #[no_mangle]
 pub unsafe extern "C" fn grove_destroy(vm: *mut GroveVm) {
     if !vm.is_null() {
@@ -167,31 +410,92 @@ pub unsafe extern "C" fn grove_destroy(vm: *mut GroveVm) {
     }
 }
 
+/// Lex, parse and execute `source`. Returns 0 on success, -1 on error.
+///
+/// # Safety
+/// `vm` must be a live VM and `source` a valid null-terminated string.
 #[no_mangle]
 pub unsafe extern "C" fn grove_eval(vm: *mut GroveVm, source: *const c_char) -> i32 {
+    if vm.is_null() || source.is_null() {
+        return -1;
+    }
+    match eval_source(&mut *vm, source) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// `grove_eval` that also hands back what the script returned with a
+/// top-level `return`, as modules do (`return {version = 1}`), or nil.
+/// `result` gets the value itself, with arrays and tables as nil since they
+/// have no `GroveValue` form; `json` (if non-null) gets it as JSON. Strings
+/// in either live in temp storage, see `clear_temp`.
+///
+/// # Safety
+/// `vm` must be a live VM, `source` a valid null-terminated string, and
+/// `result` and `json` each null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn grove_eval_value(
+    vm: *mut GroveVm,
+    source: *const c_char,
+    result: *mut GroveValue,
+    json: *mut *const c_char,
+) -> i32 {
     if vm.is_null() || source.is_null() {
         return -1;
     }
     let vm = &mut *vm;
+    let Some(val) = eval_source(vm, source) else { return -1 };
+    if !json.is_null() {
+        let mut text = String::new();
+        value_to_json(&val, &mut text);
+        *json = vm.temp_string(&text).ptr;
+    }
+    if !result.is_null() {
+        *result = match &val {
+            Value::String(s) => GroveValue {
+                tag: GroveValueTag::String,
+                data: GroveValueData { string_val: vm.temp_string(s) },
+            },
+            Value::Bytes(b) => GroveValue {
+                tag: GroveValueTag::Bytes,
+                data: GroveValueData { string_val: vm.temp_bytes(b) },
+            },
+            other => value_to_grove_value(other),
+        };
+    }
+    0
+}
+
+/// Shared body of `grove_eval` and `grove_eval_value`: the script's return
+/// value, or `None` with the error recorded on `vm`.
+unsafe fn eval_source(vm: &mut GroveVm, source: *const c_char) -> Option<Value> {
+    let mut entry = EntryGuard::new(vm);
+    let vm = &mut *entry;
+    vm.clear_temp();
+    vm.error_locals_json = None;
     let src = match CStr::from_ptr(source).to_str() {
         Ok(s) => s,
         Err(_) => {
             vm.last_error = Some(CString::new("invalid UTF-8 in source").unwrap());
             vm.last_error_line = 0;
-            return -1;
+            return None;
         }
     };
 
     // Lex
-    let mut lexer = Lexer::new(src);
+    let mut lexer = vm.interp.lexer(src);
     let tokens = match lexer.tokenize() {
         Ok(t) => t,
         Err(e) => {
             vm.last_error_line = e.line as u32;
             vm.last_error = CString::new(format!("{}", e)).ok();
-            return -1;
+            return None;
         }
     };
+    for warning in lexer.warnings() {
+        vm.interp.log(LogLevel::Warn, &warning.to_string());
+    }
 
     // Parse
     let mut parser = Parser::new(tokens);
@@ -200,25 +504,116 @@ pub unsafe extern "C" fn grove_eval(vm: *mut GroveVm, source: *const c_char) ->
         Err(e) => {
             vm.last_error_line = e.line as u32;
             vm.last_error = CString::new(format!("{}", e)).ok();
-            return -1;
+            return None;
         }
     };
 
     // Execute
     match vm.interp.execute(&program) {
-        Ok(_) => {
+        Ok(val) => {
             vm.last_error = None;
             vm.last_error_line = 0;
-            0
+            Some(val)
         }
         Err(e) => {
             vm.last_error_line = e.line as u32;
             vm.last_error = CString::new(format!("{}", e)).ok();
-            -1
+            vm.error_locals_json = vm.interp.error_scopes()
+                .and_then(|scopes| CString::new(scopes_to_json(scopes)).ok());
+            None
+        }
+    }
+}
+
+/// Render scopes as a JSON array of objects, global scope first, with keys
+/// sorted so the output is stable.
+fn scopes_to_json(scopes: &[HashMap<String, String>]) -> String {
+    let mut out = String::from("[");
+    for (i, scope) in scopes.iter().enumerate() {
+        if i > 0 { out.push(','); }
+        out.push('{');
+        let mut names: Vec<&String> = scope.keys().collect();
+        names.sort();
+        for (j, name) in names.into_iter().enumerate() {
+            if j > 0 { out.push(','); }
+            json_quote(name, &mut out);
+            out.push(':');
+            json_quote(&scope[name], &mut out);
+        }
+        out.push('}');
+    }
+    out.push(']');
+    out
+}
+
+/// Render a script value as JSON. Tables become objects with sorted keys,
+/// arrays and vec3s become arrays, objects their handle; functions are
+/// rendered by name and non-finite numbers as null.
+fn value_to_json(val: &Value, out: &mut String) {
+    match val {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_finite() => out.push_str(&n.to_string()),
+        Value::Number(_) => out.push_str("null"),
+        Value::String(s) => json_quote(s, out),
+        Value::Vec3(x, y, z) => {
+            value_to_json(&Value::array(vec![Value::Number(*x), Value::Number(*y), Value::Number(*z)]), out)
+        }
+        Value::Bytes(b) => {
+            let items = b.iter().map(|&byte| Value::Number(byte as f64)).collect();
+            value_to_json(&Value::array(items), out)
+        }
+        Value::Color(r, g, b, a) => {
+            let rgba = [r, g, b, a].map(|c| Value::Number(*c));
+            value_to_json(&Value::array(rgba.to_vec()), out)
+        }
+        Value::Array { items, .. } => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 { out.push(','); }
+                value_to_json(item, out);
+            }
+            out.push(']');
+        }
+        Value::Table { fields: map, .. } => {
+            out.push('{');
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 { out.push(','); }
+                json_quote(key, out);
+                out.push(':');
+                value_to_json(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Object(handle) => out.push_str(&handle.to_string()),
+        Value::Function(func) => json_quote(func.name(), out),
+    }
+}
+
+fn json_quote(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
         }
     }
+    out.push('"');
 }
 
+/// Register a C host function under `name`. Returns -1, with the reason in
+/// `grove_last_error`, if `name` is a keyword or not a valid identifier.
+///
+/// # Safety
+/// `vm` must be a live VM and `name` a valid null-terminated string.
+/// `userdata` must stay valid for as long as the function can be called.
 #[no_mangle]
 pub unsafe extern "C" fn grove_register_fn(
     vm: *mut GroveVm,
@@ -226,21 +621,317 @@ pub unsafe extern "C" fn grove_register_fn(
     callback: GroveHostFn,
     userdata: *mut c_void,
 ) -> i32 {
+    grove_register_fn_with_destructor(vm, name, callback, userdata, None)
+}
+
+/// `grove_register_fn`, handing ownership of `userdata` to the VM: once
+/// registration succeeds, `destroy(userdata)` runs when the function goes
+/// away, whether replaced by another registration under `name`, dropped by
+/// `grove_reset` or destroyed with the VM. If registration fails, `userdata`
+/// stays with the caller.
+///
+/// # Safety
+/// `vm` must be a live VM and `name` a valid null-terminated string.
+/// `userdata` must stay valid until `destroy` is called with it.
+#[no_mangle]
+pub unsafe extern "C" fn grove_register_fn_with_destructor(
+    vm: *mut GroveVm,
+    name: *const c_char,
+    callback: GroveHostFn,
+    userdata: *mut c_void,
+    destroy: Option<GroveDestroyFn>,
+) -> i32 {
+    if vm.is_null() || name.is_null() {
+        return -1;
+    }
+    let vm = &mut *vm;
+    let name_str = match CStr::from_ptr(name).to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return -1,
+    };
+    if !vm.check_script_name(&name_str) {
+        return -1;
+    }
+
+    let host_fn = wrap_host_fn(format!("host function '{}'", name_str), callback, userdata, destroy);
+    vm.interp.register_fn(&name_str, host_fn);
+    0
+}
+
+/// Create an empty global table `name` to hold host functions registered
+/// with `grove_register_namespaced_fn`. Returns 0 on success, -1 on error.
+///
+/// # Safety
+/// `vm` must be a live VM and `name` a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn grove_register_namespace(vm: *mut GroveVm, name: *const c_char) -> i32 {
     if vm.is_null() || name.is_null() {
         return -1;
     }
     let vm = &mut *vm;
     let name_str = match CStr::from_ptr(name).to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+    if !vm.check_script_name(name_str) {
+        return -1;
+    }
+    vm.interp.register_namespace(name_str);
+    0
+}
+
+/// Register a C host function callable from scripts as `ns.fn_name(...)`.
+/// Returns -1 if `ns` wasn't created with `grove_register_namespace`.
+///
+/// # Safety
+/// `vm` must be a live VM; `ns` and `fn_name` must be valid null-terminated
+/// strings. `userdata` must stay valid for as long as the function can be called.
+#[no_mangle]
+pub unsafe extern "C" fn grove_register_namespaced_fn(
+    vm: *mut GroveVm,
+    ns: *const c_char,
+    fn_name: *const c_char,
+    callback: GroveHostFn,
+    userdata: *mut c_void,
+) -> i32 {
+    if vm.is_null() || ns.is_null() || fn_name.is_null() {
+        return -1;
+    }
+    let vm = &mut *vm;
+    let (ns_str, name_str) = match (CStr::from_ptr(ns).to_str(), CStr::from_ptr(fn_name).to_str()) {
+        (Ok(ns), Ok(name)) => (ns, name),
+        _ => return -1,
+    };
+    if !vm.check_script_name(name_str) {
+        return -1;
+    }
+    let host_fn = wrap_host_fn(format!("host function '{}.{}'", ns_str, name_str), callback, userdata, None);
+    if vm.interp.register_namespaced_fn(ns_str, name_str, host_fn) { 0 } else { -1 }
+}
+
+/// Register a C host function as a method on object handles, so that
+/// `obj:method_name(args)` calls it with the handle as the first argument.
+///
+/// # Safety
+/// `vm` must be a live VM and `method_name` a valid null-terminated string.
+/// `userdata` must stay valid for as long as the method can be called.
+#[no_mangle]
+pub unsafe extern "C" fn grove_register_object_method(
+    vm: *mut GroveVm,
+    method_name: *const c_char,
+    callback: GroveHostFn,
+    userdata: *mut c_void,
+) -> i32 {
+    if vm.is_null() || method_name.is_null() {
+        return -1;
+    }
+    let vm = &mut *vm;
+    let name_str = match CStr::from_ptr(method_name).to_str() {
         Ok(s) => s.to_string(),
         Err(_) => return -1,
     };
+    if !vm.check_script_name(&name_str) {
+        return -1;
+    }
+    let host_fn = wrap_host_fn(format!("object method '{}'", name_str), callback, userdata, None);
+    vm.interp.register_object_method(&name_str, host_fn);
+    0
+}
+
+/// Route `obj.field` reads and `obj.field = value` writes on object handles
+/// to the host. The getter receives `(obj, field)` and writes the field value
+/// to `result`; the setter receives `(obj, field, value)`. Either may be null,
+/// which makes that kind of access an error. Returns 0 on success.
+///
+/// # Safety
+/// `vm` must be a live VM. `userdata` must stay valid for as long as the
+/// accessors can be called.
+#[no_mangle]
+pub unsafe extern "C" fn grove_register_object_accessor(
+    vm: *mut GroveVm,
+    getter: Option<GroveHostFn>,
+    setter: Option<GroveHostFn>,
+    userdata: *mut c_void,
+) -> i32 {
+    if vm.is_null() {
+        return -1;
+    }
+    let vm = &mut *vm;
+    vm.interp.set_object_accessor(
+        getter.map(|cb| wrap_host_fn("object getter".to_string(), cb, userdata, None)),
+        setter.map(|cb| wrap_host_fn("object setter".to_string(), cb, userdata, None)),
+    );
+    0
+}
+
+/// Label objects in script-visible strings (`tostring`, `..`) as
+/// `<Name:handle>`, with the name supplied by `callback`. The returned string
+/// is copied immediately and must only stay valid until the callback returns.
+///
+/// # Safety
+/// `vm` must be a live VM. `userdata` must stay valid for as long as the
+/// namer can be called.
+#[no_mangle]
+pub unsafe extern "C" fn grove_register_object_namer(
+    vm: *mut GroveVm,
+    callback: GroveObjectNamer,
+    userdata: *mut c_void,
+) -> i32 {
+    if vm.is_null() {
+        return -1;
+    }
+    let vm = &mut *vm;
+    let ud = userdata as usize;
+    vm.interp.set_object_namer(Box::new(move |handle| {
+        let name = callback(handle, ud as *mut c_void);
+        if name.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(name).to_string_lossy().into_owned())
+        }
+    }));
+    0
+}
+
+/// Call `callback` with the line and column of every statement before it
+/// executes, e.g. to drive a step debugger. Passing null removes it.
+///
+/// # Safety
+/// `vm` must be null or a live VM. `userdata` must stay valid for as long as
+/// the callback is installed.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_trace_callback(
+    vm: *mut GroveVm,
+    callback: Option<GroveTraceFn>,
+    userdata: *mut c_void,
+) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    match callback {
+        Some(cb) => {
+            let ud = userdata as usize;
+            vm.interp.set_trace_hook(Box::new(move |span, _stmt| {
+                cb(span.line as u32, span.column as u32, ud as *mut c_void)
+            }));
+        }
+        None => vm.interp.clear_trace_hook(),
+    }
+}
+
+/// Route `info`/`warn`/`error_log` messages to `callback` with their level.
+/// Passing null restores the default of appending them to the output buffer.
+///
+/// # Safety
+/// `vm` must be null or a live VM. `userdata` must stay valid for as long as
+/// the callback is installed.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_log_callback(
+    vm: *mut GroveVm,
+    callback: Option<GroveLogFn>,
+    userdata: *mut c_void,
+) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    match callback {
+        Some(cb) => {
+            let ud = userdata as usize;
+            vm.interp.set_log_hook(Box::new(move |level: LogLevel, message: &str| {
+                let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+                cb(level as i32, message.as_ptr(), ud as *mut c_void)
+            }));
+        }
+        None => vm.interp.clear_log_hook(),
+    }
+}
+
+/// Call `callback` when a script is stopped by the instruction limit or the
+/// loop iteration cap, before `grove_eval` returns the error. Passing null
+/// removes it.
+///
+/// # Safety
+/// `vm` must be null or a live VM. `userdata` must stay valid for as long as
+/// the callback is installed.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_limit_callback(
+    vm: *mut GroveVm,
+    callback: Option<GroveLimitFn>,
+    userdata: *mut c_void,
+) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    match callback {
+        Some(cb) => {
+            let ud = userdata as usize;
+            vm.interp.set_limit_hook(Box::new(move |kind, span| {
+                let code = if kind == ErrorKind::LoopLimit { 1 } else { 0 };
+                cb(code, span.line as u32, span.column as u32, ud as *mut c_void)
+            }));
+        }
+        None => vm.interp.clear_limit_hook(),
+    }
+}
+
+/// Let scripts `import(name)` modules, with `callback` supplying each one's
+/// source. A module runs once and its top-level return value is cached.
+/// Passing null removes the resolver.
+///
+/// # Safety
+/// `vm` must be null or a live VM. `userdata` must stay valid for as long as
+/// the resolver is installed, and each source it returns only needs to stay
+/// valid until it returns again.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_import_resolver(
+    vm: *mut GroveVm,
+    callback: Option<GroveImportResolver>,
+    userdata: *mut c_void,
+) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    match callback {
+        Some(cb) => {
+            let ud = userdata as usize;
+            vm.interp.set_import_resolver(Box::new(move |name| {
+                let name = CString::new(name).ok()?;
+                let source = cb(name.as_ptr(), ud as *mut c_void);
+                if source.is_null() {
+                    None
+                } else {
+                    Some(CStr::from_ptr(source).to_string_lossy().into_owned())
+                }
+            }));
+        }
+        None => vm.interp.clear_import_resolver(),
+    }
+}
 
-    // Wrap the C callback in a Rust closure.
-    // userdata is a raw pointer — the C side is responsible for its lifetime.
-    let ud = userdata as usize; // make it Send-safe
-    let err_name = name_str.clone();
-    let host_fn: HostFn = Box::new(move |args: &[Value]| {
-        let ffi_args: Vec<GroveValue> = args.iter().map(|a| value_to_grove_value(a)).collect();
+/// Host userdata, handed to `destroy` (if any) when the callback holding it
+/// is dropped. Without a destructor the C side manages its lifetime.
+struct Userdata {
+    ptr: *mut c_void,
+    destroy: Option<GroveDestroyFn>,
+}
+
+impl Drop for Userdata {
+    fn drop(&mut self) {
+        if let Some(destroy) = self.destroy {
+            destroy(self.ptr);
+        }
+    }
+}
+
+/// Wrap a C callback in a Rust closure. `what` names the callback in the
+/// error raised when it returns non-zero.
+fn wrap_host_fn(
+    what: String,
+    callback: GroveHostFn,
+    userdata: *mut c_void,
+    destroy: Option<GroveDestroyFn>,
+) -> HostFn {
+    let ud = Userdata { ptr: userdata, destroy };
+    Box::new(move |args: &[Value]| {
+        // Borrow all of `ud` so the closure owns it, not just `ud.ptr`.
+        let ud = &ud;
+        let ffi_args: Vec<GroveValue> = args.iter().map(value_to_grove_value).collect();
         let mut result = GroveValue {
             tag: GroveValueTag::Nil,
             data: GroveValueData { bool_val: 0 },
@@ -249,19 +940,18 @@ pub unsafe extern "C" fn grove_register_fn(
             ffi_args.as_ptr(),
             ffi_args.len() as u32,
             &mut result,
-            ud as *mut c_void,
+            ud.ptr,
         );
         if ret == 0 {
             Ok(grove_value_to_value(&result))
         } else {
-            Err(format!("host function '{}' returned error code {}", err_name, ret))
+            Err(format!("{} returned error code {}", what, ret))
         }
-    });
-
-    vm.interp.register_fn(&name_str, host_fn);
-    0
+    })
 }
 
+/// # Safety
+/// `vm` must be a live VM and `name` a valid null-terminated string.
 #[no_mangle]
 pub unsafe extern "C" fn grove_set_global_number(
     vm: *mut GroveVm,
@@ -274,10 +964,12 @@ pub unsafe extern "C" fn grove_set_global_number(
         Ok(s) => s,
         Err(_) => return -1,
     };
-    vm.interp.set_global(name_str, Value::Number(value));
+    if !vm.interp.set_global(name_str, Value::Number(value)) { return -1; }
     0
 }
 
+/// # Safety
+/// `vm` must be a live VM; `name` and `value` must be valid null-terminated strings.
 #[no_mangle]
 pub unsafe extern "C" fn grove_set_global_string(
     vm: *mut GroveVm,
@@ -294,10 +986,12 @@ pub unsafe extern "C" fn grove_set_global_string(
         Ok(s) => s,
         Err(_) => return -1,
     };
-    vm.interp.set_global(name_str, Value::String(val_str.to_string()));
+    if !vm.interp.set_global(name_str, Value::String(val_str.to_string())) { return -1; }
     0
 }
 
+/// # Safety
+/// `vm` must be a live VM and `name` a valid null-terminated string.
 #[no_mangle]
 pub unsafe extern "C" fn grove_set_global_vec3(
     vm: *mut GroveVm,
@@ -310,10 +1004,67 @@ pub unsafe extern "C" fn grove_set_global_vec3(
         Ok(s) => s,
         Err(_) => return -1,
     };
-    vm.interp.set_global(name_str, Value::Vec3(x, y, z));
+    if !vm.interp.set_global(name_str, Value::Vec3(x, y, z)) { return -1; }
+    0
+}
+
+/// Set global `name` to a copy of `len` bytes at `data`, kept exactly as
+/// given: unlike `grove_set_global_string`, invalid UTF-8 and NULs survive.
+///
+/// # Safety
+/// `vm` must be a live VM, `name` a valid null-terminated string and `data`
+/// must point to `len` bytes (or be null when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_global_bytes(
+    vm: *mut GroveVm,
+    name: *const c_char,
+    data: *const u8,
+    len: u32,
+) -> i32 {
+    if vm.is_null() || name.is_null() || (data.is_null() && len > 0) { return -1; }
+    let vm = &mut *vm;
+    let name_str = match CStr::from_ptr(name).to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+    let bytes = if len == 0 { Vec::new() } else { std::slice::from_raw_parts(data, len as usize).to_vec() };
+    if !vm.interp.set_global(name_str, Value::Bytes(bytes)) { return -1; }
+    0
+}
+
+/// Set `count` globals in one call: `names[i]` to `values[i]`. Nothing is set
+/// if any name is null or not valid UTF-8.
+///
+/// # Safety
+/// `vm` must be a live VM; `names` and `values` must each point to `count`
+/// entries, and every name must be a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_globals(
+    vm: *mut GroveVm,
+    names: *const *const c_char,
+    values: *const GroveValue,
+    count: u32,
+) -> i32 {
+    if vm.is_null() { return -1; }
+    if count == 0 { return 0; }
+    if names.is_null() || values.is_null() { return -1; }
+    let vm = &mut *vm;
+    let names = std::slice::from_raw_parts(names, count as usize);
+    let values = std::slice::from_raw_parts(values, count as usize);
+    let mut globals = Vec::with_capacity(names.len());
+    for (&name, value) in names.iter().zip(values) {
+        if name.is_null() { return -1; }
+        let Ok(name) = CStr::from_ptr(name).to_str() else { return -1 };
+        globals.push((name.to_string(), grove_value_to_value(value)));
+    }
+    // The values are owned copies now, so strings made for them can go.
+    vm.clear_temp();
+    if !vm.interp.set_globals(globals) { return -1; }
     0
 }
 
+/// # Safety
+/// `vm` must be null or a live VM.
 #[no_mangle]
 pub unsafe extern "C" fn grove_last_error(vm: *const GroveVm) -> *const c_char {
     if vm.is_null() { return ptr::null(); }
@@ -324,6 +1075,8 @@ pub unsafe extern "C" fn grove_last_error(vm: *const GroveVm) -> *const c_char {
     }
 }
 
+/// # Safety
+/// `vm` must be null or a live VM.
 #[no_mangle]
 pub unsafe extern "C" fn grove_last_error_line(vm: *const GroveVm) -> u32 {
     if vm.is_null() { return 0; }
@@ -331,6 +1084,8 @@ pub unsafe extern "C" fn grove_last_error_line(vm: *const GroveVm) -> u32 {
     vm.last_error_line
 }
 
+/// # Safety
+/// `vm` must be null or a live VM.
 #[no_mangle]
 pub unsafe extern "C" fn grove_set_instruction_limit(vm: *mut GroveVm, limit: u64) {
     if vm.is_null() { return; }
@@ -338,11 +1093,426 @@ pub unsafe extern "C" fn grove_set_instruction_limit(vm: *mut GroveVm, limit: u6
     vm.interp.set_instruction_limit(limit);
 }
 
+/// Capture the variables in scope whenever `grove_eval` hits a runtime error,
+/// for retrieval through `grove_error_locals_json`. Off by default.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_capture_error_locals(vm: *mut GroveVm, enabled: i32) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_capture_error_scopes(enabled != 0);
+}
+
+/// Enable strict mode (0 = off, the default), see `Interpreter::set_strict`.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_strict(vm: *mut GroveVm, enabled: i32) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_strict(enabled != 0);
+}
+
+/// Accept shell-style `# comment` lines (0 = off, the default), see
+/// `Interpreter::set_hash_comments`.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_hash_comments(vm: *mut GroveVm, enabled: i32) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_hash_comments(enabled != 0);
+}
+
+/// Let scripts call `eval` and `compile` (0 = off, the default), see
+/// `Interpreter::set_allow_eval`.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_allow_eval(vm: *mut GroveVm, enabled: i32) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_allow_eval(enabled != 0);
+}
+
+/// JSON array of the scopes (global first, each an object mapping names to
+/// displayed values) at the point the last `grove_eval` failed, or null when
+/// nothing was captured. Valid until the next `grove_eval`.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_error_locals_json(vm: *const GroveVm) -> *const c_char {
+    if vm.is_null() { return ptr::null(); }
+    let vm = &*vm;
+    match &vm.error_locals_json {
+        Some(json) => json.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Cap the iterations of any single loop (0 = unlimited).
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_max_loop_iterations(vm: *mut GroveVm, limit: u64) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_max_loop_iterations(limit);
+}
+
+/// Fail `grove_eval` when the script's global data exceeds `bytes`, as
+/// estimated after it runs (0 = unlimited).
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_memory_budget(vm: *mut GroveVm, bytes: u64) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_memory_budget(usize::try_from(bytes).unwrap_or(usize::MAX));
+}
+
+/// Seed the random builtins (`random_choice`, `shuffle`, ...) so a script
+/// produces the same results for the same seed.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_seed(vm: *mut GroveVm, seed: u64) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.set_seed(seed);
+}
+
+/// Number of commands scripts have queued with `emit` since the last
+/// `grove_clear_commands` or `grove_reset`.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_command_count(vm: *const GroveVm) -> u32 {
+    if vm.is_null() { return 0; }
+    let vm = &*vm;
+    vm.interp.commands.len() as u32
+}
+
+/// The kind of command `index` (in emit order), with its data rendered as
+/// JSON through `data_json` when that is non-null. Returns null if `index` is
+/// out of range. Both strings live in temp storage, see `clear_temp`, so
+/// each call invalidates the strings from the one before.
+///
+/// # Safety
+/// `vm` must be null or a live VM and `data_json` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn grove_command_get(
+    vm: *mut GroveVm,
+    index: u32,
+    data_json: *mut *const c_char,
+) -> *const c_char {
+    if vm.is_null() { return ptr::null(); }
+    let vm = &mut *vm;
+    vm.clear_temp();
+    let Some((kind, data)) = vm.interp.commands.get(index as usize) else {
+        return ptr::null();
+    };
+    let mut json = String::new();
+    value_to_json(data, &mut json);
+    let kind = kind.clone();
+    if !data_json.is_null() {
+        *data_json = vm.temp_string(&json).ptr;
+    }
+    vm.temp_string(&kind).ptr
+}
+
+/// Drop all queued commands, typically after the host has applied them.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_clear_commands(vm: *mut GroveVm) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.commands.clear();
+}
+
+/// Serialize blueprint `name` for `grove_load_blueprint_blob`, writing its
+/// size to `len`. Returns null if no such blueprint is defined. The blob
+/// stays valid until the next call to this function.
+///
+/// # Safety
+/// `vm` must be null or a live VM, `name` a valid null-terminated string and
+/// `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn grove_save_blueprint_blob(
+    vm: *mut GroveVm,
+    name: *const c_char,
+    len: *mut u32,
+) -> *const u8 {
+    if vm.is_null() || name.is_null() || len.is_null() {
+        return ptr::null();
+    }
+    let vm = &mut *vm;
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(s) => s,
+        Err(_) => return ptr::null(),
+    };
+    let Some(blob) = vm.interp.blueprint_blob(name) else {
+        return ptr::null();
+    };
+    vm.blueprint_blob = blob;
+    *len = vm.blueprint_blob.len() as u32;
+    vm.blueprint_blob.as_ptr()
+}
+
+/// Define a blueprint from a blob made by `grove_save_blueprint_blob`,
+/// possibly in another VM or process. Returns 0 on success, or -1 with the
+/// reason in `grove_last_error` if the blob is invalid.
+///
+/// # Safety
+/// `vm` must be null or a live VM and `data` must point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn grove_load_blueprint_blob(vm: *mut GroveVm, data: *const u8, len: u32) -> i32 {
+    if vm.is_null() || (data.is_null() && len > 0) {
+        return -1;
+    }
+    let vm = &mut *vm;
+    let bytes = if len == 0 { &[][..] } else { std::slice::from_raw_parts(data, len as usize) };
+    match vm.interp.load_blueprint_blob(bytes) {
+        Ok(_) => 0,
+        Err(e) => {
+            vm.last_error_line = 0;
+            vm.last_error = CString::new(e.message).ok();
+            -1
+        }
+    }
+}
+
+// ── Value construction ──────────────────────────────
+
+#[no_mangle]
+pub extern "C" fn grove_make_nil() -> GroveValue {
+    value_to_grove_value(&Value::Nil)
+}
+
+#[no_mangle]
+pub extern "C" fn grove_make_bool(value: i32) -> GroveValue {
+    value_to_grove_value(&Value::Bool(value != 0))
+}
+
+#[no_mangle]
+pub extern "C" fn grove_make_number(value: f64) -> GroveValue {
+    value_to_grove_value(&Value::Number(value))
+}
+
+#[no_mangle]
+pub extern "C" fn grove_make_vec3(x: f64, y: f64, z: f64) -> GroveValue {
+    value_to_grove_value(&Value::Vec3(x, y, z))
+}
+
+#[no_mangle]
+pub extern "C" fn grove_make_color(r: f64, g: f64, b: f64, a: f64) -> GroveValue {
+    value_to_grove_value(&Value::Color(r, g, b, a))
+}
+
+/// Build a string value backed by a copy held in VM temp storage. The copy
+/// lives until the next `grove_call_fn` or `grove_set_globals` has consumed
+/// it, or temp storage is otherwise cleared, see `clear_temp`.
+///
+/// # Safety
+/// `vm` must be null or a live VM; `value` must be null or a valid
+/// null-terminated string. Returns nil if either is null.
+#[no_mangle]
+pub unsafe extern "C" fn grove_make_string(vm: *mut GroveVm, value: *const c_char) -> GroveValue {
+    if vm.is_null() || value.is_null() {
+        return grove_make_nil();
+    }
+    let vm = &mut *vm;
+    let s = CStr::from_ptr(value).to_string_lossy();
+    GroveValue {
+        tag: GroveValueTag::String,
+        data: GroveValueData { string_val: vm.temp_string(&s) },
+    }
+}
+
+/// Build a bytes value backed by a copy of `len` bytes at `data`, held in VM
+/// temp storage like `grove_make_string`'s.
+///
+/// # Safety
+/// `vm` must be null or a live VM; `data` must point to `len` bytes (or be
+/// null when `len` is 0). Returns nil if `vm` is null.
+#[no_mangle]
+pub unsafe extern "C" fn grove_make_bytes(vm: *mut GroveVm, data: *const u8, len: u32) -> GroveValue {
+    if vm.is_null() || (data.is_null() && len > 0) {
+        return grove_make_nil();
+    }
+    let vm = &mut *vm;
+    let bytes = if len == 0 { &[][..] } else { std::slice::from_raw_parts(data, len as usize) };
+    GroveValue {
+        tag: GroveValueTag::Bytes,
+        data: GroveValueData { string_val: vm.temp_bytes(bytes) },
+    }
+}
+
+/// Call a script-defined blueprint by name. Returns 0 on success and writes
+/// the return value to `result` (if non-null); returns -1 on error. A string
+/// result stays valid until the next `grove_call_fn` or `grove_reset`.
+///
+/// # Safety
+/// `vm` must be a live VM, `name` a valid null-terminated string, `args` must
+/// point to `arg_count` values (or be null when `arg_count` is 0), and
+/// `result` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn grove_call_fn(
+    vm: *mut GroveVm,
+    name: *const c_char,
+    args: *const GroveValue,
+    arg_count: u32,
+    result: *mut GroveValue,
+) -> i32 {
+    if vm.is_null() || name.is_null() || (args.is_null() && arg_count > 0) {
+        return -1;
+    }
+    let mut entry = EntryGuard::new(&mut *vm);
+    let vm = &mut *entry;
+    let name_str = match CStr::from_ptr(name).to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+    let arg_vals: Vec<Value> = if arg_count == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(args, arg_count as usize)
+            .iter()
+            .map(grove_value_to_value)
+            .collect()
+    };
+    // The arguments are owned copies now, so strings made for them can go.
+    vm.clear_temp();
+
+    match vm.interp.call(name_str, &arg_vals) {
+        Ok(val) => {
+            vm.last_error = None;
+            vm.last_error_line = 0;
+            if !result.is_null() {
+                *result = match &val {
+                    Value::String(s) => GroveValue {
+                        tag: GroveValueTag::String,
+                        data: GroveValueData { string_val: vm.temp_string(s) },
+                    },
+                    Value::Bytes(b) => GroveValue {
+                        tag: GroveValueTag::Bytes,
+                        data: GroveValueData { string_val: vm.temp_bytes(b) },
+                    },
+                    other => value_to_grove_value(other),
+                };
+            }
+            0
+        }
+        Err(e) => {
+            vm.last_error_line = e.line as u32;
+            vm.last_error = CString::new(format!("{}", e)).ok();
+            -1
+        }
+    }
+}
+
+/// Create a VM that may be shared between threads, see `GroveVmShared`.
+#[no_mangle]
+pub extern "C" fn grove_shared_new() -> *mut GroveVmShared {
+    // SAFETY: the VM was just created and nothing else points to it.
+    let vm = unsafe { Box::from_raw(grove_new()) };
+    Box::into_raw(Box::new(GroveVmShared { vm: Mutex::new(SendVm(vm)) }))
+}
+
+/// Destroy a VM created by `grove_shared_new`.
+///
+/// # Safety
+/// `shared` must be null or a pointer returned by `grove_shared_new` that has
+/// not been destroyed, and no other thread may still be using it.
+#[no_mangle]
+pub unsafe extern "C" fn grove_shared_destroy(shared: *mut GroveVmShared) {
+    if !shared.is_null() {
+        drop(Box::from_raw(shared));
+    }
+}
+
+/// `grove_eval` on a shared VM, waiting for any other thread using it.
+///
+/// # Safety
+/// `shared` must be a live shared VM and `source` a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn grove_shared_eval(shared: *mut GroveVmShared, source: *const c_char) -> i32 {
+    with_shared_vm(shared, |vm| grove_eval(vm, source))
+}
+
+/// Run `callback` with exclusive access to the shared VM and return its
+/// result, or -1 if `shared` is null. Use it for any other VM call, and to
+/// read results such as `grove_last_error` before another thread can
+/// replace them. `vm` must not be used after the callback returns.
+///
+/// # Safety
+/// `shared` must be null or a live shared VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_shared_with(
+    shared: *mut GroveVmShared,
+    callback: GroveSharedFn,
+    userdata: *mut c_void,
+) -> i32 {
+    with_shared_vm(shared, |vm| callback(vm, userdata))
+}
+
+/// Run `f` on the shared VM while holding its lock.
+unsafe fn with_shared_vm(shared: *mut GroveVmShared, f: impl FnOnce(*mut GroveVm) -> i32) -> i32 {
+    if shared.is_null() {
+        return -1;
+    }
+    // Panics abort at the FFI boundary, so nobody sees a poisoned lock.
+    let mut vm = (*shared).vm.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&mut *vm.0)
+}
+
+/// Reset the VM for reuse, clearing globals, blueprints and the last error.
+/// Host functions survive when `keep_host_fns` is non-zero.
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_reset(vm: *mut GroveVm, keep_host_fns: i32) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    vm.interp.reset(keep_host_fns != 0);
+    vm.error_locals_json = None;
+    vm.last_error = None;
+    vm.last_error_line = 0;
+    vm.clear_temp();
+}
+
+/// Limit the size of sources accepted by `grove_eval`, and by `eval`,
+/// `compile` and `import` in scripts (0 = unlimited).
+///
+/// # Safety
+/// `vm` must be null or a live VM.
+#[no_mangle]
+pub unsafe extern "C" fn grove_set_source_limits(vm: *mut GroveVm, max_chars: u64, max_tokens: u64) {
+    if vm.is_null() { return; }
+    let vm = &mut *vm;
+    let to_limit = |n: u64| if n == 0 { usize::MAX } else { n as usize };
+    vm.interp.set_source_limits(to_limit(max_chars), to_limit(max_tokens));
+}
+
 // ── Integration test from Rust side ─────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::AtomicU32;
 
     #[test]
     fn test_ffi_eval() {
@@ -446,4 +1616,749 @@ mod tests {
             grove_destroy(vm);
         }
     }
+
+    #[test]
+    fn test_ffi_source_limits() {
+        unsafe {
+            let vm = grove_new();
+            grove_set_source_limits(vm, 16, 0);
+            let source = CString::new("local x = 1").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            let big = CString::new("local x = 1 + 2 + 3 + 4").unwrap();
+            assert_eq!(grove_eval(vm, big.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("source too large"));
+
+            grove_set_source_limits(vm, 0, 3);
+            assert_eq!(grove_eval(vm, source.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("too many tokens"));
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_reset() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn noop(
+                _args: *const GroveValue,
+                _arg_count: u32,
+                _result: *mut GroveValue,
+                _userdata: *mut c_void,
+            ) -> i32 {
+                0
+            }
+            let name = CString::new("place").unwrap();
+            grove_register_fn(vm, name.as_ptr(), noop, ptr::null_mut());
+
+            let define = CString::new("local leftover = 1").unwrap();
+            assert_eq!(grove_eval(vm, define.as_ptr()), 0);
+
+            grove_reset(vm, 1);
+            let uses_place = CString::new("place(1)").unwrap();
+            assert_eq!(grove_eval(vm, uses_place.as_ptr()), 0);
+            let uses_global = CString::new("place(leftover)").unwrap();
+            assert_eq!(grove_eval(vm, uses_global.as_ptr()), -1);
+
+            grove_reset(vm, 0);
+            assert_eq!(grove_eval(vm, uses_place.as_ptr()), -1);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_call_fn_with_made_values() {
+        unsafe {
+            let vm = grove_new();
+            let source = CString::new(r#"
+blueprint describe(name, count, enabled, pos, missing)
+    return name .. ":" .. count .. ":" .. enabled .. ":" .. pos.y .. ":" .. missing
+end
+blueprint scale(v, k)
+    return v * k
+end
+blueprint fade(c, alpha)
+    return color(c.r, c.g, c.b, alpha)
+end
+"#).unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            let label = CString::new("crate").unwrap();
+            let args = [
+                grove_make_string(vm, label.as_ptr()),
+                grove_make_number(3.0),
+                grove_make_bool(1),
+                grove_make_vec3(1.0, 2.0, 3.0),
+                grove_make_nil(),
+            ];
+            let mut result = grove_make_nil();
+            let name = CString::new("describe").unwrap();
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), args.as_ptr(), 5, &mut result), 0);
+            assert!(matches!(result.tag, GroveValueTag::String));
+            let sv = result.data.string_val;
+            let text = std::slice::from_raw_parts(sv.ptr as *const u8, sv.len as usize);
+            assert_eq!(text, b"crate:3:true:2:nil");
+
+            let args = [grove_make_vec3(1.0, 2.0, 3.0), grove_make_number(2.0)];
+            let name = CString::new("scale").unwrap();
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), args.as_ptr(), 2, &mut result), 0);
+            assert!(matches!(result.tag, GroveValueTag::Vec3));
+            assert_eq!(result.data.vec3_val.z, 6.0);
+
+            let args = [grove_make_color(1.0, 0.5, 0.25, 1.0), grove_make_number(0.5)];
+            let name = CString::new("fade").unwrap();
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), args.as_ptr(), 2, &mut result), 0);
+            assert!(matches!(result.tag, GroveValueTag::Color));
+            let c = result.data.color_val;
+            assert_eq!((c.r, c.g, c.b, c.a), (1.0, 0.5, 0.25, 0.5));
+
+            let name = CString::new("nope").unwrap();
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), ptr::null(), 0, &mut result), -1);
+            assert!(!grove_last_error(vm).is_null());
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_object_method() {
+        unsafe {
+            let vm = grove_new();
+
+            #[derive(Default)]
+            struct Moved {
+                handle: u64,
+                delta: (f64, f64, f64),
+                calls: u32,
+            }
+            extern "C" fn move_fn(
+                args: *const GroveValue,
+                arg_count: u32,
+                result: *mut GroveValue,
+                userdata: *mut c_void,
+            ) -> i32 {
+                unsafe {
+                    if arg_count != 2 {
+                        return 1;
+                    }
+                    let moved = &mut *(userdata as *mut Moved);
+                    let this = &*args;
+                    let delta = &*args.add(1);
+                    if !matches!(this.tag, GroveValueTag::Object) || !matches!(delta.tag, GroveValueTag::Vec3) {
+                        return 2;
+                    }
+                    let v = delta.data.vec3_val;
+                    moved.handle = this.data.object_handle;
+                    moved.delta = (v.x, v.y, v.z);
+                    moved.calls += 1;
+                    *result = grove_make_bool(1);
+                }
+                0
+            }
+
+            let mut moved = Moved::default();
+            let name = CString::new("move").unwrap();
+            grove_register_object_method(vm, name.as_ptr(), move_fn, &mut moved as *mut Moved as *mut c_void);
+            vm.as_mut().unwrap().interp.set_global("entity", Value::Object(42));
+
+            let source = CString::new("local ok = entity:move(vec3(1, 2, 3))\nif not ok then\n    entity:fail()\nend").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(moved.calls, 1);
+            assert_eq!(moved.handle, 42);
+            assert_eq!(moved.delta, (1.0, 2.0, 3.0));
+
+            let unknown = CString::new("entity:teleport()").unwrap();
+            assert_eq!(grove_eval(vm, unknown.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("object has no method 'teleport'"), "got: {}", err);
+
+            let bad_args = CString::new("entity:move()").unwrap();
+            assert_eq!(grove_eval(vm, bad_args.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("object method 'move' returned error code 1"), "got: {}", err);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_object_accessor() {
+        unsafe {
+            let vm = grove_new();
+
+            // A single host entity with a numeric `health` property.
+            extern "C" fn get_fn(
+                args: *const GroveValue,
+                _arg_count: u32,
+                result: *mut GroveValue,
+                userdata: *mut c_void,
+            ) -> i32 {
+                unsafe {
+                    let field = (*args.add(1)).data.string_val;
+                    let field = std::slice::from_raw_parts(field.ptr as *const u8, field.len as usize);
+                    if (*args).data.object_handle != 7 || field != b"health" {
+                        return 1;
+                    }
+                    *result = grove_make_number(*(userdata as *const f64));
+                }
+                0
+            }
+            extern "C" fn set_fn(
+                args: *const GroveValue,
+                arg_count: u32,
+                _result: *mut GroveValue,
+                userdata: *mut c_void,
+            ) -> i32 {
+                unsafe {
+                    let value = &*args.add(2);
+                    if arg_count != 3 || !matches!(value.tag, GroveValueTag::Number) {
+                        return 1;
+                    }
+                    *(userdata as *mut f64) = value.data.number_val;
+                }
+                0
+            }
+
+            let mut health = 10.0f64;
+            grove_register_object_accessor(vm, Some(get_fn), Some(set_fn), &mut health as *mut f64 as *mut c_void);
+            vm.as_mut().unwrap().interp.set_global("entity", Value::Object(7));
+
+            let source = CString::new("entity.health = entity.health - 3").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(health, 7.0);
+
+            let unknown = CString::new("local m = entity.mana").unwrap();
+            assert_eq!(grove_eval(vm, unknown.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("object getter returned error code 1"), "got: {}", err);
+
+            // Read-only objects: no setter registered
+            grove_register_object_accessor(vm, Some(get_fn), None, &mut health as *mut f64 as *mut c_void);
+            assert_eq!(grove_eval(vm, source.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("cannot set field 'health' on object"), "got: {}", err);
+            assert_eq!(health, 7.0);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_object_namer() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn namer(handle: u64, _userdata: *mut c_void) -> *const c_char {
+                if handle == 42 { c"Tree".as_ptr() } else { ptr::null() }
+            }
+            let source = CString::new(r#"
+blueprint describe(a, b)
+    return tostring(a) .. " " .. b
+end
+"#).unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            let name = CString::new("describe").unwrap();
+            let args = [
+                GroveValue { tag: GroveValueTag::Object, data: GroveValueData { object_handle: 42 } },
+                GroveValue { tag: GroveValueTag::Object, data: GroveValueData { object_handle: 7 } },
+            ];
+            let mut result = grove_make_nil();
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), args.as_ptr(), 2, &mut result), 0);
+            let text = |r: &GroveValue| {
+                let sv = r.data.string_val;
+                String::from_utf8_lossy(std::slice::from_raw_parts(sv.ptr as *const u8, sv.len as usize)).into_owned()
+            };
+            assert_eq!(text(&result), "<object:42> <object:7>");
+
+            grove_register_object_namer(vm, namer, ptr::null_mut());
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), args.as_ptr(), 2, &mut result), 0);
+            assert_eq!(text(&result), "<Tree:42> <object:7>");
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_trace_callback() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn trace(line: u32, _column: u32, userdata: *mut c_void) {
+                unsafe { (*(userdata as *mut Vec<u32>)).push(line) }
+            }
+            let mut lines: Vec<u32> = Vec::new();
+            grove_set_trace_callback(vm, Some(trace), &mut lines as *mut Vec<u32> as *mut c_void);
+
+            let source = CString::new("local x = 0\nfor i = 1, 3 do\n    x = x + i\nend").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(lines, vec![1, 2, 3, 3, 3]);
+
+            grove_set_trace_callback(vm, None, ptr::null_mut());
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(lines.len(), 5);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_shared_vm_serializes_evals() {
+        unsafe {
+            let shared = grove_shared_new();
+            let init = CString::new("local count = 0").unwrap();
+            assert_eq!(grove_shared_eval(shared, init.as_ptr()), 0);
+
+            let addr = shared as usize;
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    std::thread::spawn(move || {
+                        let inc = CString::new("count = count + 1").unwrap();
+                        for _ in 0..50 {
+                            assert_eq!(grove_shared_eval(addr as *mut GroveVmShared, inc.as_ptr()), 0);
+                        }
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+
+            extern "C" fn read_count(vm: *mut GroveVm, out: *mut c_void) -> i32 {
+                unsafe {
+                    let name = CString::new("get").unwrap();
+                    let def = CString::new("blueprint get() return count end").unwrap();
+                    grove_eval(vm, def.as_ptr());
+                    let mut result = grove_make_nil();
+                    let rc = grove_call_fn(vm, name.as_ptr(), ptr::null(), 0, &mut result);
+                    *(out as *mut f64) = result.data.number_val;
+                    rc
+                }
+            }
+            let mut count = 0.0f64;
+            assert_eq!(grove_shared_with(shared, read_count, &mut count as *mut f64 as *mut c_void), 0);
+            assert_eq!(count, 200.0);
+
+            grove_shared_destroy(shared);
+        }
+    }
+
+    #[test]
+    fn test_ffi_userdata_destructor() {
+        extern "C" fn noop(
+            _args: *const GroveValue,
+            _arg_count: u32,
+            _result: *mut GroveValue,
+            _userdata: *mut c_void,
+        ) -> i32 {
+            0
+        }
+        extern "C" fn count_destroy(userdata: *mut c_void) {
+            unsafe { (*(userdata as *const AtomicU32)).fetch_add(1, Ordering::Relaxed) };
+        }
+        unsafe {
+            let destroyed = AtomicU32::new(0);
+            let ud = &destroyed as *const AtomicU32 as *mut c_void;
+            let vm = grove_new();
+            let name = CString::new("spawn").unwrap();
+            let other = CString::new("despawn").unwrap();
+            let keyword = CString::new("if").unwrap();
+            assert_eq!(grove_register_fn_with_destructor(vm, name.as_ptr(), noop, ud, Some(count_destroy)), 0);
+            assert_eq!(grove_register_fn_with_destructor(vm, other.as_ptr(), noop, ud, Some(count_destroy)), 0);
+            assert_eq!(grove_register_fn_with_destructor(vm, keyword.as_ptr(), noop, ud, Some(count_destroy)), -1);
+            assert_eq!(destroyed.load(Ordering::Relaxed), 0);
+
+            // Re-registering drops the old userdata.
+            assert_eq!(grove_register_fn(vm, name.as_ptr(), noop, ptr::null_mut()), 0);
+            assert_eq!(destroyed.load(Ordering::Relaxed), 1);
+
+            grove_destroy(vm);
+            assert_eq!(destroyed.load(Ordering::Relaxed), 2);
+        }
+    }
+
+    #[test]
+    fn test_ffi_blueprint_blob() {
+        unsafe {
+            let library = grove_new();
+            let source = CString::new("blueprint area(w, h) return w * h end").unwrap();
+            assert_eq!(grove_eval(library, source.as_ptr()), 0);
+            let name = CString::new("area").unwrap();
+            let missing = CString::new("volume").unwrap();
+            let mut len = 0u32;
+            assert!(grove_save_blueprint_blob(library, missing.as_ptr(), &mut len).is_null());
+            let data = grove_save_blueprint_blob(library, name.as_ptr(), &mut len);
+            assert!(!data.is_null());
+            let blob = std::slice::from_raw_parts(data, len as usize).to_vec();
+            grove_destroy(library);
+
+            let vm = grove_new();
+            assert_eq!(grove_load_blueprint_blob(vm, blob.as_ptr(), blob.len() as u32), 0);
+            let args = [grove_make_number(6.0), grove_make_number(7.0)];
+            let mut result = grove_make_nil();
+            assert_eq!(grove_call_fn(vm, name.as_ptr(), args.as_ptr(), 2, &mut result), 0);
+            assert_eq!(result.data.number_val, 42.0);
+
+            assert_eq!(grove_load_blueprint_blob(vm, blob.as_ptr(), 3), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.starts_with("invalid blueprint blob"), "got: {}", err);
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_commands() {
+        unsafe {
+            let vm = grove_new();
+            let source = CString::new(r#"
+emit("place", {x = 1, block = "stone"})
+emit("place", {x = 2, pos = vec3(0, 1, 0), tags = ["a\"b"]})
+emit("done", nil)
+"#).unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(grove_command_count(vm), 3);
+
+            let mut data: *const c_char = ptr::null();
+            let kind = grove_command_get(vm, 0, &mut data);
+            assert_eq!(CStr::from_ptr(kind).to_str().unwrap(), "place");
+            assert_eq!(CStr::from_ptr(data).to_str().unwrap(), r#"{"block":"stone","x":1}"#);
+            grove_command_get(vm, 1, &mut data);
+            assert_eq!(
+                CStr::from_ptr(data).to_str().unwrap(),
+                r#"{"pos":[0,1,0],"tags":["a\"b"],"x":2}"#
+            );
+            let kind = grove_command_get(vm, 2, &mut data);
+            assert_eq!(CStr::from_ptr(kind).to_str().unwrap(), "done");
+            assert_eq!(CStr::from_ptr(data).to_str().unwrap(), "null");
+            assert!(grove_command_get(vm, 3, ptr::null_mut()).is_null());
+
+            grove_clear_commands(vm);
+            assert_eq!(grove_command_count(vm), 0);
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_error_locals_json() {
+        unsafe {
+            let vm = grove_new();
+            let source = CString::new("local name = \"a\\\"b\"\nfor i = 1, 5 do\n    local r = 10 / (3 - i)\nend").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), -1);
+            assert!(grove_error_locals_json(vm).is_null());
+
+            grove_set_capture_error_locals(vm, 1);
+            assert_eq!(grove_eval(vm, source.as_ptr()), -1);
+            let json = CStr::from_ptr(grove_error_locals_json(vm)).to_str().unwrap();
+            assert!(json.starts_with(r#"[{"name":"a\"b"}"#), "got: {}", json);
+            assert!(json.contains(r#""i":"3""#), "got: {}", json);
+
+            let ok = CString::new("local x = 1").unwrap();
+            assert_eq!(grove_eval(vm, ok.as_ptr()), 0);
+            assert!(grove_error_locals_json(vm).is_null());
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_namespaced_fn() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn spawn(
+                args: *const GroveValue,
+                arg_count: u32,
+                result: *mut GroveValue,
+                userdata: *mut c_void,
+            ) -> i32 {
+                unsafe {
+                    let spawned = &mut *(userdata as *mut Vec<(f64, f64, f64)>);
+                    if arg_count != 1 || !matches!((*args).tag, GroveValueTag::Vec3) {
+                        return 1;
+                    }
+                    let v = (*args).data.vec3_val;
+                    spawned.push((v.x, v.y, v.z));
+                    *result = GroveValue {
+                        tag: GroveValueTag::Object,
+                        data: GroveValueData { object_handle: spawned.len() as u64 },
+                    };
+                }
+                0
+            }
+
+            let mut spawned: Vec<(f64, f64, f64)> = Vec::new();
+            let game = CString::new("game").unwrap();
+            let name = CString::new("spawn").unwrap();
+            let ud = &mut spawned as *mut Vec<(f64, f64, f64)> as *mut c_void;
+            assert_eq!(grove_register_namespaced_fn(vm, game.as_ptr(), name.as_ptr(), spawn, ud), -1);
+            assert_eq!(grove_register_namespace(vm, game.as_ptr()), 0);
+            assert_eq!(grove_register_namespaced_fn(vm, game.as_ptr(), name.as_ptr(), spawn, ud), 0);
+
+            let source = CString::new(r#"
+local first = game.spawn(vec3(1, 2, 3))
+local second = game.spawn(vec3(4, 5, 6))
+blueprint ids()
+    return tostring(first) .. " " .. tostring(second)
+end
+"#).unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(spawned, vec![(1.0, 2.0, 3.0), (4.0, 5.0, 6.0)]);
+
+            let ids = CString::new("ids").unwrap();
+            let mut result = grove_make_nil();
+            assert_eq!(grove_call_fn(vm, ids.as_ptr(), ptr::null(), 0, &mut result), 0);
+            let sv = result.data.string_val;
+            let text = std::slice::from_raw_parts(sv.ptr as *const u8, sv.len as usize);
+            assert_eq!(text, b"<object:1> <object:2>");
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_register_rejects_keywords() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn noop(
+                _args: *const GroveValue,
+                _arg_count: u32,
+                _result: *mut GroveValue,
+                _userdata: *mut c_void,
+            ) -> i32 {
+                0
+            }
+            let keyword = CString::new("fn").unwrap();
+            assert_eq!(grove_register_fn(vm, keyword.as_ptr(), noop, ptr::null_mut()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("'fn': it is a reserved keyword"), "got: {}", err);
+
+            let dotted = CString::new("game.spawn").unwrap();
+            assert_eq!(grove_register_fn(vm, dotted.as_ptr(), noop, ptr::null_mut()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("not a valid identifier"), "got: {}", err);
+
+            let ok = CString::new("spawn").unwrap();
+            assert_eq!(grove_register_fn(vm, ok.as_ptr(), noop, ptr::null_mut()), 0);
+            let while_kw = CString::new("while").unwrap();
+            assert_eq!(grove_register_object_method(vm, while_kw.as_ptr(), noop, ptr::null_mut()), -1);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_version_and_features() {
+        unsafe {
+            let version = CStr::from_ptr(grove_version()).to_str().unwrap();
+            assert_eq!(version, env!("CARGO_PKG_VERSION"));
+
+            let present = CString::new("keyword_args").unwrap();
+            let absent = CString::new("metatables").unwrap();
+            assert_eq!(grove_has_feature(present.as_ptr()), 1);
+            assert_eq!(grove_has_feature(absent.as_ptr()), 0);
+            assert_eq!(grove_has_feature(ptr::null()), 0);
+        }
+    }
+
+    #[test]
+    fn test_ffi_log_callback() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn on_log(level: i32, message: *const c_char, userdata: *mut c_void) {
+                let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string();
+                unsafe { (*(userdata as *mut Vec<(i32, String)>)).push((level, message)) }
+            }
+            let mut logged: Vec<(i32, String)> = Vec::new();
+            grove_set_log_callback(vm, Some(on_log), &mut logged as *mut Vec<(i32, String)> as *mut c_void);
+
+            let source = CString::new("warn(\"low fuel:\", 3)").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(logged, vec![(1, "low fuel: 3".to_string())]);
+
+            grove_set_log_callback(vm, None, ptr::null_mut());
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_imprecise_integer_literal() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn on_log(level: i32, message: *const c_char, userdata: *mut c_void) {
+                let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string();
+                unsafe { (*(userdata as *mut Vec<(i32, String)>)).push((level, message)) }
+            }
+            let mut logged: Vec<(i32, String)> = Vec::new();
+            grove_set_log_callback(vm, Some(on_log), &mut logged as *mut Vec<(i32, String)> as *mut c_void);
+
+            let source = CString::new("local id = 9007199254740993").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(logged.len(), 1);
+            assert_eq!(logged[0].0, 1);
+            assert!(logged[0].1.contains("too large to represent exactly"), "got: {}", logged[0].1);
+
+            grove_set_strict(vm, 1);
+            assert_eq!(grove_eval(vm, source.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("use a string for IDs"), "got: {}", err);
+
+            let commented = CString::new("# setup\nlocal arr = [1, 2]\nlocal n = 1 +\n#arr").unwrap();
+            assert_eq!(grove_eval(vm, commented.as_ptr()), -1);
+            grove_set_hash_comments(vm, 1);
+            assert_eq!(grove_eval(vm, commented.as_ptr()), 0);
+
+            grove_set_log_callback(vm, None, ptr::null_mut());
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_limit_callback() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn limit(kind: i32, line: u32, _column: u32, userdata: *mut c_void) {
+                unsafe { (*(userdata as *mut Vec<(i32, u32)>)).push((kind, line)) }
+            }
+            let mut hits: Vec<(i32, u32)> = Vec::new();
+            grove_set_limit_callback(vm, Some(limit), &mut hits as *mut Vec<(i32, u32)> as *mut c_void);
+            grove_set_instruction_limit(vm, 100);
+
+            let source = CString::new("while true do\n    pass\nend").unwrap();
+            assert_ne!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(hits, vec![(0, 1)]);
+
+            grove_set_max_loop_iterations(vm, 3);
+            let source = CString::new("for i = 1, 10 do end").unwrap();
+            assert_ne!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(hits, vec![(0, 1), (1, 1)]);
+
+            grove_set_limit_callback(vm, None, ptr::null_mut());
+            assert_ne!(grove_eval(vm, source.as_ptr()), 0);
+            assert_eq!(hits.len(), 2);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_set_globals_batch() {
+        unsafe {
+            let vm = grove_new();
+            let names: Vec<CString> = (0..100).map(|i| CString::new(format!("cfg_{}", i)).unwrap()).collect();
+            let name_ptrs: Vec<*const c_char> = names.iter().map(|n| n.as_ptr()).collect();
+            let values: Vec<GroveValue> = (0..100).map(|i| grove_make_number(i as f64 * 2.0)).collect();
+            assert_eq!(grove_set_globals(vm, name_ptrs.as_ptr(), values.as_ptr(), 100), 0);
+
+            let source = CString::new("assert(cfg_0 == 0)\nassert(cfg_42 == 84)\nassert(cfg_99 == 198)").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            let bad = [name_ptrs[0], ptr::null()];
+            let overwrite = [grove_make_number(-1.0), grove_make_number(-1.0)];
+            assert_eq!(grove_set_globals(vm, bad.as_ptr(), overwrite.as_ptr(), 2), -1);
+            let source = CString::new("assert(cfg_0 == 0)").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_bytes_round_trip() {
+        unsafe {
+            let vm = grove_new();
+            let payload: [u8; 6] = [0xFF, 0xFE, 0x00, b'a', 0xC3, 0x28];
+            let name = CString::new("payload").unwrap();
+            assert_eq!(grove_set_global_bytes(vm, name.as_ptr(), payload.as_ptr(), payload.len() as u32), 0);
+
+            let source = CString::new("blueprint get()\n    assert(#payload == 6 and payload[0] == 255)\n    return payload\nend\nblueprint echo(b)\n    return b\nend").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            let bytes_of = |v: &GroveValue| {
+                assert!(matches!(v.tag, GroveValueTag::Bytes));
+                std::slice::from_raw_parts(v.data.string_val.ptr as *const u8, v.data.string_val.len as usize).to_vec()
+            };
+            let mut result = grove_make_nil();
+            let get = CString::new("get").unwrap();
+            assert_eq!(grove_call_fn(vm, get.as_ptr(), ptr::null(), 0, &mut result), 0);
+            assert_eq!(bytes_of(&result), payload);
+
+            let arg = grove_make_bytes(vm, payload.as_ptr(), payload.len() as u32);
+            let echo = CString::new("echo").unwrap();
+            assert_eq!(grove_call_fn(vm, echo.as_ptr(), &arg, 1, &mut result), 0);
+            assert_eq!(bytes_of(&result), payload);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_eval_value_module_return() {
+        unsafe {
+            let vm = grove_new();
+            let source = CString::new("local version = 1\nreturn {version = version, name = \"utils\"}").unwrap();
+            let mut result = grove_make_number(0.0);
+            let mut json: *const c_char = ptr::null();
+            assert_eq!(grove_eval_value(vm, source.as_ptr(), &mut result, &mut json), 0);
+            assert!(matches!(result.tag, GroveValueTag::Nil));
+            assert_eq!(CStr::from_ptr(json).to_str().unwrap(), r#"{"name":"utils","version":1}"#);
+
+            let source = CString::new("return \"ok\"").unwrap();
+            assert_eq!(grove_eval_value(vm, source.as_ptr(), &mut result, ptr::null_mut()), 0);
+            assert!(matches!(result.tag, GroveValueTag::String));
+            let sv = result.data.string_val;
+            assert_eq!(std::slice::from_raw_parts(sv.ptr as *const u8, sv.len as usize), b"ok");
+
+            let source = CString::new("local x = 1").unwrap();
+            assert_eq!(grove_eval_value(vm, source.as_ptr(), &mut result, &mut json), 0);
+            assert_eq!(CStr::from_ptr(json).to_str().unwrap(), "null");
+
+            // Temp storage is reused rather than growing with every call
+            let source = CString::new("emit(\"tick\", {n = 1})\nreturn \"ok\"").unwrap();
+            for _ in 0..100 {
+                assert_eq!(grove_eval_value(vm, source.as_ptr(), &mut result, &mut json), 0);
+                assert!(!grove_command_get(vm, 0, &mut json).is_null());
+                grove_clear_commands(vm);
+            }
+            assert_eq!((*vm)._temp_strings.len(), 2);
+
+            grove_destroy(vm);
+        }
+    }
+
+    #[test]
+    fn test_ffi_import_resolver() {
+        unsafe {
+            let vm = grove_new();
+            extern "C" fn resolve(name: *const c_char, userdata: *mut c_void) -> *const c_char {
+                unsafe {
+                    *(userdata as *mut u32) += 1;
+                    match CStr::from_ptr(name).to_str().unwrap() {
+                        "config" => c"return {version = 3, name = \"forest\"}".as_ptr(),
+                        _ => ptr::null(),
+                    }
+                }
+            }
+            let mut calls: u32 = 0;
+            grove_set_import_resolver(vm, Some(resolve), &mut calls as *mut u32 as *mut c_void);
+
+            let source = CString::new("local cfg = import(\"config\")\nreturn import(\"config\")").unwrap();
+            let mut json: *const c_char = ptr::null();
+            assert_eq!(grove_eval_value(vm, source.as_ptr(), ptr::null_mut(), &mut json), 0);
+            assert_eq!(CStr::from_ptr(json).to_str().unwrap(), r#"{"name":"forest","version":3}"#);
+            assert_eq!(calls, 1);
+
+            let source = CString::new("import(\"missing\")").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), -1);
+            let err = CStr::from_ptr(grove_last_error(vm)).to_str().unwrap();
+            assert!(err.contains("module 'missing' not found"), "{}", err);
+
+            // Already-imported modules stay cached without the resolver.
+            grove_set_import_resolver(vm, None, ptr::null_mut());
+            let source = CString::new("import(\"config\")").unwrap();
+            assert_eq!(grove_eval(vm, source.as_ptr()), 0);
+
+            grove_destroy(vm);
+        }
+    }
 }
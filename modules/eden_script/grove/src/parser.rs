@@ -2,14 +2,59 @@ use crate::ast::*;
 use crate::error::{GroveError, GroveResult};
 use crate::lexer::{Token, TokenKind};
 
+/// Defaults for `Parser::max_depth` and `Parser::max_expression_depth`, low
+/// enough that even a debug build can run the most deeply nested accepted
+/// program on an 8 MiB main thread stack. Expressions get more room than
+/// blocks since generated code chains operators (`a ^ b ^ ...`) far deeper
+/// than anyone nests blocks.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+pub const DEFAULT_MAX_EXPRESSION_DEPTH: usize = 256;
+
+/// Run `f` with the main thread sized stack the nesting limits are sized
+/// for, which debug builds don't give test threads.
+#[cfg(test)]
+pub(crate) fn on_main_thread_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::Builder::new().stack_size(8 << 20).spawn(f).unwrap().join().unwrap()
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    /// Statements currently being parsed, one inside the other.
+    depth: usize,
+    max_depth: usize,
+    /// Expressions currently being parsed, one inside the other.
+    expression_depth: usize,
+    max_expression_depth: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        // Comment tokens only exist for tooling; the grammar never sees them.
+        tokens.retain(|t| !matches!(t.kind, TokenKind::Comment(_)));
+        Self {
+            tokens,
+            pos: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            expression_depth: 0,
+            max_expression_depth: DEFAULT_MAX_EXPRESSION_DEPTH,
+        }
+    }
+
+    /// Reject programs nesting blocks more than `max_depth` deep with a
+    /// syntax error, rather than overflowing the stack while parsing or
+    /// running them.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Like `max_depth`, for expressions nested inside one another
+    /// (parentheses, operands, brackets, call arguments).
+    pub fn max_expression_depth(mut self, max_depth: usize) -> Self {
+        self.max_expression_depth = max_depth;
+        self
     }
 
     pub fn parse(&mut self) -> GroveResult<Program> {
@@ -59,11 +104,59 @@ impl Parser {
         }
     }
 
+    /// Consume keyword `expected` or fail with "expected '<keyword>' <context>"
+    /// at the end of the previous token, where the keyword was left out,
+    /// rather than at whatever token happens to follow.
+    fn expect_keyword(&mut self, expected: &TokenKind, keyword: &str, context: &str) -> GroveResult<()> {
+        if self.check(expected) {
+            self.advance();
+            return Ok(());
+        }
+        let prev = &self.tokens[self.pos.saturating_sub(1)];
+        Err(GroveError::syntax(
+            format!("expected '{}' {}", keyword, context),
+            prev.end_line,
+            prev.end_column,
+        ))
+    }
+
+    /// Consume the `end` closing the `construct` that started at `start`.
+    fn expect_end(&mut self, construct: &str, start: &Span) -> GroveResult<()> {
+        self.expect_keyword(&TokenKind::End, "end", &format!("to close '{}' on line {}", construct, start.line))
+    }
+
     fn span(&self) -> Span {
         let tok = self.current_token();
         Span { line: tok.line, column: tok.column }
     }
 
+    /// Run `parse` one block or expression level deeper, failing with
+    /// "block too deeply nested" or "expression too deeply nested" past the
+    /// matching limit.
+    fn nested<T>(&mut self, expression: bool, parse: impl FnOnce(&mut Self) -> GroveResult<T>) -> GroveResult<T> {
+        let (depth, max, what) = if expression {
+            (self.expression_depth, self.max_expression_depth, "expression")
+        } else {
+            (self.depth, self.max_depth, "block")
+        };
+        if depth >= max {
+            let s = self.span();
+            return Err(GroveError::syntax(format!("{} too deeply nested", what), s.line, s.column));
+        }
+        self.set_depth(expression, depth + 1);
+        let result = parse(self);
+        self.set_depth(expression, depth);
+        result
+    }
+
+    fn set_depth(&mut self, expression: bool, depth: usize) {
+        if expression {
+            self.expression_depth = depth;
+        } else {
+            self.depth = depth;
+        }
+    }
+
     #[allow(dead_code)]
     fn prev_span(&self) -> Span {
         let tok = if self.pos > 0 { &self.tokens[self.pos - 1] } else { &self.tokens[0] };
@@ -73,8 +166,13 @@ impl Parser {
     // ── Statements ──────────────────────────────────────
 
     fn statement(&mut self) -> GroveResult<Stmt> {
+        self.nested(false, Self::nested_statement)
+    }
+
+    fn nested_statement(&mut self) -> GroveResult<Stmt> {
         match self.peek() {
             TokenKind::Local | TokenKind::Let => self.local_decl(),
+            TokenKind::Const => self.const_decl(),
             TokenKind::If => self.if_stmt(),
             TokenKind::While => self.while_stmt(),
             TokenKind::For => self.for_stmt(),
@@ -84,6 +182,7 @@ impl Parser {
             TokenKind::Return => self.return_stmt(),
             TokenKind::Break => { let s = self.span(); self.advance(); Ok(Stmt::Break { span: s }) }
             TokenKind::Continue => { let s = self.span(); self.advance(); Ok(Stmt::Continue { span: s }) }
+            TokenKind::Pass => { let s = self.span(); self.advance(); Ok(Stmt::Pass { span: s }) }
             _ => self.expr_or_assign_stmt(),
         }
     }
@@ -101,11 +200,20 @@ impl Parser {
         Ok(Stmt::LocalDecl { name, init, span: s })
     }
 
+    fn const_decl(&mut self) -> GroveResult<Stmt> {
+        let s = self.span();
+        self.advance(); // consume 'const'
+        let name = self.expect_identifier()?;
+        self.expect(&TokenKind::Assign)?;
+        let init = self.expression(0)?;
+        Ok(Stmt::ConstDecl { name, init, span: s })
+    }
+
     fn if_stmt(&mut self) -> GroveResult<Stmt> {
         let s = self.span();
         self.advance(); // consume 'if'
-        let condition = self.expression(0)?;
-        self.expect(&TokenKind::Then)?;
+        let condition = self.condition()?;
+        self.expect_keyword(&TokenKind::Then, "then", "after if condition")?;
 
         let then_body = self.block_until(&[
             TokenKind::ElseIf,
@@ -114,10 +222,12 @@ impl Parser {
         ])?;
 
         let mut elseif_clauses = Vec::new();
-        while matches!(self.peek(), TokenKind::ElseIf) {
-            self.advance();
-            let cond = self.expression(0)?;
-            self.expect(&TokenKind::Then)?;
+        while matches!(self.peek(), TokenKind::ElseIf) || self.at_else_if() {
+            if self.advance().kind == TokenKind::Else {
+                self.advance(); // consume the 'if' of 'else if'
+            }
+            let cond = self.condition()?;
+            self.expect_keyword(&TokenKind::Then, "then", "after elseif condition")?;
             let body = self.block_until(&[
                 TokenKind::ElseIf,
                 TokenKind::Else,
@@ -133,17 +243,17 @@ impl Parser {
             None
         };
 
-        self.expect(&TokenKind::End)?;
+        self.expect_end("if", &s)?;
         Ok(Stmt::If { condition, then_body, elseif_clauses, else_body, span: s })
     }
 
     fn while_stmt(&mut self) -> GroveResult<Stmt> {
         let s = self.span();
         self.advance(); // consume 'while'
-        let condition = self.expression(0)?;
-        self.expect(&TokenKind::Do)?;
+        let condition = self.condition()?;
+        self.expect_keyword(&TokenKind::Do, "do", "after while condition")?;
         let body = self.block_until(&[TokenKind::End])?;
-        self.expect(&TokenKind::End)?;
+        self.expect_end("while", &s)?;
         Ok(Stmt::While { condition, body, span: s })
     }
 
@@ -164,9 +274,9 @@ impl Parser {
             } else {
                 None
             };
-            self.expect(&TokenKind::Do)?;
+            self.expect_keyword(&TokenKind::Do, "do", "after for range")?;
             let body = self.block_until(&[TokenKind::End])?;
-            self.expect(&TokenKind::End)?;
+            self.expect_end("for", &s)?;
             Ok(Stmt::NumericFor { var: first_var, start, limit, step, body, span: s })
         } else {
             // Generic for: for k, v in expr do ... end
@@ -177,9 +287,9 @@ impl Parser {
             }
             self.expect(&TokenKind::In)?;
             let iter = self.expression(0)?;
-            self.expect(&TokenKind::Do)?;
+            self.expect_keyword(&TokenKind::Do, "do", "after for iterator")?;
             let body = self.block_until(&[TokenKind::End])?;
-            self.expect(&TokenKind::End)?;
+            self.expect_end("for", &s)?;
             Ok(Stmt::GenericFor { vars, iter, body, span: s })
         }
     }
@@ -188,8 +298,8 @@ impl Parser {
         let s = self.span();
         self.advance(); // consume 'repeat'
         let body = self.block_until(&[TokenKind::Until])?;
-        self.expect(&TokenKind::Until)?;
-        let condition = self.expression(0)?;
+        self.expect_keyword(&TokenKind::Until, "until", &format!("to close 'repeat' on line {}", s.line))?;
+        let condition = self.condition()?;
         Ok(Stmt::RepeatUntil { body, condition, span: s })
     }
 
@@ -201,25 +311,34 @@ impl Parser {
         let params = self.param_list()?;
         self.expect(&TokenKind::RightParen)?;
         let body = self.block_until(&[TokenKind::End])?;
-        self.expect(&TokenKind::End)?;
+        self.expect_end("blueprint", &s)?;
         Ok(Stmt::Blueprint { name, params, body, span: s })
     }
 
     fn build_stmt(&mut self) -> GroveResult<Stmt> {
         let s = self.span();
+        let (name, args) = self.build_call()?;
+        Ok(Stmt::Build { name, args, span: s })
+    }
+
+    /// `build name(args)`, as a statement or an expression.
+    fn build_call(&mut self) -> GroveResult<(String, Vec<Expr>)> {
         self.advance(); // consume 'build'
         let name = self.expect_identifier()?;
         self.expect(&TokenKind::LeftParen)?;
         let args = self.arg_list()?;
         self.expect(&TokenKind::RightParen)?;
-        Ok(Stmt::Build { name, args, span: s })
+        Ok((name, args))
     }
 
     fn return_stmt(&mut self) -> GroveResult<Stmt> {
         let s = self.span();
         self.advance(); // consume 'return'
-        // Return has optional value — if next token could start an expression, parse it
-        let value = if self.is_at_end() || self.is_block_terminator() {
+        // The value is optional. Newlines are otherwise insignificant, so to
+        // keep `return` followed by a statement on the next line from reading
+        // that statement as the value, the value must start on the same line.
+        let same_line = self.current_token().line == s.line;
+        let value = if !same_line || self.is_at_end() || self.is_block_terminator() {
             None
         } else {
             Some(self.expression(0)?)
@@ -242,17 +361,39 @@ impl Parser {
 
     fn block_until(&mut self, terminators: &[TokenKind]) -> GroveResult<Vec<Stmt>> {
         let mut stmts = Vec::new();
+        // Running out of input is left to the caller, which knows which
+        // keyword is missing.
         while !self.is_at_end() && !terminators.iter().any(|t| self.check(t)) {
             stmts.push(self.statement()?);
         }
-        if self.is_at_end() && !terminators.iter().any(|t| self.check(t)) {
+        Ok(stmts)
+    }
+
+    /// Whether the next tokens are `else if` on one line, which is read as
+    /// `elseif` so the chain needs a single `end`. An `if` on the line after
+    /// `else` still starts a nested statement.
+    fn at_else_if(&self) -> bool {
+        let else_tok = self.current_token();
+        else_tok.kind == TokenKind::Else
+            && self
+                .tokens
+                .get(self.pos + 1)
+                .is_some_and(|next| next.kind == TokenKind::If && next.line == else_tok.line)
+    }
+
+    /// The condition of an `if`/`elseif`/`while`/`until`, with a targeted
+    /// error for the common `if x = 5` typo.
+    fn condition(&mut self) -> GroveResult<Expr> {
+        let condition = self.expression(0)?;
+        if matches!(self.peek(), TokenKind::Assign) {
+            let tok = self.current_token();
             return Err(GroveError::syntax(
-                format!("unexpected end of input, expected one of {:?}", terminators),
-                self.current_token().line,
-                self.current_token().column,
+                "unexpected '='; did you mean '=='?",
+                tok.line,
+                tok.column,
             ));
         }
-        Ok(stmts)
+        Ok(condition)
     }
 
     fn is_block_terminator(&self) -> bool {
@@ -290,20 +431,65 @@ impl Parser {
     }
 
     fn arg_list(&mut self) -> GroveResult<Vec<Expr>> {
-        let mut args = Vec::new();
+        let mut args: Vec<Expr> = Vec::new();
         if !matches!(self.peek(), TokenKind::RightParen) {
-            args.push(self.expression(0)?);
-            while matches!(self.peek(), TokenKind::Comma) {
+            loop {
+                let arg = self.argument()?;
+                let after_keyword = matches!(args.last(), Some(Expr::KeywordArg { .. }));
+                if after_keyword && !matches!(arg, Expr::KeywordArg { .. }) {
+                    let s = arg.span();
+                    return Err(GroveError::syntax(
+                        "positional argument after keyword arguments",
+                        s.line, s.column,
+                    ));
+                }
+                args.push(arg);
+                if !matches!(self.peek(), TokenKind::Comma) {
+                    break;
+                }
                 self.advance();
-                args.push(self.expression(0)?);
             }
         }
         Ok(args)
     }
 
+    /// Whether the next tokens are `name:` starting a keyword argument. Spacing
+    /// doesn't matter: `name:` followed by `method(` is a method call
+    /// instead, and anything else after the colon is the argument's value.
+    fn at_keyword_arg(&self) -> bool {
+        let kind = |offset: usize| self.tokens.get(self.pos + offset).map(|t| &t.kind);
+        if !matches!(self.peek(), TokenKind::Identifier(_)) || kind(1) != Some(&TokenKind::Colon) {
+            return false;
+        }
+        !(matches!(kind(2), Some(TokenKind::Identifier(_))) && kind(3) == Some(&TokenKind::LeftParen))
+    }
+
+    /// One call argument: an expression, `...expr` to spread an array, or
+    /// `name: expr` to pass a blueprint parameter by name.
+    fn argument(&mut self) -> GroveResult<Expr> {
+        if self.at_keyword_arg() {
+            let s = self.span();
+            let name = self.expect_identifier()?;
+            self.advance(); // consume ':'
+            let value = self.expression(0)?;
+            return Ok(Expr::KeywordArg { name, value: Box::new(value), span: s });
+        }
+        if matches!(self.peek(), TokenKind::DotDotDot) {
+            let s = self.span();
+            self.advance();
+            let expr = self.expression(0)?;
+            return Ok(Expr::Spread { expr: Box::new(expr), span: s });
+        }
+        self.expression(0)
+    }
+
     // ── Pratt Expression Parser ─────────────────────────
 
     fn expression(&mut self, min_bp: u8) -> GroveResult<Expr> {
+        self.nested(true, |p| p.nested_expression(min_bp))
+    }
+
+    fn nested_expression(&mut self, min_bp: u8) -> GroveResult<Expr> {
         let mut left = self.prefix()?;
 
         loop {
@@ -378,6 +564,19 @@ impl Parser {
                 self.advance();
                 Ok(Expr::NumberLit { value: v, span: s })
             }
+            TokenKind::Build => {
+                let (name, args) = self.build_call()?;
+                Ok(Expr::Build { name, args, span: s })
+            }
+            TokenKind::Fn => {
+                self.advance();
+                self.expect(&TokenKind::LeftParen)?;
+                let params = self.param_list()?;
+                self.expect(&TokenKind::RightParen)?;
+                let body = self.block_until(&[TokenKind::End])?;
+                self.expect_end("fn", &s)?;
+                Ok(Expr::FunctionLit { params, body, span: s })
+            }
             TokenKind::StringLit(val) => {
                 let v = val.clone();
                 self.advance();
@@ -602,6 +801,37 @@ mod tests {
         assert_eq!(prog.statements.len(), 1);
     }
 
+    #[test]
+    fn test_empty_table_and_array_literals() {
+        let prog = parse_str("local t = {}\nlocal a = []").unwrap();
+        assert_eq!(prog.statements.len(), 2);
+        if let Stmt::LocalDecl { init: Some(expr), .. } = &prog.statements[0] {
+            assert!(matches!(expr, Expr::TableLit { fields, .. } if fields.is_empty()));
+        } else {
+            panic!("expected empty table literal");
+        }
+        if let Stmt::LocalDecl { init: Some(expr), .. } = &prog.statements[1] {
+            assert!(matches!(expr, Expr::ArrayLit { elements, .. } if elements.is_empty()));
+        } else {
+            panic!("expected empty array literal");
+        }
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let prog = parse_str("local a = [1, 2,]\nlocal t = {x = 1, y = 2,}").unwrap();
+        if let Stmt::LocalDecl { init: Some(Expr::ArrayLit { elements, .. }), .. } = &prog.statements[0] {
+            assert_eq!(elements.len(), 2);
+        } else {
+            panic!("expected array literal");
+        }
+        if let Stmt::LocalDecl { init: Some(Expr::TableLit { fields, .. }), .. } = &prog.statements[1] {
+            assert_eq!(fields.len(), 2);
+        } else {
+            panic!("expected table literal");
+        }
+    }
+
     #[test]
     fn test_elseif() {
         let prog = parse_str("if x > 10 then\n  log(1)\nelseif x > 5 then\n  log(2)\nelse\n  log(3)\nend").unwrap();
@@ -632,4 +862,225 @@ mod tests {
             panic!("expected concat");
         }
     }
+
+    #[test]
+    fn test_const_decl() {
+        let prog = parse_str("const Dir = {North = 0, East = 1}").unwrap();
+        assert!(matches!(&prog.statements[0], Stmt::ConstDecl { name, .. } if name == "Dir"));
+        assert!(parse_str("const X").is_err());
+    }
+
+    #[test]
+    fn test_parser_skips_comment_tokens() {
+        let tokens = Lexer::new("-- doc\nlocal x = 1 -- note\n").keep_comments(true).tokenize().unwrap();
+        let prog = Parser::new(tokens).parse().unwrap();
+        assert_eq!(prog.statements.len(), 1);
+    }
+
+    #[test]
+    fn test_pass_stmt() {
+        let prog = parse_str("if x then pass end").unwrap();
+        match &prog.statements[0] {
+            Stmt::If { then_body, .. } => assert!(matches!(then_body[..], [Stmt::Pass { .. }])),
+            other => panic!("expected if, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_keyword_args() {
+        let prog = parse_str("build tree(1, height: 10, kind: obj:name())").unwrap();
+        match &prog.statements[0] {
+            Stmt::Build { args, .. } => {
+                assert!(matches!(&args[0], Expr::NumberLit { .. }));
+                assert!(matches!(&args[1], Expr::KeywordArg { name, .. } if name == "height"));
+                assert!(matches!(&args[2], Expr::KeywordArg { name, value, .. }
+                    if name == "kind" && matches!(**value, Expr::MethodCall { .. })));
+            }
+            other => panic!("expected build, got {:?}", other),
+        }
+        // `name:method(` is a method call, spaced or not; any other value
+        // after the colon makes a keyword argument, spaced or not.
+        let first_arg = |src: &str| match parse_str(src).unwrap().statements.remove(0) {
+            Stmt::ExprStmt { expr: Expr::Call { mut args, .. }, .. } => args.remove(0),
+            other => panic!("expected call, got {:?}", other),
+        };
+        for src in ["f(obj:name())", "f(obj: name())"] {
+            assert!(matches!(first_arg(src), Expr::MethodCall { .. }), "{}", src);
+        }
+        for src in ["f(height: h)", "f(height:h)", "f(height:10)", "f(pos:vec.x)"] {
+            assert!(matches!(first_arg(src), Expr::KeywordArg { name, .. } if name == "height" || name == "pos"), "{}", src);
+        }
+        let err = parse_str("f(a: 1, 2)").unwrap_err();
+        assert_eq!(err.message, "positional argument after keyword arguments");
+    }
+
+    #[test]
+    fn test_stmt_spans_point_at_statement_start() {
+        let src = "\
+local a = 1
+const B = 2
+  a = 3
+  f(a)
+if a then pass end
+while false do break end
+for i = 1, 2 do continue end
+for k, v in t do end
+repeat until true
+blueprint g() return end
+build g()
+return a";
+        let prog = parse_str(src).unwrap();
+        let starts: Vec<(usize, usize)> = prog.statements.iter().map(|s| (s.span().line, s.span().column)).collect();
+        assert_eq!(starts, vec![
+            (1, 1), (2, 1), (3, 3), (4, 3), (5, 1), (6, 1), (7, 1), (8, 1), (9, 1), (10, 1), (11, 1), (12, 1),
+        ]);
+        let inner = |stmt: &Stmt| match stmt {
+            Stmt::If { then_body, .. } | Stmt::While { body: then_body, .. }
+            | Stmt::NumericFor { body: then_body, .. } | Stmt::Blueprint { body: then_body, .. } => {
+                let span = then_body[0].span();
+                (span.line, span.column)
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(inner(&prog.statements[4]), (5, 11));
+        assert_eq!(inner(&prog.statements[5]), (6, 16));
+        assert_eq!(inner(&prog.statements[6]), (7, 17));
+        assert_eq!(inner(&prog.statements[9]), (10, 15));
+    }
+
+    #[test]
+    fn test_return_value_must_start_on_same_line() {
+        let body = |src: &str| match parse_str(src).unwrap().statements.into_iter().next() {
+            Some(Stmt::Blueprint { body, .. }) => body,
+            other => panic!("expected blueprint, got {:?}", other),
+        };
+        assert!(matches!(body("blueprint f() return end")[..], [Stmt::Return { value: None, .. }]));
+        assert!(matches!(
+            body("blueprint f() return 5 end")[..],
+            [Stmt::Return { value: Some(Expr::NumberLit { .. }), .. }]
+        ));
+        assert!(matches!(
+            body("blueprint f()\n    return\n    x = 1\nend")[..],
+            [Stmt::Return { value: None, .. }, Stmt::Assign { .. }]
+        ));
+        assert!(matches!(
+            body("blueprint f()\n    if a then return else return b end\nend")[..],
+            [Stmt::If { .. }]
+        ));
+        let prog = parse_str("return\nx").unwrap();
+        assert!(matches!(prog.statements[..], [Stmt::Return { value: None, .. }, Stmt::ExprStmt { .. }]));
+    }
+
+    #[test]
+    fn test_missing_block_keywords() {
+        let cases = [
+            ("if x > 1
+    log(x)
+end", "expected 'then' after if condition", (1, 9)),
+            ("if a then
+elseif b
+    c()
+end", "expected 'then' after elseif condition", (2, 9)),
+            ("while running
+    step()
+end", "expected 'do' after while condition", (1, 14)),
+            ("for i = 1, 10
+    log(i)
+end", "expected 'do' after for range", (1, 14)),
+            ("for k, v in pairs(t)
+    log(k)
+end", "expected 'do' after for iterator", (1, 21)),
+            ("if a then
+    b()
+", "expected 'end' to close 'if' on line 1", (2, 8)),
+            ("while a do
+    b()", "expected 'end' to close 'while' on line 1", (2, 8)),
+            ("x = 1
+for i = 1, 2 do
+    b()", "expected 'end' to close 'for' on line 2", (3, 8)),
+            ("blueprint f()
+    return 1", "expected 'end' to close 'blueprint' on line 1", (2, 13)),
+            ("local f = fn()
+    return 1", "expected 'end' to close 'fn' on line 1", (2, 13)),
+            ("repeat
+    b()", "expected 'until' to close 'repeat' on line 1", (2, 8)),
+        ];
+        for (src, message, pos) in cases {
+            let err = parse_str(src).unwrap_err();
+            assert_eq!(err.message, message, "{}", src);
+            assert_eq!((err.line, err.column), pos, "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_assignment_in_condition() {
+        for src in ["if x = 5 then end", "while y = 0 do end", "repeat until z = 1", "if a then elseif b = 2 then end"] {
+            let err = parse_str(src).unwrap_err();
+            assert_eq!(err.message, "unexpected '='; did you mean '=='?", "{}", src);
+        }
+        let err = parse_str("if x = 5 then end").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 6));
+    }
+
+    #[test]
+    fn test_else_if_folds_into_elseif() {
+        let prog = parse_str("if a then x = 1 else if b then x = 2 else if c then x = 3 else x = 4 end").unwrap();
+        assert_eq!(prog.statements.len(), 1);
+        match &prog.statements[0] {
+            Stmt::If { elseif_clauses, else_body, .. } => {
+                assert_eq!(elseif_clauses.len(), 2);
+                assert!(else_body.is_some());
+            }
+            other => panic!("expected if, got {:?}", other),
+        }
+        assert!(parse_str("if a then else if b then end end").is_err());
+
+        // On separate lines, `if` is a nested statement needing its own `end`.
+        let prog = parse_str("if a then\nelse\n    if b then end\nend").unwrap();
+        match &prog.statements[0] {
+            Stmt::If { elseif_clauses, else_body: Some(body), .. } => {
+                assert!(elseif_clauses.is_empty());
+                assert!(matches!(body[..], [Stmt::If { .. }]));
+            }
+            other => panic!("expected if with else, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_expression_nesting_limit() {
+        on_main_thread_stack(expression_nesting_limit);
+    }
+
+    fn expression_nesting_limit() {
+        let deep = format!("local x = {}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        let err = parse_str(&deep).unwrap_err();
+        assert_eq!(err.message, "expression too deeply nested");
+        assert!(parse_str(&format!("local x = {}1", "-".repeat(10_000))).is_err());
+        assert!(parse_str(&format!("local x = {}{}", "[".repeat(10_000), "]".repeat(10_000))).is_err());
+
+        let nested = |n: usize| format!("local x = {}1{}", "(".repeat(n), ")".repeat(n));
+        // One level goes to the whole initializer.
+        assert!(parse_str(&nested(DEFAULT_MAX_EXPRESSION_DEPTH - 1)).is_ok());
+        assert!(parse_str(&nested(DEFAULT_MAX_EXPRESSION_DEPTH)).is_err());
+        // Long flat chains don't nest.
+        assert!(parse_str(&format!("local x = 1{}", " + 1".repeat(10_000))).is_ok());
+        // Realistic generated code: a right-associative chain, deep in blocks.
+        let chain = format!("local x = 2{}", " ^ 1".repeat(70));
+        let ifs = DEFAULT_MAX_DEPTH - 1;
+        assert!(parse_str(&format!("{}{}{}", "if true then\n".repeat(ifs), chain, "\nend".repeat(ifs))).is_ok());
+
+        let tokens = Lexer::new(&nested(20)).tokenize().unwrap();
+        assert!(Parser::new(tokens).max_expression_depth(10).parse().is_err());
+    }
+
+    #[test]
+    fn test_block_nesting_limit() {
+        let ifs = |n: usize| format!("{}pass{}", "if true then\n".repeat(n), "\nend".repeat(n));
+        let err = parse_str(&ifs(10_000)).unwrap_err();
+        assert!(err.message.ends_with("too deeply nested"), "{}", err.message);
+        let err = parse_str(&format!("{}pass{}", "repeat\n".repeat(10_000), "\nuntil true".repeat(10_000))).unwrap_err();
+        assert_eq!(err.message, "block too deeply nested");
+        assert!(parse_str(&format!("{}pass{}", "while true do\n".repeat(10_000), "\nend".repeat(10_000))).is_err());
+        assert!(parse_str(&ifs(DEFAULT_MAX_DEPTH - 1)).is_ok());
+    }
 }
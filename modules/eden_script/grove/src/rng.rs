@@ -0,0 +1,105 @@
+//! Seeded pseudo-random numbers and noise for script builtins.
+//!
+//! SplitMix64: small, fast and fully determined by the seed, so generation
+//! scripts produce the same output for the same seed on every platform.
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.state)
+    }
+
+    /// A uniform index in `0..n`. `n` must be non-zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// The SplitMix64 output function: scrambles `z` into a well-distributed hash.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Pseudo-random value in [-1, 1] at an integer lattice point.
+fn lattice(seed: u64, x: i64, y: i64, z: i64) -> f64 {
+    let h = mix(
+        seed ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9),
+    );
+    (h >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+/// Value noise at `(x, y, z)`: random values at integer points, blended with
+/// a smootherstep curve in between. Always in [-1, 1], continuous, and equal
+/// for equal coordinates and seed.
+pub fn noise3(seed: u64, x: f64, y: f64, z: f64) -> f64 {
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (tx, ty, tz) = (fade(x - x0), fade(y - y0), fade(z - z0));
+    let (ix, iy, iz) = (x0 as i64, y0 as i64, z0 as i64);
+    let at = |dx, dy, dz| lattice(seed, ix + dx, iy + dy, iz + dz);
+    let plane = |dz| {
+        lerp(
+            lerp(at(0, 0, dz), at(1, 0, dz), tx),
+            lerp(at(0, 1, dz), at(1, 1, dz), tx),
+            ty,
+        )
+    };
+    lerp(plane(0), plane(1), tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let seq_a: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let seq_b: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        let seq_c: Vec<u64> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(seq_a, seq_b);
+        assert_ne!(seq_a, seq_c);
+    }
+
+    #[test]
+    fn test_below_stays_in_range() {
+        let mut rng = Rng::new(7);
+        let mut seen = [false; 5];
+        for _ in 0..200 {
+            let i = rng.below(5);
+            assert!(i < 5);
+            seen[i] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_noise_deterministic_and_in_range() {
+        assert_eq!(noise3(1, 0.3, 4.7, -2.1), noise3(1, 0.3, 4.7, -2.1));
+        assert_ne!(noise3(1, 0.3, 4.7, -2.1), noise3(2, 0.3, 4.7, -2.1));
+        let mut rng = Rng::new(5);
+        for _ in 0..1000 {
+            let mut coord = || (rng.next_u64() % 20_000) as f64 / 100.0 - 100.0;
+            let n = noise3(3, coord(), coord(), coord());
+            assert!((-1.0..=1.0).contains(&n), "{}", n);
+        }
+        // Continuous: nearby points have nearby values.
+        assert!((noise3(3, 1.5, 2.5, 0.0) - noise3(3, 1.5001, 2.5, 0.0)).abs() < 0.01);
+    }
+}
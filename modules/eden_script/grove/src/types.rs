@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::Stmt;
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -7,10 +10,77 @@ pub enum Value {
     Bool(bool),
     Number(f64),
     String(String),
+    /// Raw bytes from the host, kept exactly as given even if not UTF-8.
+    Bytes(Vec<u8>),
     Vec3(f64, f64, f64),
-    Array(Vec<Value>),
-    Table(HashMap<String, Value>),
+    /// RGBA components, from `color(r, g, b[, a])`.
+    Color(f64, f64, f64, f64),
+    /// `frozen` is set by `freeze` to reject mutation.
+    Array { items: Vec<Value>, frozen: bool },
+    Table { fields: HashMap<String, Value>, frozen: bool },
     Object(u64),
+    Function(Function),
+}
+
+/// A callable value that can be stored in variables and tables.
+#[derive(Debug, Clone)]
+pub enum Function {
+    /// A host function, by the name it was registered under (`game.spawn`).
+    Host(String),
+    /// A script blueprint, by name.
+    Blueprint(String),
+    /// `partial(f, args...)`: `f` with leading arguments already bound.
+    Partial(Box<Function>, Vec<Value>),
+    /// An anonymous `fn(params) ... end`.
+    Closure(Rc<Closure>),
+    /// A program from `compile(source)`, run like `eval` when called.
+    Compiled(Rc<[Stmt]>),
+}
+
+/// Closures and compiled programs are equal only to themselves.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Function::Host(a), Function::Host(b)) | (Function::Blueprint(a), Function::Blueprint(b)) => a == b,
+            (Function::Partial(f, args), Function::Partial(g, other_args)) => f == g && args == other_args,
+            (Function::Closure(a), Function::Closure(b)) => a == b,
+            (Function::Compiled(a), Function::Compiled(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// An anonymous function with copies of the local variables in scope where
+/// it was created. Capturing by value means a closure made in a loop keeps
+/// that iteration's values, and that assignments to captured variables last
+/// only for the call making them.
+#[derive(Debug)]
+pub struct Closure {
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+    pub captured: Vec<(String, Value)>,
+    /// The imported module whose globals and blueprints the body sees, or
+    /// `None` for the main script.
+    pub module: Option<Rc<str>>,
+}
+
+/// Closures are equal only to themselves.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Function {
+    /// The name of the host function or blueprint that ends up being called.
+    pub fn name(&self) -> &str {
+        match self {
+            Function::Host(name) | Function::Blueprint(name) => name,
+            Function::Partial(inner, _) => inner.name(),
+            Function::Closure(_) => "fn",
+            Function::Compiled(_) => "compiled",
+        }
+    }
 }
 
 impl Value {
@@ -28,10 +98,13 @@ impl Value {
             Value::Bool(_) => "bool",
             Value::Number(_) => "number",
             Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
             Value::Vec3(..) => "vec3",
-            Value::Array(_) => "array",
-            Value::Table(_) => "table",
+            Value::Color(..) => "color",
+            Value::Array { .. } => "array",
+            Value::Table { .. } => "table",
             Value::Object(_) => "object",
+            Value::Function(_) => "function",
         }
     }
 
@@ -55,43 +128,274 @@ impl Value {
             _ => None,
         }
     }
+
+    pub fn as_vec3(&self) -> Option<(f64, f64, f64)> {
+        match self {
+            Value::Vec3(x, y, z) => Some((*x, *y, *z)),
+            _ => None,
+        }
+    }
+
+    /// An array that isn't frozen.
+    pub fn array(items: Vec<Value>) -> Self {
+        Value::Array { items, frozen: false }
+    }
+
+    /// A table that isn't frozen.
+    pub fn table(fields: HashMap<String, Value>) -> Self {
+        Value::Table { fields, frozen: false }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array { items: arr, .. } => Some(arr),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&HashMap<String, Value>> {
+        match self {
+            Value::Table { fields: map, .. } => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Whether this is an array or table that `freeze` has made read-only.
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, Value::Array { frozen: true, .. } | Value::Table { frozen: true, .. })
+    }
+
+    /// Levels of array/table nesting: 0 for scalars, 1 for `[1, 2]`, and so on.
+    pub fn nesting_depth(&self) -> usize {
+        match self {
+            Value::Array { items: arr, .. } => 1 + arr.iter().map(Value::nesting_depth).max().unwrap_or(0),
+            Value::Table { fields: map, .. } => 1 + map.values().map(Value::nesting_depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Rough heap footprint in bytes, for budgeting memory: the value itself
+    /// plus string and bytes contents, table keys and elements, recursively.
+    /// Code shared between values (blueprint and closure bodies) isn't counted.
+    pub fn approx_size(&self) -> usize {
+        std::mem::size_of::<Value>() + match self {
+            Value::String(s) => s.len(),
+            Value::Bytes(b) => b.len(),
+            Value::Array { items: arr, .. } => arr.iter().map(Value::approx_size).sum(),
+            Value::Table { fields: map, .. } => map
+                .iter()
+                .map(|(k, v)| std::mem::size_of::<String>() + k.len() + v.approx_size())
+                .sum(),
+            Value::Function(Function::Partial(_, bound)) => bound.iter().map(Value::approx_size).sum(),
+            Value::Function(Function::Closure(closure)) => {
+                closure.captured.iter().map(|(name, v)| name.len() + v.approx_size()).sum()
+            }
+            _ => 0,
+        }
+    }
+
+    /// Every leaf as a `(path, value)` pair, for flat stores and diffs. Paths
+    /// join table keys and array indices with dots (`"tree.branches.0.len"`),
+    /// table keys in sorted order; a scalar on its own has the path `""`.
+    /// Empty arrays and tables are kept as leaves so they survive the trip.
+    /// Fails on a table key that `unflatten` would read back differently:
+    /// empty, all digits, or containing a dot.
+    pub fn flatten(&self) -> Result<Vec<(String, Value)>, String> {
+        let mut out = Vec::new();
+        self.flatten_into(String::new(), &mut out)?;
+        Ok(out)
+    }
+
+    fn flatten_into(&self, path: String, out: &mut Vec<(String, Value)>) -> Result<(), String> {
+        let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+        match self {
+            Value::Array { items: arr, .. } if !arr.is_empty() => {
+                for (i, item) in arr.iter().enumerate() {
+                    item.flatten_into(join(&i.to_string()), out)?;
+                }
+            }
+            Value::Table { fields: map, .. } if !map.is_empty() => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                for key in keys {
+                    if key.is_empty() || key.contains('.') || key.bytes().all(|b| b.is_ascii_digit()) {
+                        return Err(format!("cannot flatten table key '{}'", key));
+                    }
+                    map[key].flatten_into(join(key), out)?;
+                }
+            }
+            _ => out.push((path, self.clone())),
+        }
+        Ok(())
+    }
+
+    /// Rebuild a value from `flatten`'s pairs. An all-digit path segment is
+    /// an array index, anything else a table key; frozenness doesn't
+    /// round-trip. Two paths that disagree, such as `a` and `a.b`, or `a.0`
+    /// and `a.k`, are an error rather than one overwriting the other. Array indices must arrive in order, as `flatten` emits
+    /// them: an index past the end of its array is an error, so untrusted
+    /// paths can't force a huge allocation.
+    pub fn unflatten(entries: impl IntoIterator<Item = (String, Value)>) -> Result<Value, String> {
+        let mut root = Value::Nil;
+        for (path, value) in entries {
+            let segments: Vec<&str> = if path.is_empty() { Vec::new() } else { path.split('.').collect() };
+            root.insert_path(&segments, value).map_err(|e| format!("{} in path '{}'", e, path))?;
+        }
+        Ok(root)
+    }
+
+    fn insert_path(&mut self, segments: &[&str], value: Value) -> Result<(), String> {
+        const CONFLICT: &str = "path conflicts with existing value";
+        let Some((first, rest)) = segments.split_first() else {
+            if !matches!(self, Value::Nil) {
+                return Err(CONFLICT.to_string());
+            }
+            *self = value;
+            return Ok(());
+        };
+        // `parse` alone would also take "+1"
+        let index = if first.bytes().all(|b| b.is_ascii_digit()) { first.parse::<usize>().ok() } else { None };
+        match index {
+            Some(i) => {
+                if matches!(self, Value::Nil) {
+                    *self = Value::array(Vec::new());
+                }
+                if let Value::Array { items: arr, .. } = self {
+                    if i > arr.len() {
+                        return Err(format!("index {} skips past array end {}", i, arr.len()));
+                    }
+                    if i == arr.len() {
+                        arr.push(Value::Nil);
+                    }
+                    return arr[i].insert_path(rest, value);
+                }
+            }
+            None => {
+                if matches!(self, Value::Nil) {
+                    *self = Value::table(HashMap::new());
+                }
+                if let Value::Table { fields: map, .. } = self {
+                    return map.entry(first.to_string()).or_insert(Value::Nil).insert_path(rest, value);
+                }
+            }
+        }
+        Err(CONFLICT.to_string())
+    }
 }
 
-impl fmt::Display for Value {
+/// Whether `n` is a finite whole number in `i64` range, e.g. `3.0`.
+pub fn is_integer(n: f64) -> bool {
+    n == (n as i64) as f64 && n.is_finite()
+}
+
+/// Nesting depth beyond which `Display` prints `...` instead of recursing.
+pub const DEFAULT_DISPLAY_DEPTH: usize = 32;
+
+/// Host-provided display name for an object handle, e.g. `Tree` for `<Tree:42>`.
+pub type ObjectNamer = dyn Fn(u64) -> Option<String>;
+
+/// `Display` adapter with a custom nesting limit and object namer, see
+/// `Value::display_depth` and `Value::display_named`.
+pub struct DisplayDepth<'a> {
+    value: &'a Value,
+    max_depth: usize,
+    namer: Option<&'a ObjectNamer>,
+}
+
+impl fmt::Display for DisplayDepth<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt_depth(f, self.max_depth, self.namer)
+    }
+}
+
+impl Value {
+    /// Format with arrays and tables nested deeper than `max_depth` levels
+    /// shown as `[...]` / `{...}`.
+    pub fn display_depth(&self, max_depth: usize) -> DisplayDepth<'_> {
+        DisplayDepth { value: self, max_depth, namer: None }
+    }
+
+    /// Format with objects labelled by `namer`: `<Tree:42>` where it returns
+    /// `Some("Tree")`, the usual `<object:42>` where it returns `None`.
+    pub fn display_named<'a>(&'a self, namer: &'a ObjectNamer) -> DisplayDepth<'a> {
+        DisplayDepth { value: self, max_depth: DEFAULT_DISPLAY_DEPTH, namer: Some(namer) }
+    }
+
+    fn fmt_depth(&self, f: &mut fmt::Formatter<'_>, depth: usize, namer: Option<&ObjectNamer>) -> fmt::Result {
         match self {
             Value::Nil => write!(f, "nil"),
             Value::Bool(b) => write!(f, "{}", b),
-            Value::Number(n) => {
-                if *n == (*n as i64) as f64 && n.is_finite() {
-                    write!(f, "{}", *n as i64)
-                } else {
-                    write!(f, "{}", n)
+            Value::Number(n) => fmt_number(*n, f),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bytes(b) => write!(f, "<bytes:{}>", b.len()),
+            Value::Vec3(x, y, z) => {
+                write!(f, "vec3(")?;
+                fmt_number(*x, f)?;
+                write!(f, ", ")?;
+                fmt_number(*y, f)?;
+                write!(f, ", ")?;
+                fmt_number(*z, f)?;
+                write!(f, ")")
+            }
+            Value::Color(r, g, b, a) => {
+                write!(f, "color(")?;
+                for (i, c) in [r, g, b, a].into_iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    fmt_number(*c, f)?;
                 }
+                write!(f, ")")
             }
-            Value::String(s) => write!(f, "{}", s),
-            Value::Vec3(x, y, z) => write!(f, "vec3({}, {}, {})", x, y, z),
-            Value::Array(arr) => {
+            Value::Array { .. } if depth == 0 => write!(f, "[...]"),
+            Value::Array { items: arr, .. } => {
                 write!(f, "[")?;
                 for (i, v) in arr.iter().enumerate() {
                     if i > 0 { write!(f, ", ")?; }
-                    write!(f, "{}", v)?;
+                    v.fmt_depth(f, depth - 1, namer)?;
                 }
                 write!(f, "]")
             }
-            Value::Table(map) => {
+            Value::Table { .. } if depth == 0 => write!(f, "{{...}}"),
+            Value::Table { fields: map, .. } => {
+                // Sorted so output is stable regardless of HashMap order
+                let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
                 write!(f, "{{")?;
-                for (i, (k, v)) in map.iter().enumerate() {
+                for (i, (k, v)) in entries.into_iter().enumerate() {
                     if i > 0 { write!(f, ", ")?; }
-                    write!(f, "{} = {}", k, v)?;
+                    write!(f, "{} = ", k)?;
+                    v.fmt_depth(f, depth - 1, namer)?;
                 }
                 write!(f, "}}")
             }
-            Value::Object(handle) => write!(f, "<object:{}>", handle),
+            Value::Object(handle) => match namer.and_then(|name| name(*handle)) {
+                Some(name) => write!(f, "<{}:{}>", name, handle),
+                None => write!(f, "<object:{}>", handle),
+            },
+            Value::Function(func) => write!(f, "<function:{}>", func.name()),
         }
     }
 }
 
+/// Canonical number formatting, never in scientific notation: whole numbers
+/// as their exact integer value (`1e20` is `100000000000000000000`), with
+/// `-0` as `0`; fractions in the shortest form that reads back as the same
+/// number (`1e-7` is `0.0000001`).
+fn fmt_number(n: f64, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if n == 0.0 {
+        write!(f, "0")
+    } else if n.is_finite() && n.fract() == 0.0 {
+        write!(f, "{:.0}", n)
+    } else {
+        write!(f, "{}", n)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_depth(f, DEFAULT_DISPLAY_DEPTH, None)
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -99,9 +403,155 @@ impl PartialEq for Value {
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Number(a), Value::Number(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
             (Value::Vec3(ax, ay, az), Value::Vec3(bx, by, bz)) => ax == bx && ay == by && az == bz,
+            (Value::Color(ar, ag, ab, aa), Value::Color(br, bg, bb, ba)) => {
+                ar == br && ag == bg && ab == bb && aa == ba
+            }
             (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => a == b,
             _ => false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compound_accessors() {
+        let v = Value::Vec3(1.0, 2.0, 3.0);
+        let arr = Value::array(vec![Value::Number(1.0)]);
+        let mut map = HashMap::new();
+        map.insert("k".to_string(), Value::Bool(true));
+        let table = Value::table(map);
+
+        assert_eq!(v.as_vec3(), Some((1.0, 2.0, 3.0)));
+        assert_eq!(arr.as_array(), Some(&[Value::Number(1.0)][..]));
+        assert_eq!(table.as_table().and_then(|t| t.get("k")), Some(&Value::Bool(true)));
+
+        for other in [&v, &arr, &table, &Value::Nil] {
+            if !matches!(other, Value::Vec3(..)) { assert!(other.as_vec3().is_none()); }
+            if !matches!(other, Value::Array { .. }) { assert!(other.as_array().is_none()); }
+            if !matches!(other, Value::Table { .. }) { assert!(other.as_table().is_none()); }
+        }
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        assert_eq!(Value::Number(1.0).nesting_depth(), 0);
+        assert_eq!(Value::array(vec![]).nesting_depth(), 1);
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), Value::array(vec![Value::array(vec![])]));
+        map.insert("b".to_string(), Value::Nil);
+        assert_eq!(Value::table(map).nesting_depth(), 3);
+    }
+
+    #[test]
+    fn test_approx_size() {
+        let base = std::mem::size_of::<Value>();
+        let key = std::mem::size_of::<String>();
+        assert_eq!(Value::Number(1.0).approx_size(), base);
+        assert_eq!(Value::String("hello".into()).approx_size(), base + 5);
+        let arr = Value::array(vec![Value::Nil, Value::String("ab".into())]);
+        assert_eq!(arr.approx_size(), 3 * base + 2);
+
+        let mut map = HashMap::new();
+        map.insert("items".to_string(), arr);
+        map.insert("n".to_string(), Value::Number(0.0));
+        let table = Value::table(map);
+        assert_eq!(table.approx_size(), base + (key + 5 + 3 * base + 2) + (key + 1 + base));
+    }
+
+    #[test]
+    fn test_flatten_round_trip() {
+        let table = |fields: Vec<(&str, Value)>| {
+            Value::table(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        };
+        let s = |v: &str| Value::String(v.to_string());
+        let tree = table(vec![
+            ("name", s("oak")),
+            ("size", table(vec![("w", Value::Number(1.0)), ("h", Value::Number(2.0))])),
+            ("branches", Value::array(vec![table(vec![("len", Value::Number(3.0))]), Value::Bool(true)])),
+            ("tags", Value::array(vec![])),
+        ]);
+
+        let flat = tree.flatten().unwrap();
+        let paths: Vec<&str> = flat.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, ["branches.0.len", "branches.1", "name", "size.h", "size.w", "tags"]);
+        assert_eq!(flat[0].1, Value::Number(3.0));
+
+        let rebuilt = Value::unflatten(flat).unwrap();
+        assert_eq!(rebuilt.to_string(), tree.to_string());
+
+        assert_eq!(Value::Number(5.0).flatten().unwrap(), [(String::new(), Value::Number(5.0))]);
+        for key in ["a.b", "7", ""] {
+            assert!(table(vec![(key, Value::Nil)]).flatten().is_err(), "key {:?}", key);
+        }
+
+        let unflatten = |paths: &[&str]| Value::unflatten(paths.iter().map(|p| (p.to_string(), s("x"))));
+        assert_eq!(unflatten(&["a.0", "a.1"]).unwrap().to_string(), "{a = [x, x]}");
+        assert!(unflatten(&["a.2"]).is_err());
+        assert!(unflatten(&["a.99999999999"]).is_err());
+        assert!(unflatten(&["a.18446744073709551615"]).is_err());
+
+        // Paths that disagree about what's at a place are errors, either way round
+        for paths in [["a", "a.b"], ["a.b", "a"], ["a.0", "a.k"], ["a.k", "a.0"], ["a", "a"]] {
+            let err = unflatten(&paths).unwrap_err();
+            assert!(err.starts_with("path conflicts with existing value"), "{:?}: {}", paths, err);
+        }
+    }
+
+    #[test]
+    fn test_table_display_sorts_keys() {
+        let mut map = HashMap::new();
+        map.insert("b".to_string(), Value::Number(2.0));
+        map.insert("a".to_string(), Value::Number(1.0));
+        map.insert("c".to_string(), Value::table(HashMap::new()));
+        assert_eq!(format!("{}", Value::table(map)), "{a = 1, b = 2, c = {}}");
+    }
+
+    #[test]
+    fn test_display_depth_limit() {
+        let nested = Value::array(vec![Value::array(vec![Value::array(vec![Value::Number(1.0)])])]);
+        assert_eq!(format!("{}", nested), "[[[1]]]");
+        assert_eq!(format!("{}", nested.display_depth(2)), "[[[...]]]");
+        assert_eq!(format!("{}", nested.display_depth(0)), "[...]");
+    }
+
+    #[test]
+    fn test_display_deeply_nested_is_truncated() {
+        let mut value = Value::Number(0.0);
+        for i in 0..1000 {
+            value = if i % 2 == 0 {
+                Value::array(vec![value])
+            } else {
+                let mut map = HashMap::new();
+                map.insert("k".to_string(), value);
+                Value::table(map)
+            };
+        }
+        let text = format!("{}", value);
+        assert!(text.contains("..."));
+        assert!(text.len() < 1000);
+    }
+
+    #[test]
+    fn test_number_display_canonical_forms() {
+        let show = |n: f64| Value::Number(n).to_string();
+        assert_eq!(show(-0.0), "0");
+        assert_eq!(show(0.0), "0");
+        assert_eq!(show(3.0), "3");
+        assert_eq!(show(-42.0), "-42");
+        assert_eq!(show(1e20), "100000000000000000000");
+        assert_eq!(show(-1e20), "-100000000000000000000");
+        // Exact values on both sides of the i64 range, not rounded digits.
+        assert_eq!(show(2f64.powi(62)), "4611686018427387904");
+        assert_eq!(show(2f64.powi(63)), "9223372036854775808");
+        assert_eq!(show(1e-7), "0.0000001");
+        assert_eq!(show(0.1), "0.1");
+        assert_eq!(show(f64::INFINITY), "inf");
+        assert_eq!(Value::Vec3(-0.0, 1e20, 0.5).to_string(), "vec3(0, 100000000000000000000, 0.5)");
+    }
+}